@@ -0,0 +1,98 @@
+//! Addresses.
+//!
+//! An address is the base58check encoding of a version byte, the public key
+//! hash and a 4-byte checksum. Every network has its own version byte, see
+//! [`crate::ChainParams`], so an address of one network is rejected on another.
+
+use crate::common::{base58_decode, base58_encode};
+use crate::error::Error::InvalidAddress;
+use crate::wallet::Wallet;
+use crate::{Network, Result};
+use std::fmt;
+
+/// The len of a public key hash, `RIPEMD160(SHA256(public_key))`.
+const PUB_KEY_HASH_LEN: usize = 20;
+
+/// The len of the checksum of an address.
+pub(crate) const ADDRESS_CHECKSUM_LEN: usize = 4;
+
+/// A checked address on a network.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Address {
+    network: Network,
+    pub_key_hash: Vec<u8>,
+}
+
+impl Address {
+    /// New an address of the public key hash on the network.
+    pub fn new(network: Network, pub_key_hash: Vec<u8>) -> Self {
+        Address {
+            network,
+            pub_key_hash,
+        }
+    }
+
+    /// Parse an address, verifying its checksum and that it belongs to the network.
+    pub fn parse(address: &str, network: Network) -> Result<Self> {
+        let invalid = |reason: &str| InvalidAddress(format!("{}: {}", address, reason));
+        let payload = base58_decode(address).map_err(|_| invalid("not base58"))?;
+        if payload.len() != 1 + PUB_KEY_HASH_LEN + ADDRESS_CHECKSUM_LEN {
+            return Err(invalid("wrong length"));
+        }
+        let (versioned, checksum) = payload.split_at(1 + PUB_KEY_HASH_LEN);
+        if Wallet::checksum(versioned) != checksum {
+            return Err(invalid("wrong checksum"));
+        }
+        if versioned[0] != network.address_version() {
+            return Err(invalid(&format!("not an address of {:?}", network)));
+        }
+        Ok(Address::new(network, versioned[1..].to_vec()))
+    }
+
+    /// Return the network of the address.
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Return the public key hash.
+    pub fn pub_key_hash(&self) -> &[u8] {
+        &self.pub_key_hash
+    }
+}
+
+/// Encode `version + pub_key_hash + checksum` with base58.
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut payload = vec![self.network.address_version()];
+        payload.extend(&self.pub_key_hash);
+        let checksum = Wallet::checksum(&payload);
+        payload.extend(checksum);
+        write!(f, "{}", base58_encode(&payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let wallet = Wallet::new();
+        let address = Address::parse(&wallet.address(), Network::Mainnet).unwrap();
+        assert_eq!(address.pub_key_hash(), wallet.pub_key_hash());
+        assert_eq!(address.to_string(), wallet.address());
+
+        let testnet = Address::new(Network::Testnet, wallet.pub_key_hash());
+        assert!(Address::parse(&testnet.to_string(), Network::Testnet).is_ok());
+        assert!(Address::parse(&testnet.to_string(), Network::Mainnet).is_err());
+
+        // A typo breaks the checksum.
+        let mut typo = wallet.address().into_bytes();
+        let last = typo.len() - 1;
+        typo[last] = if typo[last] == b'2' { b'3' } else { b'2' };
+        let typo = String::from_utf8(typo).unwrap();
+        assert!(Address::parse(&typo, Network::Mainnet).is_err());
+        assert!(Address::parse("who", Network::Mainnet).is_err());
+        assert!(Address::parse("0OIl", Network::Mainnet).is_err());
+    }
+}
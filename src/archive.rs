@@ -0,0 +1,214 @@
+//! Chain archives, the file format of `export-chain` and `import-chain`.
+//!
+//! An archive is the magic `RCHN`, a format version byte, the network name
+//! prefixed with its length as one byte, and then every block in height order
+//! as its stored serialization prefixed with its length as a big-endian `u32`.
+//! A zero length ends the blocks, and the SHA-256 of everything before it is the
+//! trailer.
+
+use crate::error::Error::InvalidArchive;
+use crate::{Block, Network, Result};
+use sha2::{Digest, Sha256};
+use std::io::{ErrorKind, Read, Write};
+
+const MAGIC: &[u8; 4] = b"RCHN";
+
+const VERSION: u8 = 1;
+
+/// Blocks are far smaller, a larger length means a corrupt archive.
+const MAX_BLOCK_LEN: u32 = 64 << 20;
+
+/// Writes blocks to an archive.
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Write the header of an archive of the network.
+    pub fn new(writer: W, network: Network) -> Result<Self> {
+        let mut archive = ArchiveWriter {
+            writer,
+            hasher: Sha256::new(),
+        };
+        let network = network.to_string();
+        archive.write(MAGIC)?;
+        archive.write(&[VERSION, network.len() as u8])?;
+        archive.write(network.as_bytes())?;
+        Ok(archive)
+    }
+
+    /// Write the next block, blocks must be written in height order.
+    pub fn write_block(&mut self, block: &Block) -> Result<()> {
+        let block = block.serialize()?;
+        self.write(&(block.len() as u32).to_be_bytes())?;
+        self.write(block.as_bytes())
+    }
+
+    /// Write the end of the blocks and the checksum, and return the writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.write(&0u32.to_be_bytes())?;
+        let checksum = self.hasher.finalize_reset();
+        self.writer.write_all(&checksum)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.hasher.update(data);
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+}
+
+/// Reads the blocks of an archive in height order.
+///
+/// The checksum is checked once the last block is read, and a mismatch is
+/// returned as the last item.
+pub struct ArchiveReader<R: Read> {
+    reader: R,
+    hasher: Sha256,
+    network: Network,
+    done: bool,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    /// Read the header of an archive.
+    pub fn new(reader: R) -> Result<Self> {
+        let mut archive = ArchiveReader {
+            reader,
+            hasher: Sha256::new(),
+            network: Network::Mainnet,
+            done: false,
+        };
+        let mut header = [0; 6];
+        archive.read(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(InvalidArchive("not a chain archive".to_owned()));
+        }
+        if header[4] != VERSION {
+            return Err(InvalidArchive(format!("unknown version {}", header[4])));
+        }
+        let mut network = vec![0; header[5] as usize];
+        archive.read(&mut network)?;
+        archive.network = String::from_utf8(network)?.parse()?;
+        Ok(archive)
+    }
+
+    /// Return the network of the chain in the archive.
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Read the whole archive and return the number of blocks, which checks the checksum.
+    pub fn verify(self) -> Result<u64> {
+        let mut count = 0;
+        for block in self {
+            block?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn next_block(&mut self) -> Result<Option<Block>> {
+        let mut len = [0; 4];
+        self.read(&mut len)?;
+        let len = u32::from_be_bytes(len);
+        if len == 0 {
+            let expected = self.hasher.finalize_reset();
+            let mut checksum = [0; 32];
+            self.reader.read_exact(&mut checksum).map_err(truncated)?;
+            if checksum[..] != expected[..] {
+                return Err(InvalidArchive("checksum mismatch".to_owned()));
+            }
+            return Ok(None);
+        }
+        if len > MAX_BLOCK_LEN {
+            return Err(InvalidArchive(format!("block of {} bytes", len)));
+        }
+        let mut block = vec![0; len as usize];
+        self.read(&mut block)?;
+        let block = Block::deserialize(&String::from_utf8(block)?)
+            .map_err(|e| InvalidArchive(format!("unreadable block: {}", e)))?;
+        Ok(Some(block))
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.reader.read_exact(buf).map_err(truncated)?;
+        self.hasher.update(&buf);
+        Ok(())
+    }
+}
+
+fn truncated(e: std::io::Error) -> crate::Error {
+    match e.kind() {
+        ErrorKind::UnexpectedEof => InvalidArchive("truncated".to_owned()),
+        _ => e.into(),
+    }
+}
+
+impl<R: Read> Iterator for ArchiveReader<R> {
+    type Item = Result<Block>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let block = self.next_block().transpose();
+        if !matches!(block, Some(Ok(_))) {
+            self.done = true;
+        }
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::MemoryEngine;
+    use crate::wallet::Wallets;
+    use crate::{Address, ChainParams};
+    use std::sync::Arc;
+
+    fn archive() -> (Vec<Block>, Vec<u8>) {
+        let params = ChainParams::regtest();
+        let address = Address::new(Network::Regtest, vec![0; 20]);
+        let wallets = Wallets::with_engine(Arc::new(MemoryEngine::new())).unwrap();
+        let genesis = params.genesis_block(&address, &wallets).unwrap();
+        let consensus = params.consensus().unwrap();
+        let block = Block::new(vec![], genesis.hash.clone(), 1, &*consensus, &wallets).unwrap();
+        let blocks = vec![genesis, block];
+
+        let mut writer = ArchiveWriter::new(vec![], Network::Regtest).unwrap();
+        for block in &blocks {
+            writer.write_block(block).unwrap();
+        }
+        (blocks, writer.finish().unwrap())
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (blocks, data) = archive();
+        let reader = ArchiveReader::new(&data[..]).unwrap();
+        assert_eq!(reader.network(), Network::Regtest);
+        let read = reader.collect::<Result<Vec<Block>>>().unwrap();
+        assert_eq!(read, blocks);
+        assert_eq!(ArchiveReader::new(&data[..]).unwrap().verify().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_corrupt_archive() {
+        let (_, data) = archive();
+        let truncated = ArchiveReader::new(&data[..data.len() - 1])
+            .unwrap()
+            .verify();
+        assert!(matches!(truncated, Err(InvalidArchive(e)) if e == "truncated"));
+
+        let mut flipped = data.clone();
+        let last = flipped.len() - 40;
+        flipped[last] ^= 1;
+        assert!(ArchiveReader::new(&flipped[..]).unwrap().verify().is_err());
+
+        assert!(ArchiveReader::new(&b"RCHAIN"[..]).is_err());
+    }
+}
@@ -1,87 +1,958 @@
-use clap::{arg, Arg, Command};
-use rchain::wallet::{Wallet, Wallets};
-use rchain::{Blockchain, ProofOfWork, Transaction};
+use clap::{arg, Arg, ArgAction, Command};
+use log::{info, warn};
+use rchain::archive::ArchiveReader;
+use rchain::config::{chain_path, network_dir, wallets_path, Config, CONFIG_FILE};
+use rchain::engine::SledEngine;
+use rchain::events::EventServer;
+use rchain::hd::{generate_mnemonic, mnemonic_to_seed};
+use rchain::migration::copy_engine;
+use rchain::rest::RestServer;
+use rchain::snapshot::UtxoSnapshot;
+use rchain::stratum::{StratumMiner, StratumServer};
+use rchain::view::{
+    BalanceView, BlockView, ChainInfoView, HistoryView, TransactionView, UtxoSetInfoView, UtxoView,
+    WalletView,
+};
+use rchain::wallet::{KeyFormat, Wallet, Wallets, WatchOnly};
+use rchain::Error::{
+    BlockNotFound, BlockPruned, ChainNotFound, InvalidKey, StringError, TxNotFound, WalletNotFound,
+};
+use rchain::{
+    Address, Approval, AuthorityChange, Block, Blockchain, ChainParams, ConsensusParams,
+    Governance, Network, PowHash, Result, Transaction,
+};
+use serde::Serialize;
+use serde_json::json;
 use std::env::current_dir;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
-fn main() {
-    env_logger::init();
-    let path = current_dir().unwrap();
+/// The environment variable that holds the wallet passphrase.
+const PASSPHRASE_ENV: &str = "RCHAIN_PASSPHRASE";
+
+/// The environment variable that holds the new passphrase for `change-passphrase`.
+const NEW_PASSPHRASE_ENV: &str = "RCHAIN_NEW_PASSPHRASE";
+
+/// How long a command keeps the wallets unlocked, unless `--unlock-timeout` says otherwise.
+const UNLOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The format of the output of a command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Output {
+    /// Human-readable text.
+    Text,
+
+    /// A single JSON document, see the README for the schema.
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => Err(format!("unknown output {}", s)),
+        }
+    }
+}
+
+impl Output {
+    /// Print the value as JSON, or as text with the closure.
+    fn print<T: Serialize>(self, value: &T, text: impl FnOnce(&T)) -> Result<()> {
+        match self {
+            Output::Json => println!("{}", serde_json::to_string_pretty(value)?),
+            Output::Text => text(value),
+        }
+        Ok(())
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<()> {
     let matches = cli().get_matches();
+    // Flags and environment variables come first, then the config file.
+    let datadir = match matches.get_one::<PathBuf>("datadir") {
+        Some(datadir) => datadir.clone(),
+        None => current_dir()?,
+    };
+    let config = match matches.get_one::<PathBuf>("config") {
+        Some(config) => Config::load(config)?,
+        None => Config::load(datadir.join(CONFIG_FILE))?,
+    };
+    init_logger(
+        matches
+            .get_one::<String>("log-level")
+            .or(config.log_level.as_ref()),
+    );
+    let network = matches
+        .get_one::<Network>("network")
+        .copied()
+        .or(config.network)
+        .unwrap_or_default();
+    let output = *matches.get_one::<Output>("output").expect("output");
+    let mut params = network.params();
+    // A chain with a hash function or an engine of its own mints its own genesis block.
+    if let Some(pow_hash) = config.pow_hash {
+        params.pow_hash = pow_hash;
+        params.genesis = None;
+    }
+    if let Some(consensus) = &config.consensus {
+        params.consensus = consensus.clone();
+        params.genesis = None;
+    }
+    params
+        .checkpoints
+        .extend(config.checkpoints.iter().cloned());
+    if let Some(assume_valid) = &config.assume_valid {
+        params.assume_valid = Some(assume_valid.clone());
+    }
+    let path = network_dir(&datadir, network);
+    let prune = matches.get_one::<u64>("prune").copied().or(config.prune);
+    let unlock_timeout = matches
+        .get_one::<u64>("unlock-timeout")
+        .copied()
+        .or(config.unlock_timeout)
+        .map(Duration::from_secs);
     match matches.subcommand() {
         Some(("create-blockchain", sub_matches)) => {
-            let address = sub_matches.get_one::<String>("ADDRESS").expect("address");
-            let chain = Blockchain::new(&path, address).unwrap();
-            print_chain(&chain);
+            let address = sub_matches.get_one::<String>("ADDRESS");
+            if address.is_none() && params.genesis.is_none() {
+                return Err(StringError(
+                    "an address is required, the chain has no fixed genesis block".to_owned(),
+                ));
+            }
+            let created = !chain_path(&path).exists();
+            let mut chain =
+                Blockchain::with_params(&path, address.map_or("", |a| a.as_str()), params.clone())?;
+            chain.set_prune_depth(prune)?;
+            // The fixed genesis block rewards nobody, so the first block rewards the address.
+            if let (true, Some(address), Some(_)) = (created, address, &params.genesis) {
+                chain.seal_block(&Address::parse(address, network)?)?;
+            }
+            print_chain(&chain, output)?;
         }
         Some(("ls", _)) => {
-            let address;
-            {
-                let wallets = Wallets::with_path(&path);
-                let wallet = Wallet::new();
-                address = wallet.address();
-                wallets.set(&wallet).unwrap();
-            }
-            let chain = Blockchain::new(&path, &address).unwrap();
-            print_chain(&chain);
+            let chain = open_chain(&path, &params, prune)?;
+            print_chain(&chain, output)?;
         }
-        Some(("balance", sub_matches)) => {
-            let user = sub_matches.get_one::<String>("ADDRESS").expect("address");
-
-            let chain = Blockchain::new(&path, user).unwrap();
-
-            let mut balance = 0;
-            let pub_key_hash = Transaction::pub_key_hash_from_address(user);
-            let utxo = chain.find_utxo(&pub_key_hash);
-            for (_, v) in utxo {
-                balance += v.iter().fold(0, |acc, (_, x)| acc + x.value);
+        Some(("balance", sub_matches)) => match sub_matches.get_one::<String>("ADDRESS") {
+            Some(user) => {
+                let chain = open_chain(&path, &params, prune)?;
+                let watch_only = chain.wallets().is_watch_only(user)?;
+                output.print(&balance(&chain, user, watch_only)?, |v| {
+                    println!("balance: {}", v.balance)
+                })?;
             }
-            println!("balance: {}", balance);
-        }
+            None => {
+                let chain = open_chain(&path, &params, prune)?;
+                let balances: Vec<BalanceView> = wallet_addresses(chain.wallets())?
+                    .iter()
+                    .map(|(address, watch_only)| balance(&chain, address, *watch_only))
+                    .collect::<Result<_>>()?;
+                output.print(&balances, |v| {
+                    for b in v {
+                        println!(
+                            "address: {}{}, balance: {}",
+                            b.address,
+                            watch_only_label(b.watch_only),
+                            b.balance
+                        );
+                    }
+                })?;
+            }
+        },
         Some(("send", sub_match)) => {
             let from = sub_match.get_one::<String>("FROM").expect("from");
             let to = sub_match.get_one::<String>("TO").expect("to");
             let amount: i64 = *sub_match.get_one::<i64>("AMOUNT").expect("amount");
 
-            let mut chain = Blockchain::new(&path, from).unwrap();
+            let mut chain = open_chain(&path, &params, prune)?;
+            unlock_wallets(chain.wallets(), unlock_timeout)?;
 
-            let tx = Transaction::new(from, to, amount, &chain).unwrap();
-            chain.mine_block(vec![tx]).unwrap();
+            let tx = Transaction::new(from, to, amount, &chain)?;
+            chain.mine_block(vec![tx])?;
 
-            print_chain(&chain);
+            print_chain(&chain, output)?;
+        }
+        Some(("create-wallet", sub_matches)) => {
+            let wallets = Wallets::with_datadir(&path)?.on_network(network);
+            unlock_wallets(&wallets, unlock_timeout)?;
+            let mut mnemonic = None;
+            if sub_matches.get_flag("mnemonic") {
+                let words = generate_mnemonic()?;
+                wallets.set_seed(&mnemonic_to_seed(&words, "")?)?;
+                mnemonic = Some(words);
+            }
+            let derived = wallets.has_seed()?;
+            let wallet = if derived {
+                wallets.derive_next(sub_matches.get_flag("change"))?
+            } else {
+                let wallet = Wallet::new();
+                wallets.set(&wallet)?;
+                wallet
+            };
+            let value = json!({
+                "address": wallet.address_on(network),
+                "mnemonic": mnemonic,
+            });
+            output.print(&value, |_| {
+                if let Some(mnemonic) = &mnemonic {
+                    println!("mnemonic: {}", mnemonic);
+                }
+                if !derived {
+                    println!("wallet: {:?}", wallet);
+                }
+                println!("address: {}", wallet.address_on(network));
+            })?;
         }
-        Some(("create-wallet", _)) => {
-            let wallets = Wallets::with_path(&path);
-            let wallet = Wallet::new();
-            let address = wallet.address();
-            println!("wallet: {:?}", wallet);
-            println!("address: {}", address);
-            wallets.set(&wallet).unwrap();
+        Some(("restore-wallet", sub_matches)) => {
+            let mnemonic = sub_matches.get_one::<String>("MNEMONIC").expect("mnemonic");
+            let count = *sub_matches.get_one::<u32>("count").expect("count");
+            let wallets = Wallets::with_datadir(&path)?.on_network(network);
+            unlock_wallets(&wallets, unlock_timeout)?;
+            wallets.set_seed(&mnemonic_to_seed(mnemonic, "")?)?;
+            let mut addresses = vec![];
+            for _ in 0..count {
+                let wallet = wallets.derive_next(false)?;
+                addresses.push(wallet.address_on(network));
+            }
+            output.print(&json!({ "addresses": addresses }), |_| {
+                for address in &addresses {
+                    println!("address: {}", address);
+                }
+            })?;
         }
         Some(("wallets", _)) => {
-            let wallets = Wallets::with_path(&path);
-            let v = wallets.list();
-            for (address, w) in &v {
-                println!("addr: {}, wallet: {:?}", address, w);
+            let wallets = Wallets::with_datadir(&path)?.on_network(network);
+            let list = wallets.list()?;
+            let watch_only = wallets.list_watch_only()?;
+            let mut views = vec![];
+            for (address, w) in &list {
+                views.push(WalletView {
+                    address: address.clone(),
+                    public_key: Some(w.public_key()),
+                    watch_only: false,
+                    encrypted: w.is_encrypted(),
+                });
+            }
+            for (address, w) in &watch_only {
+                views.push(WalletView {
+                    address: address.clone(),
+                    public_key: w.public_key.clone(),
+                    watch_only: true,
+                    encrypted: false,
+                });
+            }
+            output.print(&views, |_| {
+                for (address, w) in &list {
+                    println!("addr: {}, wallet: {:?}", address, w);
+                }
+                for (address, w) in &watch_only {
+                    println!("addr: {} (watch-only), {:?}", address, w);
+                }
+            })?;
+        }
+        Some(("watch", sub_matches)) => {
+            let watch_only = match sub_matches.get_one::<String>("public-key") {
+                Some(public_key) => WatchOnly::from_public_key(public_key)?,
+                None => {
+                    let address = sub_matches.get_one::<String>("ADDRESS");
+                    let address = address.ok_or(StringError(
+                        "an address or --public-key is required".to_owned(),
+                    ))?;
+                    WatchOnly::from_address(&Address::parse(address, network)?)
+                }
+            };
+            let wallets = Wallets::with_datadir(&path)?.on_network(network);
+            let address = watch_only.address_on(network);
+            let removed = sub_matches.get_flag("remove");
+            if removed {
+                wallets.unwatch(&address)?;
+            } else {
+                wallets.watch(&watch_only)?;
+            }
+            output.print(&json!({ "address": address, "watching": !removed }), |_| {
+                println!("address: {}", address)
+            })?;
+        }
+        Some(("export-key", sub_matches)) => {
+            let address = sub_matches.get_one::<String>("ADDRESS").expect("address");
+            let format = *sub_matches.get_one::<KeyFormat>("format").expect("format");
+            let wallets = Wallets::with_datadir(&path)?.on_network(network);
+            unlock_wallets(&wallets, unlock_timeout)?;
+            let wallet = wallets
+                .get(address)?
+                .ok_or(WalletNotFound(address.clone()))?;
+            let key = wallet.export_key(format)?;
+            match sub_matches.get_one::<String>("file") {
+                Some(file) => {
+                    fs::write(file, key)?;
+                    output.print(&json!({ "address": address, "file": file }), |_| {})?;
+                }
+                None if format == KeyFormat::Der => {
+                    return Err(InvalidKey("DER is binary, write it with --file".to_owned()))
+                }
+                None => {
+                    let key = String::from_utf8(key)?.trim_end().to_owned();
+                    output.print(&json!({ "address": address, "key": key }), |_| {
+                        println!("{}", key)
+                    })?;
+                }
             }
         }
-        _ => panic!("no implemented"),
+        Some(("import-key", sub_matches)) => {
+            let format = *sub_matches.get_one::<KeyFormat>("format").expect("format");
+            let key = match sub_matches.get_one::<String>("file") {
+                Some(file) => fs::read(file)?,
+                None => sub_matches
+                    .get_one::<String>("KEY")
+                    .ok_or(InvalidKey("a key or --file is required".to_owned()))?
+                    .clone()
+                    .into_bytes(),
+            };
+            let wallet = Wallet::import_key(&key, format)?;
+            let address = match sub_matches.get_one::<String>("address") {
+                Some(address) => address.clone(),
+                None => wallet.address_on(network),
+            };
+            let wallets = Wallets::with_datadir(&path)?.on_network(network);
+            unlock_wallets(&wallets, unlock_timeout)?;
+            wallets.set_with_address(&address, &wallet)?;
+            output.print(&json!({ "address": address }), |_| {
+                println!("address: {}", address)
+            })?;
+        }
+        Some(("encrypt-wallet", _)) => {
+            let wallets = Wallets::with_datadir(&path)?.on_network(network);
+            let passphrase = passphrase(PASSPHRASE_ENV, "New passphrase: ")?;
+            wallets.encrypt(&passphrase)?;
+            output.print(&json!({ "encrypted": true }), |_| {
+                println!("wallets encrypted")
+            })?;
+        }
+        Some(("change-passphrase", _)) => {
+            let wallets = Wallets::with_datadir(&path)?.on_network(network);
+            let old = passphrase(PASSPHRASE_ENV, "Passphrase: ")?;
+            let new = passphrase(NEW_PASSPHRASE_ENV, "New passphrase: ")?;
+            wallets.change_passphrase(&old, &new)?;
+            output.print(&json!({ "passphrase_changed": true }), |_| {
+                println!("passphrase changed")
+            })?;
+        }
+        Some(("history", sub_matches)) => {
+            let from = *sub_matches.get_one::<usize>("from").expect("from");
+            let limit = *sub_matches.get_one::<usize>("limit").expect("limit");
+            match sub_matches.get_one::<String>("ADDRESS") {
+                Some(user) => {
+                    let chain = open_chain(&path, &params, prune)?;
+                    let watch_only = chain.wallets().is_watch_only(user)?;
+                    let view = history(&chain, user, watch_only, from, limit)?;
+                    output.print(&view, print_history)?;
+                }
+                None => {
+                    let chain = open_chain(&path, &params, prune)?;
+                    let views: Vec<HistoryView> = wallet_addresses(chain.wallets())?
+                        .iter()
+                        .map(|(address, watch_only)| {
+                            history(&chain, address, *watch_only, from, limit)
+                        })
+                        .collect::<Result<_>>()?;
+                    output.print(&views, |v| {
+                        for view in v {
+                            println!(
+                                "address: {}{}",
+                                view.address,
+                                watch_only_label(view.watch_only)
+                            );
+                            print_history(view);
+                        }
+                    })?;
+                }
+            }
+        }
+        Some(("generate", sub_matches)) => {
+            let count = *sub_matches.get_one::<u64>("COUNT").expect("count");
+            let address = sub_matches
+                .get_one::<String>("ADDRESS")
+                .or(config.mining_address.as_ref())
+                .ok_or(StringError(
+                    "an address, RCHAIN_MINING_ADDRESS or mining_address in the config is required"
+                        .to_owned(),
+                ))?;
+            let mut chain = Blockchain::with_params(&path, address, params.clone())?;
+            chain.set_prune_depth(prune)?;
+            // Blocks that are signed, not mined, need the key of their sealer.
+            if params.consensus != ConsensusParams::ProofOfWork {
+                unlock_wallets(chain.wallets(), unlock_timeout)?;
+            }
+            let hashes = chain.generate_blocks(&Address::parse(address, network)?, count)?;
+            output.print(&json!({ "hashes": hashes }), |_| {
+                for hash in &hashes {
+                    println!("{}", hash);
+                }
+            })?;
+        }
+        Some((name @ ("add-authority" | "remove-authority"), sub_matches)) => {
+            let address = sub_matches.get_one::<String>("ADDRESS").expect("address");
+            let change = match name {
+                "add-authority" => AuthorityChange::Add(address.clone()),
+                _ => AuthorityChange::Remove(address.clone()),
+            };
+            let mut chain = open_chain(&path, &params, prune)?;
+            unlock_wallets(chain.wallets(), unlock_timeout)?;
+            let mut governance = Governance::new(change, chain.height()? + 1);
+            governance.approvals = local_approvals(&chain, &governance)?;
+            for file in sub_matches
+                .get_many::<PathBuf>("approvals")
+                .into_iter()
+                .flatten()
+            {
+                let value: serde_json::Value = serde_json::from_slice(&fs::read(file)?)?;
+                let approvals: Vec<Approval> = serde_json::from_value(value["approvals"].clone())?;
+                for approval in approvals {
+                    if !governance
+                        .approvals
+                        .iter()
+                        .any(|a| a.public_key == approval.public_key)
+                    {
+                        governance.approvals.push(approval);
+                    }
+                }
+            }
+            let tx = Transaction::new_governance_tx(governance)?;
+            chain.mine_block(vec![tx.clone()])?;
+            let authorities = chain.consensus().sealers(chain.height()? + 1)?;
+            let value = json!({ "tx_id": tx.id, "authorities": authorities });
+            output.print(&value, |_| {
+                println!("tx: {}", tx.id);
+                for authority in &authorities {
+                    println!("authority: {}", authority);
+                }
+            })?;
+        }
+        Some(("approve-authority", sub_matches)) => {
+            let address = sub_matches.get_one::<String>("ADDRESS").expect("address");
+            let change = match sub_matches.get_one::<String>("CHANGE").map(String::as_str) {
+                Some("add") => AuthorityChange::Add(address.clone()),
+                _ => AuthorityChange::Remove(address.clone()),
+            };
+            let chain = open_chain(&path, &params, prune)?;
+            unlock_wallets(chain.wallets(), unlock_timeout)?;
+            let governance = Governance::new(change, chain.height()? + 1);
+            let approvals = local_approvals(&chain, &governance)?;
+            if approvals.is_empty() {
+                return Err(StringError(format!(
+                    "no authority at height {} has its key in the wallets",
+                    governance.height
+                )));
+            }
+            let value = json!({ "height": governance.height, "approvals": approvals });
+            output.print(&value, |_| {
+                for approval in &approvals {
+                    if let Ok(Some(approver)) = governance.approver(approval, network) {
+                        println!("approved by: {}", approver);
+                    }
+                }
+            })?;
+        }
+        Some(("get-block", sub_matches)) => {
+            let id = sub_matches.get_one::<String>("BLOCK").expect("block");
+            let chain = open_chain(&path, &params, prune)?;
+            let (height, block) = find_block(&chain, id)?;
+            let valid = chain.consensus().check_block(&block, height)?;
+            output.print(&BlockView::new(&block, height, network), |_| {
+                println!("height: {}", height);
+                print_block(&block, valid);
+            })?;
+        }
+        Some(("get-tx", sub_matches)) => {
+            let id = sub_matches.get_one::<String>("ID").expect("id");
+            let chain = open_chain(&path, &params, prune)?;
+            let (tx, block_hash) = chain.find_transaction(id)?.ok_or(TxNotFound(id.clone()))?;
+            let height = chain.get_block_height(&block_hash)?;
+            let view = TransactionView::new(&tx, network);
+            let value = json!({
+                "block_hash": block_hash,
+                "height": height,
+                "transaction": view,
+            });
+            output.print(&value, |_| {
+                println!("tx: {}", view.id);
+                println!("block: {}", block_hash);
+                println!("height: {}", height.unwrap_or_default());
+                print_transaction(&view);
+            })?;
+        }
+        Some(("get-utxos", sub_matches)) => {
+            let address = sub_matches.get_one::<String>("ADDRESS").expect("address");
+            let chain = open_chain(&path, &params, prune)?;
+            let utxo = chain.find_utxo(Address::parse(address, network)?.pub_key_hash())?;
+            let utxos = UtxoView::list(utxo, network);
+            let balance: i64 = utxos.iter().map(|u| u.output.value).sum();
+            let value = json!({ "address": address, "balance": balance, "utxos": utxos });
+            output.print(&value, |_| {
+                for utxo in &utxos {
+                    println!(
+                        "tx: {}, vout: {}, value: {}",
+                        utxo.tx_id, utxo.idx_vout, utxo.output.value
+                    );
+                }
+                println!("balance: {}", balance);
+            })?;
+        }
+        Some(("hash-rate", sub_matches)) => {
+            let seconds = *sub_matches.get_one::<f64>("seconds").expect("seconds");
+            let rates = PowHash::ALL
+                .iter()
+                .map(|hash| {
+                    let rate = hash.hash_rate(Duration::from_secs_f64(seconds))?;
+                    Ok(json!({ "hash": hash, "hashes_per_second": rate }))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            output.print(&rates, |v| {
+                for rate in v {
+                    println!(
+                        "{}: {:.0} hashes/s",
+                        rate["hash"].as_str().unwrap_or_default(),
+                        rate["hashes_per_second"].as_f64().unwrap_or_default()
+                    );
+                }
+            })?;
+        }
+        Some(("chain-info", _)) => {
+            let chain = open_chain(&path, &params, prune)?;
+            let (utxo_count, supply) = chain.utxo_stats()?;
+            let info = ChainInfoView {
+                network: network.to_string(),
+                tip: chain.tip.clone(),
+                height: chain.height()?,
+                total_work: chain.total_work()?.to_string(),
+                utxo_count,
+                supply,
+                pruned_height: chain.pruned_height()?,
+            };
+            output.print(&info, |v| {
+                println!("network: {}", v.network);
+                println!("tip: {}", v.tip);
+                println!("height: {}", v.height);
+                println!("total work: {}", v.total_work);
+                println!("utxos: {}", v.utxo_count);
+                println!("supply: {}", v.supply);
+                if let Some(height) = v.pruned_height {
+                    println!("pruned: blocks up to height {}", height);
+                }
+            })?;
+        }
+        Some(("export-chain", sub_matches)) => {
+            let file = sub_matches.get_one::<String>("FILE").expect("file");
+            let chain = open_chain(&path, &params, prune)?;
+            let blocks = chain.export(BufWriter::new(File::create(file)?))?;
+            let value = json!({ "file": file, "blocks": blocks });
+            output.print(&value, |_| {
+                println!("exported {} blocks to {}", blocks, file);
+            })?;
+        }
+        Some(("import-chain", sub_matches)) => {
+            let file = sub_matches.get_one::<String>("FILE").expect("file");
+            // Check the whole archive before connecting any block of it.
+            ArchiveReader::new(BufReader::new(File::open(file)?))?.verify()?;
+            let (mut chain, imported) =
+                Blockchain::import(&path, params, BufReader::new(File::open(file)?))?;
+            chain.set_prune_depth(prune)?;
+            let value =
+                json!({ "imported": imported, "tip": chain.tip, "height": chain.height()? });
+            output.print(&value, |_| {
+                println!("imported {} blocks", imported);
+                println!("tip: {}", chain.tip);
+            })?;
+        }
+        Some(("utxo-set-info", _)) => {
+            let chain = open_chain(&path, &params, prune)?;
+            let (utxo_count, supply) = chain.utxo_stats()?;
+            let info = UtxoSetInfoView {
+                height: chain.height()?,
+                tip: chain.tip.clone(),
+                utxo_count,
+                supply,
+                hash: chain.utxo_hash()?,
+            };
+            output.print(&info, |v| {
+                println!("height: {}", v.height);
+                println!("tip: {}", v.tip);
+                println!("utxos: {}", v.utxo_count);
+                println!("supply: {}", v.supply);
+                println!("hash: {}", v.hash);
+            })?;
+        }
+        Some(("dump-utxos", sub_matches)) => {
+            let file = sub_matches.get_one::<String>("FILE").expect("file");
+            let chain = open_chain(&path, &params, prune)?;
+            let height = match sub_matches.get_one::<u64>("height") {
+                Some(height) => *height,
+                None => chain.height()?,
+            };
+            let snapshot = chain.utxo_snapshot(height)?;
+            snapshot.write(BufWriter::new(File::create(file)?))?;
+            let value = json!({
+                "file": file,
+                "height": height,
+                "tip": snapshot.tip(),
+                "hash": snapshot.hash(),
+                "utxo_count": snapshot.outputs.len(),
+            });
+            output.print(&value, |_| {
+                println!(
+                    "dumped {} outputs at height {} to {}",
+                    snapshot.outputs.len(),
+                    height,
+                    file
+                );
+                println!("hash: {}", snapshot.hash());
+            })?;
+        }
+        Some(("load-utxos", sub_matches)) => {
+            let file = sub_matches.get_one::<String>("FILE").expect("file");
+            let snapshot = UtxoSnapshot::read(BufReader::new(File::open(file)?))?;
+            let hash = sub_matches.get_one::<String>("hash").expect("hash");
+            let mut chain = Blockchain::load_snapshot(&path, params, &snapshot, hash)?;
+            chain.set_prune_depth(prune)?;
+            let value = json!({
+                "height": snapshot.height(),
+                "tip": chain.tip,
+                "hash": snapshot.hash(),
+            });
+            output.print(&value, |_| {
+                println!(
+                    "loaded {} outputs at height {}",
+                    snapshot.outputs.len(),
+                    snapshot.height()
+                );
+                println!("tip: {}", chain.tip);
+            })?;
+        }
+        Some(("migrate", sub_matches)) => {
+            let target = match sub_matches.get_one::<PathBuf>("copy-to") {
+                Some(dir) => {
+                    let target = network_dir(dir, network);
+                    copy_databases(&path, &target)?;
+                    target
+                }
+                None => path.clone(),
+            };
+            // Opening the databases upgrades them.
+            let chain = Blockchain::open_with_params(&target, params)?;
+            let value = json!({
+                "path": target,
+                "chain_version": chain.schema_version()?,
+                "wallets_version": chain.wallets().schema_version()?,
+            });
+            output.print(&value, |_| {
+                println!("migrated {}", target.display());
+                println!("chain: version {}", value["chain_version"]);
+                println!("wallets: version {}", value["wallets_version"]);
+            })?;
+        }
+        Some(("rest-server", sub_matches)) => {
+            let rpc_port = sub_matches.get_one::<u16>("rpc-port").copied();
+            let bind = match (
+                sub_matches.get_one::<String>("bind"),
+                rpc_port.or(config.rpc_port),
+            ) {
+                (Some(bind), _) => bind.clone(),
+                (None, Some(port)) => format!("127.0.0.1:{}", port),
+                (None, None) => params.default_rest_addr(),
+            };
+            let chain = open_chain(&path, &params, prune)?;
+            let server = RestServer::bind(chain, &bind)?;
+            server.run();
+        }
+        Some(("stratum-server", sub_matches)) => {
+            let bind = match sub_matches.get_one::<String>("bind") {
+                Some(bind) => bind.clone(),
+                None => params.default_stratum_addr(),
+            };
+            let chain = open_chain(&path, &params, prune)?;
+            if unlock_timeout.is_some() {
+                unlock_wallets(chain.wallets(), unlock_timeout)?;
+            }
+            // Only the process that connects blocks publishes events, since
+            // the database lock keeps other processes from writing.
+            if sub_matches.contains_id("events") {
+                let events = match sub_matches.get_one::<String>("events") {
+                    Some(events) => events.clone(),
+                    None => params.default_event_addr(),
+                };
+                let server = EventServer::bind(chain.events().clone(), &events)?;
+                thread::spawn(move || server.run());
+            }
+            StratumServer::bind(chain, &bind)?.run();
+        }
+        Some(("events-server", sub_matches)) => {
+            let bind = match sub_matches.get_one::<String>("bind") {
+                Some(bind) => bind.clone(),
+                None => params.default_event_addr(),
+            };
+            let mut chain = open_chain(&path, &params, prune)?;
+            let server = EventServer::bind(chain.events().clone(), &bind)?;
+            let Some(address) = sub_matches.get_one::<String>("seal") else {
+                server.run();
+                return Ok(());
+            };
+            let address = Address::parse(address, network)?;
+            if unlock_timeout.is_some() {
+                unlock_wallets(chain.wallets(), unlock_timeout)?;
+            }
+            let interval = *sub_matches.get_one::<u64>("interval").expect("interval");
+            thread::spawn(move || server.run());
+            loop {
+                // The database lock keeps every other process from sealing,
+                // so a height whose sealer has no key here stalls the chain.
+                // Subscribers learn of a sealed block, so it must survive a crash.
+                match chain.seal_block(&address).and_then(|hash| {
+                    chain.flush()?;
+                    Ok(hash)
+                }) {
+                    Ok(hash) => info!("sealed block {}", hash),
+                    Err(e) => warn!("failed to seal a block: {}", e),
+                }
+                thread::sleep(Duration::from_secs(interval));
+            }
+        }
+        Some(("stratum-miner", sub_matches)) => {
+            let addr = match sub_matches.get_one::<String>("ADDR") {
+                Some(addr) => addr.clone(),
+                None => params.default_stratum_addr(),
+            };
+            let address = sub_matches
+                .get_one::<String>("address")
+                .or(config.mining_address.as_ref())
+                .ok_or(StringError(
+                    "--address, RCHAIN_MINING_ADDRESS or mining_address in the config is required"
+                        .to_owned(),
+                ))?;
+            let count = *sub_matches.get_one::<usize>("blocks").expect("blocks");
+            let hashes = StratumMiner::connect(&addr, address)?.mine(count)?;
+            output.print(&json!({ "hashes": hashes }), |_| {
+                for hash in &hashes {
+                    println!("{}", hash);
+                }
+            })?;
+        }
+        _ => return Err(StringError("no subcommand, see --help".to_owned())),
+    }
+    Ok(())
+}
+
+/// Log with the level, or as `RUST_LOG` says if there is none.
+fn init_logger(level: Option<&String>) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if let Some(level) = level {
+        builder.parse_filters(level);
+    }
+    builder.init();
+}
+
+/// Return the addresses of all wallets, and whether each one is watch-only.
+fn wallet_addresses(wallets: &Wallets) -> Result<Vec<(String, bool)>> {
+    let mut addresses: Vec<(String, bool)> = wallets
+        .list()?
+        .into_iter()
+        .map(|(address, _)| (address, false))
+        .collect();
+    for (address, _) in wallets.list_watch_only()? {
+        addresses.push((address, true));
     }
+    Ok(addresses)
 }
 
-fn print_chain(chain: &Blockchain) {
-    let iter = chain.iter();
-    for block in iter {
-        println!("pre_hash: {}", block.pre_hash);
-        println!("hash: {}", block.hash);
-        println!("transaction: {:?}", block.transactions);
-        println!("nonce: {}", block.nonce);
-        println!("timestamp: {}", block.timestamp);
-        let pow = ProofOfWork::new(block.clone());
-        println!("pow: {}", pow.validate());
-        println!();
+fn watch_only_label(watch_only: bool) -> &'static str {
+    if watch_only {
+        " (watch-only)"
+    } else {
+        ""
+    }
+}
+
+fn balance(chain: &Blockchain, address: &str, watch_only: bool) -> Result<BalanceView> {
+    let utxo = chain.find_utxo(Address::parse(address, chain.network())?.pub_key_hash())?;
+    let balance = utxo
+        .values()
+        .map(|v| v.iter().fold(0, |acc, (_, x)| acc + x.value))
+        .sum();
+    Ok(BalanceView {
+        address: address.to_owned(),
+        balance,
+        watch_only,
+    })
+}
+
+fn history(
+    chain: &Blockchain,
+    address: &str,
+    watch_only: bool,
+    from: usize,
+    limit: usize,
+) -> Result<HistoryView> {
+    let address = Address::parse(address, chain.network())?;
+    Ok(HistoryView {
+        address: address.to_string(),
+        watch_only,
+        history: chain.address_history(address.pub_key_hash(), from, limit)?,
+    })
+}
+
+fn print_history(view: &HistoryView) {
+    for entry in &view.history {
+        println!(
+            "height: {}, tx: {}, {:?}: {}",
+            entry.height, entry.tx_id, entry.direction, entry.amount
+        );
+    }
+}
+
+/// Unlock the wallets for the timeout, [`UNLOCK_TIMEOUT`] by default, if they are encrypted.
+fn unlock_wallets(wallets: &Wallets, timeout: Option<Duration>) -> Result<()> {
+    if wallets.is_encrypted()? {
+        let passphrase = passphrase(PASSPHRASE_ENV, "Passphrase: ")?;
+        wallets.unlock(&passphrase, timeout.unwrap_or(UNLOCK_TIMEOUT))?;
+    }
+    Ok(())
+}
+
+/// Read a passphrase from the environment variable, or prompt for it.
+fn passphrase(env: &str, prompt: &str) -> Result<String> {
+    match std::env::var(env) {
+        Ok(v) => Ok(v),
+        Err(_) => Ok(rpassword::prompt_password(prompt)?),
+    }
+}
+
+/// Print the blocks from the tip to the genesis block, or to the last pruned block.
+fn print_chain(chain: &Blockchain, output: Output) -> Result<()> {
+    let mut blocks = vec![];
+    for block in chain.iter() {
+        let block = match block {
+            Ok(block) => block,
+            Err(BlockPruned(_)) => break,
+            Err(e) => return Err(e),
+        };
+        let height = chain.get_block_height(&block.hash)?.unwrap_or(0);
+        let valid = chain.consensus().check_block(&block, height)?;
+        blocks.push((height, block, valid));
+    }
+    let views: Vec<BlockView> = blocks
+        .iter()
+        .map(|(height, block, _)| BlockView::new(block, *height, chain.network()))
+        .collect();
+    output.print(&views, |_| {
+        for (_, block, valid) in &blocks {
+            print_block(block, *valid);
+        }
+    })
+}
+
+/// Print the block, `valid` is whether its seal is valid.
+fn print_block(block: &Block, valid: bool) {
+    println!("pre_hash: {}", block.pre_hash);
+    println!("hash: {}", block.hash);
+    println!("transaction: {:?}", block.transactions);
+    println!("nonce: {}", block.nonce);
+    println!("timestamp: {}", block.timestamp);
+    if block.validator.is_empty() {
+        println!("pow: {}", valid);
+    } else {
+        println!("validator: {}", block.validator);
+        println!("signature: {}", valid);
+    }
+    println!();
+}
+
+/// Print the inputs, outputs and governance change of the transaction.
+fn print_transaction(tx: &TransactionView) {
+    for input in &tx.vin {
+        if tx.coinbase {
+            println!("coinbase: {}", input.public_key);
+        } else {
+            println!("input: {}:{}", input.tx_id, input.idx_vout);
+        }
+    }
+    for output in &tx.vout {
+        println!("output: {} to {}", output.value, output.address);
+    }
+    if let Some(governance) = &tx.governance {
+        match &governance.change {
+            AuthorityChange::Add(address) => println!("governance: add {}", address),
+            AuthorityChange::Remove(address) => println!("governance: remove {}", address),
+        }
+        println!("approvals: {}", governance.approvals.len());
     }
 }
 
+/// Return the approvals of the governance change by the authorities at its
+/// height whose keys are in the wallets.
+fn local_approvals(chain: &Blockchain, governance: &Governance) -> Result<Vec<Approval>> {
+    let mut approvals = vec![];
+    for authority in chain.consensus().sealers(governance.height)? {
+        if let Some(wallet) = chain.get_wallet(&authority)? {
+            approvals.push(governance.approve(&wallet)?);
+        }
+    }
+    Ok(approvals)
+}
+
+/// Open the existing chain, without creating one, pruning it if `prune` is set.
+fn open_chain(path: &Path, params: &ChainParams, prune: Option<u64>) -> Result<Blockchain> {
+    let mut chain = Blockchain::open_with_params(path, params.clone())?;
+    chain.set_prune_depth(prune)?;
+    Ok(chain)
+}
+
+/// Copy the chain and the wallet databases of a network directory to another one.
+fn copy_databases(from: &Path, to: &Path) -> Result<()> {
+    if !chain_path(from).exists() {
+        return Err(ChainNotFound);
+    }
+    if chain_path(to).exists() {
+        return Err(StringError(format!("{} already has a chain", to.display())));
+    }
+    copy_engine(
+        &SledEngine::open(chain_path(from))?,
+        &SledEngine::open(chain_path(to))?,
+    )?;
+    copy_engine(
+        &SledEngine::open(wallets_path(from))?,
+        &SledEngine::open(wallets_path(to))?,
+    )
+}
+
+/// Find a block by its hash, or by its height if it is a number.
+fn find_block(chain: &Blockchain, id: &str) -> Result<(u64, Block)> {
+    let not_found = || BlockNotFound(id.to_owned());
+    let hash = match id.parse::<u64>() {
+        Ok(height) if id.len() < 64 => chain.get_block_hash(height)?.ok_or_else(not_found)?,
+        _ => id.to_owned(),
+    };
+    let block = chain.get_block(&hash)?.ok_or_else(not_found)?;
+    let height = chain.get_block_height(&hash)?.ok_or_else(not_found)?;
+    Ok((height, block))
+}
+
 fn cli() -> Command {
     Command::new("rchain")
         .about("A blockchain implemented in Rust")
@@ -91,11 +962,39 @@ fn cli() -> Command {
         //.subcommand_required(true)
         .allow_external_subcommands(true)
         .arg_required_else_help(true)
+        .args([
+            arg!(--datadir <DIR> "the data directory, current directory by default")
+                .global(true)
+                .env("RCHAIN_DATADIR")
+                .value_parser(clap::value_parser!(PathBuf)),
+            arg!(--config <FILE> "the config file, rchain.toml in the data directory by default")
+                .global(true)
+                .env("RCHAIN_CONFIG")
+                .value_parser(clap::value_parser!(PathBuf)),
+            arg!(--network <NETWORK> "mainnet, testnet or regtest, mainnet by default")
+                .global(true)
+                .env("RCHAIN_NETWORK")
+                .value_parser(clap::value_parser!(Network)),
+            arg!(--"log-level" <LEVEL> "the log filter, in the syntax of RUST_LOG")
+                .global(true)
+                .env("RCHAIN_LOG_LEVEL"),
+            arg!(--prune <DEPTH> "keep the bodies of only the last DEPTH blocks")
+                .global(true)
+                .env("RCHAIN_PRUNE")
+                .value_parser(clap::value_parser!(u64)),
+            arg!(--"unlock-timeout" <SECONDS> "keep encrypted wallets unlocked this long, 60 by default; stratum-server and events-server unlock them only with it")
+                .global(true)
+                .env("RCHAIN_UNLOCK_TIMEOUT")
+                .value_parser(clap::value_parser!(u64)),
+            arg!(--output <FORMAT> "text or json")
+                .global(true)
+                .value_parser(clap::value_parser!(Output))
+                .default_value("text"),
+        ])
         .subcommand(Command::new("ls").about("list the blockchain store in this directory"))
         .subcommand(
             Command::new("balance")
-                .about("show the balance of the address.")
-                .arg_required_else_help(true)
+                .about("show the balance of the address, or of all wallets and watch-only addresses.")
                 .arg(arg!([ADDRESS] "address")),
         )
         .subcommand(
@@ -108,12 +1007,234 @@ fn cli() -> Command {
                     Arg::new("AMOUNT").value_parser(clap::value_parser!(i64)),
                 ]),
         )
-        .subcommand(Command::new("create-wallet").about("create a wallet."))
+        .subcommand(
+            Command::new("create-wallet")
+                .about("create a wallet, derived from the seed if the wallets have one.")
+                .args([
+                    arg!(--mnemonic "generate a mnemonic to derive this and later wallets from"),
+                    arg!(--change "derive a change wallet instead of a receive wallet"),
+                ]),
+        )
+        .subcommand(
+            Command::new("restore-wallet")
+                .about("restore HD wallets from a mnemonic.")
+                .arg_required_else_help(true)
+                .args([
+                    arg!(<MNEMONIC> "the mnemonic words, quoted"),
+                    arg!(--count <N> "the number of receive wallets to derive")
+                        .value_parser(clap::value_parser!(u32))
+                        .default_value("1"),
+                ]),
+        )
         .subcommand(Command::new("wallets"))
+        .subcommand(
+            Command::new("watch")
+                .about("watch an address without its private key.")
+                .arg_required_else_help(true)
+                .args([
+                    arg!([ADDRESS] "the address, unless --public-key is given"),
+                    arg!(--"public-key" <KEY> "watch the address of the public key"),
+                    arg!(--remove "stop watching the address"),
+                ]),
+        )
+        .subcommand(
+            Command::new("export-key")
+                .about("export the private key of a wallet.")
+                .arg_required_else_help(true)
+                .args([
+                    arg!(<ADDRESS> "address"),
+                    arg!(--format <FORMAT> "pem, der, wif or hex")
+                        .value_parser(clap::value_parser!(KeyFormat))
+                        .default_value("wif"),
+                    arg!(--file <FILE> "write the key to the file instead of stdout"),
+                ]),
+        )
+        .subcommand(
+            Command::new("import-key")
+                .about("import a private key as a wallet.")
+                .arg_required_else_help(true)
+                .args([
+                    arg!([KEY] "the key, unless --file is given"),
+                    arg!(--format <FORMAT> "pem, der, wif or hex")
+                        .value_parser(clap::value_parser!(KeyFormat))
+                        .default_value("wif"),
+                    arg!(--file <FILE> "read the key from the file"),
+                    arg!(--address <ADDRESS> "the address the key must belong to"),
+                ]),
+        )
+        .subcommand(
+            Command::new("encrypt-wallet")
+                .about("encrypt the private keys of all wallets with a passphrase."),
+        )
+        .subcommand(
+            Command::new("change-passphrase").about("change the passphrase of encrypted wallets."),
+        )
         .subcommand(
             Command::new("create-blockchain")
                 .about("Create a blockchain.")
+                .arg(arg!([ADDRESS] "the address the first block rewards, the genesis block on a chain without a fixed one")),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("show the transaction history of the address, or of all wallets and watch-only addresses, newest first.")
+                .args([
+                    arg!([ADDRESS] "address"),
+                    arg!(--from <N> "skip the first N entries")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("0"),
+                    arg!(--limit <N> "show at most N entries")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10"),
+                ]),
+        )
+        .subcommand(
+            Command::new("generate")
+                .about("generate blocks that reward the address, on networks that allow it.")
                 .arg_required_else_help(true)
-                .arg(arg!([ADDRESS] "address")),
+                .args([
+                    Arg::new("COUNT").value_parser(clap::value_parser!(u64)),
+                    arg!([ADDRESS] "the address to reward, mining_address in the config by default")
+                        .env("RCHAIN_MINING_ADDRESS"),
+                ]),
+        )
+        .subcommand(
+            Command::new("add-authority")
+                .about("add an authority from the next block, on proof-of-authority networks.")
+                .arg_required_else_help(true)
+                .args([arg!(<ADDRESS> "the address of the authority"), approvals_arg()]),
+        )
+        .subcommand(
+            Command::new("remove-authority")
+                .about("remove an authority from the next block, on proof-of-authority networks.")
+                .arg_required_else_help(true)
+                .args([arg!(<ADDRESS> "the address of the authority"), approvals_arg()]),
+        )
+        .subcommand(
+            Command::new("approve-authority")
+                .about("approve adding or removing an authority in the next block, with the keys of the authorities in the wallets.")
+                .arg_required_else_help(true)
+                .args([
+                    arg!(<CHANGE> "the change").value_parser(["add", "remove"]),
+                    arg!(<ADDRESS> "the address of the authority"),
+                ]),
+        )
+        .subcommand(
+            Command::new("get-block")
+                .about("print a block.")
+                .arg_required_else_help(true)
+                .arg(arg!(<BLOCK> "the hash or the height of the block")),
+        )
+        .subcommand(
+            Command::new("get-tx")
+                .about("print a transaction and the block that contains it.")
+                .arg_required_else_help(true)
+                .arg(arg!(<ID> "the id of the transaction")),
+        )
+        .subcommand(
+            Command::new("get-utxos")
+                .about("print the unspent outputs of an address.")
+                .arg_required_else_help(true)
+                .arg(arg!(<ADDRESS> "the address")),
+        )
+        .subcommand(
+            Command::new("hash-rate")
+                .about("measure the hashes per second of every proof-of-work hash function.")
+                .arg(
+                    arg!(--seconds <S> "how long to measure each function")
+                        .value_parser(clap::value_parser!(f64))
+                        .default_value("1"),
+                ),
+        )
+        .subcommand(
+            Command::new("chain-info")
+                .about("print the tip, height, total work, UTXO count and supply of the chain."),
+        )
+        .subcommand(
+            Command::new("export-chain")
+                .about("write the blocks of the chain in height order to an archive file.")
+                .arg(arg!(<FILE> "the archive file to write")),
+        )
+        .subcommand(
+            Command::new("import-chain")
+                .about("validate and connect the blocks of an archive file, creating the chain if needed.")
+                .arg(arg!(<FILE> "the archive file to read")),
+        )
+        .subcommand(
+            Command::new("utxo-set-info")
+                .about("print the height, UTXO count, supply and UTXO set hash, to compare nodes."),
+        )
+        .subcommand(
+            Command::new("dump-utxos")
+                .about("write a snapshot of the UTXO set and the headers to a file.")
+                .args([
+                    arg!(<FILE> "the snapshot file to write"),
+                    arg!(--height <N> "the height of the snapshot, the tip by default")
+                        .value_parser(clap::value_parser!(u64)),
+                ]),
+        )
+        .subcommand(
+            Command::new("load-utxos")
+                .about("create the chain from a UTXO set snapshot, and validate blocks from its height.")
+                .args([
+                    arg!(<FILE> "the snapshot file to read"),
+                    arg!(--hash <HASH> "the UTXO set hash the snapshot must have, from a node you trust")
+                        .required(true),
+                ]),
         )
+        .subcommand(
+            Command::new("migrate")
+                .about("upgrade the chain and wallet databases to the schema of this binary.")
+                .arg(
+                    arg!(--"copy-to" <DIR> "upgrade a copy in this data directory, keeping the original")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("rest-server")
+                .about("Serve the read-only REST API of the block explorer.")
+                .args([
+                    arg!(--bind <ADDR> "the address to listen on, overrides --rpc-port"),
+                    arg!(--"rpc-port" <PORT> "the port to listen on, by default the port of the network")
+                        .env("RCHAIN_RPC_PORT")
+                        .value_parser(clap::value_parser!(u16)),
+                ]),
+        )
+        .subcommand(
+            Command::new("stratum-server")
+                .about("Serve block templates to external miners over the Stratum-style protocol.")
+                .args([
+                    arg!(--bind <ADDR> "the address to listen on, by default the port of the network"),
+                    arg!(--events [ADDR] "also stream chain events as newline-delimited JSON, by default on the port of the network"),
+                ]),
+        )
+        .subcommand(
+            Command::new("events-server")
+                .about("Stream chain events as newline-delimited JSON, under every consensus.")
+                .args([
+                    arg!(--bind <ADDR> "the address to listen on, by default the port of the network"),
+                    arg!(--seal <ADDRESS> "also seal a block that rewards the address every interval"),
+                    arg!(--interval <SECONDS> "the seconds between sealed blocks")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("10"),
+                ]),
+        )
+        .subcommand(
+            Command::new("stratum-miner")
+                .about("mine blocks for a Stratum server, and print their hashes.")
+                .args([
+                    arg!([ADDR] "the address of the server, by default the port of the network"),
+                    arg!(--address <ADDRESS> "the address to reward, mining_address in the config by default")
+                        .env("RCHAIN_MINING_ADDRESS"),
+                    arg!(--blocks <N> "stop after the server accepts N blocks")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("1"),
+                ]),
+        )
+}
+
+/// The `--approvals` argument of the governance commands.
+fn approvals_arg() -> Arg {
+    arg!(--approvals <FILE> "approvals of other authorities, the JSON output of approve-authority")
+        .action(ArgAction::Append)
+        .value_parser(clap::value_parser!(PathBuf))
 }
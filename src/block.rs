@@ -1,7 +1,9 @@
-use crate::proof_of_work::ProofOfWork;
+use crate::common::hash_str;
+use crate::consensus::Consensus;
+use crate::error::Error::StringError;
 use crate::transaction::Transaction;
+use crate::wallet::Wallets;
 use crate::Result;
-use log::info;
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -18,37 +20,110 @@ pub struct Block {
     /// The hash of this block, also as block headers.
     pub hash: String,
 
+    /// The hash of the transactions, which the block hash commits to.
+    ///
+    /// Empty in blocks stored before blocks committed to their transactions.
+    #[serde(default)]
+    pub tx_root: String,
+
     /// The nonce from Proof-of-Work mining.
     pub nonce: u64,
 
+    /// The public key of the validator that signed the block, empty under proof of work.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub validator: String,
+
+    /// The hex signature of the validator over the hash, empty under proof of work.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub signature: String,
+
     /// Stores transactions.
     pub transactions: Vec<Transaction>,
 }
 
+/// The fields of a block without its transactions, kept when the body is pruned.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlockHeader {
+    /// The current timestamp when the block is created.
+    pub timestamp: u64,
+
+    /// The hash of the previous block.
+    pub pre_hash: String,
+
+    /// The hash of the block.
+    pub hash: String,
+
+    /// The hash of the transactions, see [`Block::tx_root`].
+    #[serde(default)]
+    pub tx_root: String,
+
+    /// The nonce from Proof-of-Work mining.
+    pub nonce: u64,
+
+    /// The public key of the validator that signed the block, empty under proof of work.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub validator: String,
+
+    /// The hex signature of the validator over the hash, empty under proof of work.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub signature: String,
+}
+
 impl Block {
     /// New a genesis block.
-    pub fn new_genesis(coinbase: Transaction) -> Self {
-        Self::new(vec![coinbase], String::new())
+    pub fn new_genesis(
+        coinbase: Transaction,
+        consensus: &dyn Consensus,
+        wallets: &Wallets,
+    ) -> Result<Self> {
+        Self::new(vec![coinbase], String::new(), 0, consensus, wallets)
+    }
+
+    /// New a block with some data and the previous hash, sealed by the
+    /// consensus engine at the height the block will have.
+    pub fn new(
+        transactions: Vec<Transaction>,
+        pre_hash: String,
+        height: u64,
+        consensus: &dyn Consensus,
+        wallets: &Wallets,
+    ) -> Result<Self> {
+        let mut block = Self::new_unsealed(transactions, pre_hash)?;
+        consensus.seal(&mut block, height, wallets)?;
+        Ok(block)
     }
 
-    /// New a block with some data and the previous hash.
-    pub fn new(transactions: Vec<Transaction>, pre_hash: String) -> Self {
+    /// New a block with some data and the previous hash, timestamped now but
+    /// without a hash or a proof, for an external miner to seal.
+    pub fn new_unsealed(transactions: Vec<Transaction>, pre_hash: String) -> Result<Self> {
         let mut block = Block {
             transactions,
             pre_hash,
             hash: String::new(),
+            tx_root: String::new(),
             timestamp: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
-                .unwrap()
+                .map_err(|e| StringError(e.to_string()))?
                 .as_secs(),
             nonce: 0,
+            validator: String::new(),
+            signature: String::new(),
         };
-        let pow = ProofOfWork::new(block.clone());
-        let (nonce, hash) = pow.run();
-        info!("The block get nonce {}", nonce);
-        block.hash = hash;
-        block.nonce = nonce;
-        block
+        block.tx_root = block.compute_tx_root()?;
+        Ok(block)
+    }
+
+    /// Return the header of the block.
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            timestamp: self.timestamp,
+            pre_hash: self.pre_hash.clone(),
+            hash: self.hash.clone(),
+            tx_root: self.tx_root.clone(),
+            nonce: self.nonce,
+            validator: self.validator.clone(),
+            signature: self.signature.clone(),
+        }
     }
 
     /// Serialize a block to String.
@@ -71,4 +146,9 @@ impl Block {
         }
         Ok(str)
     }
+
+    /// Return the SHA-256 of the serialized transactions, which [`Block::tx_root`] must be.
+    pub fn compute_tx_root(&self) -> Result<String> {
+        Ok(hash_str(self.serialize_transactions()?))
+    }
 }
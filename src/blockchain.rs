@@ -1,56 +1,731 @@
-use crate::block::Block;
-use crate::engine::{SledEngine, BLOCK_TREE, LAST_HASH_OF_CHAIN};
-use crate::error::Error::StringError;
-use crate::transaction::{TXOutput, Transaction};
+use crate::archive::{ArchiveReader, ArchiveWriter};
+use crate::block::{Block, BlockHeader};
+use crate::config::{chain_path, wallets_path};
+use crate::consensus::Consensus;
+use crate::engine::{
+    Batch, MemoryEngine, SledEngine, StorageEngine, Tree, BLOCK_HEIGHT_TREE, BLOCK_TREE, CONSENSUS,
+    HEADERS_INDEXED, HEADER_TREE, HEIGHT_TREE, HISTORY_INDEXED, HISTORY_TREE, LAST_HASH_OF_CHAIN,
+    LEGACY_HEIGHT, NETWORK, PRUNED_HEIGHT, SCHEMA_VERSION, TX_INDEXED, TX_TREE, UNDO_TREE,
+    UTXO_INDEXED, UTXO_TREE,
+};
+use crate::error::Error::{
+    BlockNotFound, BlockPruned, ChainNotFound, CorruptBlock, InvalidArchive, InvalidBlock,
+    InvalidSnapshot, StringError, TxNotFound,
+};
+use crate::events::{ChainEvent, ChainEvents};
+use crate::history::{HistoryEntry, HistoryIndex};
+use crate::migration::{self, rewrite_tree, Migration};
+use crate::snapshot::{snapshot_hash, UtxoSnapshot};
+use crate::transaction::{TXInput, TXOutput, Transaction};
+use crate::utxo::UtxoSet;
 use crate::wallet::{Wallet, Wallets};
-use crate::{error, Result};
+use crate::{error, Address, ChainParams, Network, Result};
 use log::info;
-use std::collections::HashMap;
+use num::BigInt;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
 use std::path::PathBuf;
-
-const GENESIS_COINBASE_DATA: &str =
-    "The Times 03/Jan/2009 Chancellor on brink of second bailout for bank";
+use std::sync::Arc;
 
 /// The actual Blockchain container.
 pub struct Blockchain {
     /// Hash of the last block
     pub tip: String,
 
-    engine: SledEngine,
+    /// The engine that stores the trees, which writes each block in one batch.
+    store: Arc<dyn StorageEngine>,
+
+    engine: Tree,
+
+    /// Maps a block height to the block hash.
+    heights: Tree,
+
+    /// Maps a block hash to the block height.
+    block_heights: Tree,
+
+    /// Maps a block hash to the block header, kept when the body is pruned.
+    headers: Tree,
+
+    /// Maps a block hash to the outputs that its inputs spend.
+    undo: Tree,
+
+    /// The transaction history of addresses.
+    history: HistoryIndex,
+
+    /// The unspent transaction outputs.
+    utxo: UtxoSet,
+
+    /// Maps a transaction id to the hash of its block.
+    txs: Tree,
+
+    /// Subscribers of chain changes.
+    events: ChainEvents,
 
     /// wa.
     wallets: Wallets,
+
+    /// The parameters of the network that the chain runs on.
+    params: ChainParams,
+
+    /// The consensus engine of the network, which seals and checks blocks.
+    consensus: Box<dyn Consensus>,
+
+    /// The number of recent blocks whose bodies are kept, `None` keeps all of them.
+    prune_depth: Option<u64>,
+}
+
+/// Return the output that an input spends, out of the outputs that a block spends.
+fn spent_output_of(
+    spent: &HashMap<(String, usize), TXOutput>,
+    input: &TXInput,
+) -> Result<TXOutput> {
+    spent
+        .get(&(input.tx_id.clone(), input.idx_vout))
+        .cloned()
+        .ok_or(StringError(format!(
+            "no spent output {}:{}",
+            input.tx_id, input.idx_vout
+        )))
+}
+
+/// Read the body of a block, `None` if the chain has never seen the block.
+fn read_block(engine: &Tree, headers: &Tree, hash: &str) -> Result<Option<Block>> {
+    match engine.get(hash)? {
+        Some(v) => match Block::deserialize(&v) {
+            Ok(block) => Ok(Some(block)),
+            Err(e) => Err(CorruptBlock(format!("{}: {}", hash, e))),
+        },
+        None if headers.get(hash)?.is_some() => Err(BlockPruned(hash.to_owned())),
+        None => Ok(None),
+    }
 }
 
 impl Blockchain {
-    /// New a genesis Blockchain.
+    /// Open the chain on mainnet, or create it with the fixed genesis block of mainnet,
+    /// see [`Blockchain::with_engine`].
+    ///
+    /// `path` is the directory of the network, it holds the chain and the wallet databases.
     pub fn new(path: impl Into<PathBuf>, address: &str) -> Result<Self> {
+        Self::with_params(path, address, ChainParams::mainnet())
+    }
+
+    /// Open the chain on the network of the parameters, or create it with
+    /// its genesis block, see [`Blockchain::with_engine`].
+    ///
+    /// An existing chain must run on the same network.
+    pub fn with_params(
+        path: impl Into<PathBuf>,
+        address: &str,
+        params: ChainParams,
+    ) -> Result<Self> {
+        let path = path.into();
+        let store = Arc::new(SledEngine::open(chain_path(&path))?);
+        let wallets = Wallets::with_db(&sled::open(wallets_path(&path))?)?;
+        Self::with_engine(store, wallets, address, params)
+    }
+
+    /// Open the chain in the storage engine, or create it with the fixed genesis
+    /// block of the network, or else a genesis block that rewards the address.
+    ///
+    /// The address must be known to the wallets if coins are sent from it.
+    pub fn with_engine(
+        store: Arc<dyn StorageEngine>,
+        wallets: Wallets,
+        address: &str,
+        params: ChainParams,
+    ) -> Result<Self> {
+        let mut chain = Self::load(store, wallets, params)?;
+        if chain.tip.is_empty() {
+            info!("Creating a genesis block...");
+            let genesis = match chain.params.fixed_genesis_block()? {
+                Some(genesis) => genesis,
+                None => {
+                    let address = Address::parse(address, chain.params.network)?;
+                    chain.params.genesis_block(&address, &chain.wallets)?
+                }
+            };
+            chain.create(&genesis)?;
+        } else {
+            chain.migrate()?;
+            chain.check_network()?;
+            chain.check_genesis()?;
+            chain.check_checkpoints()?;
+        }
+        Ok(chain)
+    }
+
+    /// Open an existing chain on mainnet.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        Self::open_with_params(path, ChainParams::mainnet())
+    }
+
+    /// Open an existing chain on the network of the parameters.
+    ///
+    /// Return [`ChainNotFound`] instead of creating a chain if there is none.
+    pub fn open_with_params(path: impl Into<PathBuf>, params: ChainParams) -> Result<Self> {
         let path = path.into();
-        let db = sled::open(path)?;
-        let engine = SledEngine::with_db(BLOCK_TREE, &db)?;
-        let wallets = Wallets::with_db(&db);
+        // Opening a sled database creates it, so look before opening.
+        if !chain_path(&path).exists() {
+            return Err(ChainNotFound);
+        }
+        let store = Arc::new(SledEngine::open(chain_path(&path))?);
+        let wallets = Wallets::with_db(&sled::open(wallets_path(&path))?)?;
+        Self::open_with_engine(store, wallets, params)
+    }
+
+    /// Open an existing chain in the storage engine.
+    ///
+    /// Return [`ChainNotFound`] if the engine holds no chain.
+    pub fn open_with_engine(
+        store: Arc<dyn StorageEngine>,
+        wallets: Wallets,
+        params: ChainParams,
+    ) -> Result<Self> {
+        let chain = Self::load(store, wallets, params)?;
+        if chain.tip.is_empty() {
+            return Err(ChainNotFound);
+        }
+        chain.migrate()?;
+        chain.check_network()?;
+        chain.check_genesis()?;
+        chain.check_checkpoints()?;
+        Ok(chain)
+    }
+
+    /// Open the trees of the engine, the tip is empty if there is no chain.
+    fn load(store: Arc<dyn StorageEngine>, wallets: Wallets, params: ChainParams) -> Result<Self> {
+        let engine = store.open_tree(BLOCK_TREE)?;
+        let heights = store.open_tree(HEIGHT_TREE)?;
+        let block_heights = store.open_tree(BLOCK_HEIGHT_TREE)?;
+        let headers = store.open_tree(HEADER_TREE)?;
+        let undo = store.open_tree(UNDO_TREE)?;
+        let history = HistoryIndex::new(store.open_tree(HISTORY_TREE)?);
+        let utxo = UtxoSet::new(store.open_tree(UTXO_TREE)?);
+        let txs = store.open_tree(TX_TREE)?;
+        let wallets = wallets.on_network(params.network);
+        let mut consensus = params.consensus()?;
+        consensus.open(&*store)?;
         let tip = engine.get(LAST_HASH_OF_CHAIN)?;
-        let tip = match tip {
-            Some(v) => v,
-            None => {
-                info!("Creating a genesis block...");
-                let cbtx = Transaction::new_coinbase_tx(
-                    address.to_owned(),
-                    GENESIS_COINBASE_DATA.to_owned(),
-                );
-                let genesis = Block::new_genesis(cbtx);
-                let _ = engine.set(LAST_HASH_OF_CHAIN, &genesis.hash)?;
-                engine.set(&genesis.hash, genesis.serialize()?).unwrap();
-                genesis.hash
-            }
-        };
         Ok(Blockchain {
-            tip,
+            tip: tip.unwrap_or_default(),
+            store,
             engine,
+            heights,
+            block_heights,
+            headers,
+            undo,
+            history,
+            utxo,
+            txs,
+            events: ChainEvents::new(),
             wallets,
+            params,
+            consensus,
+            prune_depth: None,
+        })
+    }
+
+    /// Import the blocks of an archive into the chain in the directory, creating
+    /// the chain from the genesis block of the archive if there is none.
+    ///
+    /// Return the chain and the number of blocks connected.
+    pub fn import(
+        path: impl Into<PathBuf>,
+        params: ChainParams,
+        reader: impl Read,
+    ) -> Result<(Self, u64)> {
+        let path = path.into();
+        let store = Arc::new(SledEngine::open(chain_path(&path))?);
+        let wallets = Wallets::with_db(&sled::open(wallets_path(&path))?)?;
+        Self::import_with_engine(store, wallets, params, reader)
+    }
+
+    /// Import the blocks of an archive into the chain in the storage engine, see [`Blockchain::import`].
+    ///
+    /// Blocks the chain already has must match, and every other block is validated
+    /// as it is connected, so a failed import leaves a valid chain behind.
+    ///
+    /// With an assume-valid block in the parameters, signatures are not checked
+    /// until it connects, which proves the blocks before it are its ancestors
+    /// since every hash commits to the one before. If it never connects, the
+    /// blocks are disconnected and connected again with signature checks.
+    pub fn import_with_engine(
+        store: Arc<dyn StorageEngine>,
+        wallets: Wallets,
+        params: ChainParams,
+        reader: impl Read,
+    ) -> Result<(Self, u64)> {
+        let archive = ArchiveReader::new(reader)?;
+        if archive.network() != params.network {
+            return Err(InvalidArchive(format!(
+                "the archive is of {}, not {}",
+                archive.network(),
+                params.network
+            )));
+        }
+        let mut chain = Self::load(store, wallets, params)?;
+        if !chain.tip.is_empty() {
+            chain.migrate()?;
+            chain.check_network()?;
+            chain.check_checkpoints()?;
+        }
+        let mut unverified = 0;
+        let connected = chain.import_blocks(archive, &mut unverified);
+        chain.check_signatures(unverified)?;
+        let connected = connected?;
+        if chain.tip.is_empty() {
+            return Err(InvalidArchive("no blocks".to_owned()));
+        }
+        Ok((chain, connected))
+    }
+
+    /// Connect the blocks of the archive that the chain does not have, and
+    /// return their number.
+    ///
+    /// `unverified` counts the blocks at the tip whose signatures were not checked.
+    fn import_blocks(
+        &mut self,
+        archive: ArchiveReader<impl Read>,
+        unverified: &mut u64,
+    ) -> Result<u64> {
+        let mut assume_valid = match &self.params.assume_valid {
+            Some(hash) if self.get_block_height(hash)?.is_none() => Some(hash.clone()),
+            _ => None,
+        };
+        let mut connected = 0;
+        for (height, block) in (0u64..).zip(archive) {
+            let block = block?;
+            if let Some(hash) = self.get_block_hash(height)? {
+                if hash != block.hash {
+                    return Err(InvalidArchive(format!(
+                        "the archive diverges from the chain at height {}",
+                        height
+                    )));
+                }
+                continue;
+            }
+            // A genesis block has no signatures to skip.
+            let assumed = height > 0 && assume_valid.is_some();
+            self.connect(&block, !assumed)?;
+            connected += 1;
+            if assumed {
+                *unverified += 1;
+            }
+            if assume_valid.as_ref() == Some(&block.hash) {
+                assume_valid = None;
+                *unverified = 0;
+            }
+        }
+        Ok(connected)
+    }
+
+    /// Disconnect the `count` blocks at the tip and connect them again with signature checks.
+    fn check_signatures(&mut self, count: u64) -> Result<()> {
+        let mut blocks = vec![];
+        for _ in 0..count {
+            blocks.push(self.disconnect()?);
+        }
+        for block in blocks.iter().rev() {
+            self.connect(block, true)?;
+        }
+        Ok(())
+    }
+
+    /// Create the chain in the directory from a UTXO set snapshot, see
+    /// [`Blockchain::load_snapshot_with_engine`].
+    pub fn load_snapshot(
+        path: impl Into<PathBuf>,
+        params: ChainParams,
+        snapshot: &UtxoSnapshot,
+        trusted_hash: &str,
+    ) -> Result<Self> {
+        let path = path.into();
+        let store = Arc::new(SledEngine::open(chain_path(&path))?);
+        let wallets = Wallets::with_db(&sled::open(wallets_path(&path))?)?;
+        Self::load_snapshot_with_engine(store, wallets, params, snapshot, trusted_hash)
+    }
+
+    /// Create the chain in the storage engine from a UTXO set snapshot, in one batch.
+    ///
+    /// The headers must link, hash to their hashes and have valid seals. Headers
+    /// do not commit to the UTXO set, so [`UtxoSnapshot::hash`] must equal the
+    /// hash that a trusted node reports. The chain starts at the block of the
+    /// snapshot with every body up to it pruned, and the transaction and history
+    /// indexes start there too.
+    pub fn load_snapshot_with_engine(
+        store: Arc<dyn StorageEngine>,
+        wallets: Wallets,
+        params: ChainParams,
+        snapshot: &UtxoSnapshot,
+        trusted_hash: &str,
+    ) -> Result<Self> {
+        if snapshot.hash() != trusted_hash {
+            return Err(InvalidSnapshot(format!(
+                "the UTXO set hash is {}, not {}",
+                snapshot.hash(),
+                trusted_hash
+            )));
+        }
+        if snapshot.network != params.network {
+            return Err(InvalidSnapshot(format!(
+                "the snapshot is of {}, not {}",
+                snapshot.network, params.network
+            )));
+        }
+        let mut chain = Self::load(store, wallets, params)?;
+        if !chain.tip.is_empty() {
+            return Err(InvalidSnapshot(
+                "a chain already exists, load a snapshot into an empty one".to_owned(),
+            ));
+        }
+        // The headers are checked against the authority sets of the snapshot,
+        // which the batch below writes along with everything else.
+        let scratch = MemoryEngine::new();
+        let mut consensus = chain.params.consensus()?;
+        consensus.open(&scratch)?;
+        let mut batch = Batch::new();
+        consensus.load_authority_sets(&mut batch, &snapshot.authority_sets, snapshot.height())?;
+        scratch.apply_batch(&batch)?;
+
+        let mut batch = Batch::new();
+        let mut pre_hash = "";
+        for (height, header) in (0u64..).zip(&snapshot.headers) {
+            if header.pre_hash != pre_hash
+                || !consensus.check_header(header, height)?
+                || !chain.params.matches_checkpoints(height, &header.hash)
+                || !chain.params.matches_genesis(height, &header.hash)
+            {
+                return Err(InvalidSnapshot(format!(
+                    "invalid header {} at height {}",
+                    header.hash, height
+                )));
+            }
+            batch.put(&chain.headers, &header.hash, ron::to_string(header)?);
+            chain.set_height(&mut batch, height, &header.hash);
+            pre_hash = &header.hash;
+        }
+        for (tx_id, idx_vout, output) in &snapshot.outputs {
+            chain.utxo.add(&mut batch, tx_id, *idx_vout, output)?;
+        }
+        chain.consensus.load_authority_sets(
+            &mut batch,
+            &snapshot.authority_sets,
+            snapshot.height(),
+        )?;
+        let version = migration::latest(&chain.migrations());
+        batch.put(&chain.engine, SCHEMA_VERSION, version.to_string());
+        batch.put(&chain.engine, NETWORK, chain.params.network.to_string());
+        batch.put(&chain.engine, CONSENSUS, chain.consensus.name());
+        batch.put(&chain.engine, HEADERS_INDEXED, "1");
+        batch.put(&chain.engine, HISTORY_INDEXED, "1");
+        batch.put(&chain.engine, TX_INDEXED, "1");
+        batch.put(&chain.engine, UTXO_INDEXED, "1");
+        batch.put(&chain.engine, PRUNED_HEIGHT, snapshot.height().to_string());
+        batch.put(&chain.engine, LAST_HASH_OF_CHAIN, snapshot.tip());
+        chain.store.apply_batch(&batch)?;
+        chain.tip = snapshot.tip().to_owned();
+        Ok(chain)
+    }
+
+    /// Return a snapshot of the UTXO set at the height, with the headers up to it.
+    ///
+    /// Below the tip, a copy of the UTXO set is rolled back with the undo data,
+    /// so the blocks above the height must not be pruned.
+    pub fn utxo_snapshot(&self, height: u64) -> Result<UtxoSnapshot> {
+        let tip = self.height()?;
+        if height > tip {
+            return Err(StringError(format!("no block at height {}", height)));
+        }
+        let store = MemoryEngine::new();
+        let utxo = UtxoSet::new(store.open_tree(UTXO_TREE)?);
+        let mut batch = Batch::new();
+        for (tx_id, idx_vout, output) in self.utxo.outputs()? {
+            utxo.add(&mut batch, &tx_id, idx_vout, &output)?;
+        }
+        store.apply_batch(&batch)?;
+        for height in (height + 1..=tip).rev() {
+            let hash = self
+                .get_block_hash(height)?
+                .ok_or(StringError(format!("no block at height {}", height)))?;
+            let block = self.get_block(&hash)?.ok_or(BlockNotFound(hash))?;
+            let spent = self.undo_outputs(&block)?;
+            let mut batch = Batch::new();
+            utxo.disconnect_block(&mut batch, &block, |input| spent_output_of(&spent, input))?;
+            store.apply_batch(&batch)?;
+        }
+        let mut headers = vec![];
+        for height in 0..=height {
+            let hash = self
+                .get_block_hash(height)?
+                .ok_or(StringError(format!("no block at height {}", height)))?;
+            headers.push(self.get_header(&hash)?.ok_or(BlockNotFound(hash))?);
+        }
+        Ok(UtxoSnapshot {
+            network: self.params.network,
+            headers,
+            outputs: utxo.outputs()?,
+            authority_sets: self.consensus.authority_sets(height)?,
         })
     }
 
+    /// Write the blocks of the chain in height order as an archive, and return
+    /// the number of blocks.
+    pub fn export(&self, writer: impl Write) -> Result<u64> {
+        let mut archive = ArchiveWriter::new(writer, self.params.network)?;
+        let height = self.height()?;
+        for height in 0..=height {
+            let hash = self
+                .get_block_hash(height)?
+                .ok_or(StringError(format!("no block at height {}", height)))?;
+            let block = self.get_block(&hash)?.ok_or(BlockNotFound(hash))?;
+            archive.write_block(&block)?;
+        }
+        archive.finish()?;
+        Ok(height + 1)
+    }
+
+    /// Check that the stored chain runs on the network of the parameters.
+    fn check_network(&self) -> Result<()> {
+        let network = match self.engine.get(NETWORK)? {
+            Some(v) => v.parse()?,
+            None => Network::Mainnet,
+        };
+        if network != self.params.network {
+            return Err(StringError(format!(
+                "the chain runs on {}, not {}",
+                network, self.params.network
+            )));
+        }
+        // Chains created before consensus engines existed run on proof of work.
+        let consensus = self.engine.get(CONSENSUS)?;
+        let consensus = consensus.as_deref().unwrap_or("proof-of-work");
+        if consensus != self.consensus.name() {
+            return Err(StringError(format!(
+                "the chain runs on {}, not {}",
+                consensus,
+                self.consensus.name()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that the stored chain starts with the fixed genesis block of the network.
+    fn check_genesis(&self) -> Result<()> {
+        match self.get_block_hash(0)? {
+            Some(hash) if !self.params.matches_genesis(0, &hash) => Err(StringError(format!(
+                "the chain starts with block {}, not the genesis block of {}",
+                hash, self.params.network
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that the stored chain has the blocks of the checkpoints up to its tip.
+    fn check_checkpoints(&self) -> Result<()> {
+        for checkpoint in &self.params.checkpoints {
+            match self.get_block_hash(checkpoint.height)? {
+                Some(hash) if hash != checkpoint.hash => {
+                    return Err(StringError(format!(
+                        "the chain has block {} at height {}, which conflicts with checkpoint {}",
+                        hash, checkpoint.height, checkpoint.hash
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The migrations of the chain database, in version order.
+    fn migrations(&self) -> Vec<Migration<'_>> {
+        vec![
+            Migration::new(1, "build the missing indexes", || self.reindex()),
+            Migration::new(2, "commit blocks to their transactions", || {
+                let mut batch = rewrite_tree(&self.engine, |_, v| match Block::deserialize(v) {
+                    Ok(mut block) if block.tx_root.is_empty() => {
+                        block.tx_root = block.compute_tx_root()?;
+                        Ok(Some(block.serialize()?))
+                    }
+                    // Other keys of the block tree are not blocks.
+                    _ => Ok(Some(v.to_owned())),
+                })?;
+                batch.append(rewrite_tree(&self.headers, |hash, v| {
+                    let mut header: BlockHeader = ron::from_str(v).map_err(|e| e.code)?;
+                    if header.tx_root.is_empty() {
+                        // Pruned headers have no body to compute the root from.
+                        if let Some(body) = self.engine.get(hash)? {
+                            header.tx_root = Block::deserialize(&body)?.compute_tx_root()?;
+                        }
+                    }
+                    Ok(Some(ron::to_string(&header)?))
+                })?);
+                batch.put(&self.engine, LEGACY_HEIGHT, self.height()?.to_string());
+                Ok(batch)
+            }),
+        ]
+    }
+
+    /// Upgrade the chain database to the schema of this binary, see [`crate::migration`].
+    fn migrate(&self) -> Result<()> {
+        migration::migrate(&*self.store, &self.engine, "chain", &self.migrations())?;
+        Ok(())
+    }
+
+    /// Return the schema version of the chain database.
+    pub fn schema_version(&self) -> Result<u64> {
+        migration::version(&self.engine)
+    }
+
+    /// Return the writes that build the indexes that are missing.
+    ///
+    /// Chains created before an index existed only have block bodies. The
+    /// indexes are built from the bodies alone, in one batch with their
+    /// markers, so an interrupted build starts over on the next open.
+    fn reindex(&self) -> Result<Batch> {
+        let heights = self.get_block_height(&self.tip)?.is_none();
+        let headers = self.engine.get(HEADERS_INDEXED)?.is_none();
+        let txs = self.engine.get(TX_INDEXED)?.is_none();
+        let utxo = self.engine.get(UTXO_INDEXED)?.is_none();
+        let history = self.engine.get(HISTORY_INDEXED)?.is_none();
+        let mut batch = Batch::new();
+        if !(heights || headers || txs || utxo || history) {
+            return Ok(batch);
+        }
+        info!("Indexing blocks...");
+        let mut blocks = self.iter().collect::<Result<Vec<Block>>>()?;
+        blocks.reverse();
+        // The outputs of the blocks so far, since the batch is not applied yet.
+        let mut outputs: HashMap<(String, usize), i64> = HashMap::new();
+        for (height, block) in (0u64..).zip(&blocks) {
+            for tx in &block.transactions {
+                for (idx_vout, output) in tx.vout.iter().enumerate() {
+                    outputs.insert((tx.id.clone(), idx_vout), output.value);
+                }
+            }
+            if heights {
+                self.set_height(&mut batch, height, &block.hash);
+            }
+            if headers {
+                batch.put(&self.headers, &block.hash, ron::to_string(&block.header())?);
+            }
+            if txs {
+                self.index_transactions(&mut batch, block);
+            }
+            if utxo {
+                self.utxo.connect_block(&mut batch, block)?;
+            }
+            if history {
+                self.history
+                    .connect_block(&mut batch, block, height, |input| {
+                        outputs
+                            .get(&(input.tx_id.clone(), input.idx_vout))
+                            .copied()
+                            .ok_or(TxNotFound(input.tx_id.clone()))
+                    })?;
+            }
+        }
+        for (missing, marker) in [
+            (headers, HEADERS_INDEXED),
+            (txs, TX_INDEXED),
+            (utxo, UTXO_INDEXED),
+            (history, HISTORY_INDEXED),
+        ] {
+            if missing {
+                batch.put(&self.engine, marker, "1");
+            }
+        }
+        Ok(batch)
+    }
+
+    fn set_height(&self, batch: &mut Batch, height: u64, hash: &str) {
+        batch.put(&self.heights, height.to_string(), hash);
+        batch.put(&self.block_heights, hash, height.to_string());
+    }
+
+    fn index_transactions(&self, batch: &mut Batch, block: &Block) {
+        for tx in &block.transactions {
+            batch.put(&self.txs, &tx.id, &block.hash);
+        }
+    }
+
+    /// Return the output that an input of the block spends, looking up the transaction.
+    fn spent_output(&self, block: &Block, input: &TXInput) -> Result<TXOutput> {
+        let prev_tx = match block.transactions.iter().find(|tx| tx.id == input.tx_id) {
+            Some(tx) => tx.clone(),
+            None => self
+                .get_transaction(&input.tx_id)?
+                .ok_or(TxNotFound(input.tx_id.clone()))?,
+        };
+        let output = prev_tx
+            .vout
+            .get(input.idx_vout)
+            .ok_or(StringError(format!("no such output {}", input.idx_vout)))?;
+        Ok(output.clone())
+    }
+
+    /// Return the outputs that the inputs of a block spend, before it is connected.
+    ///
+    /// Outputs created earlier in the block come from the block, the others from the UTXO set.
+    fn spent_outputs(&self, block: &Block) -> Result<HashMap<(String, usize), TXOutput>> {
+        let mut created = HashMap::new();
+        let mut spent = HashMap::new();
+        for tx in &block.transactions {
+            if !tx.is_coinbase() {
+                for input in &tx.vin {
+                    let outpoint = (input.tx_id.clone(), input.idx_vout);
+                    let output = match created.remove(&outpoint) {
+                        Some(output) => output,
+                        None => {
+                            let pub_key_hash = Wallet::hash_pub_key(&input.public_key);
+                            self.utxo
+                                .get(&pub_key_hash, &input.tx_id, input.idx_vout)?
+                                .ok_or(StringError(format!(
+                                    "tx {} spends a missing output {}:{}",
+                                    tx.id, input.tx_id, input.idx_vout
+                                )))?
+                        }
+                    };
+                    spent.insert(outpoint, output);
+                }
+            }
+            for (idx_vout, output) in tx.vout.iter().enumerate() {
+                created.insert((tx.id.clone(), idx_vout), output.clone());
+            }
+        }
+        Ok(spent)
+    }
+
+    /// Return the outputs that the inputs of a connected block spend.
+    fn undo_outputs(&self, block: &Block) -> Result<HashMap<(String, usize), TXOutput>> {
+        if let Some(v) = self.undo.get(&block.hash)? {
+            return Ok(ron::from_str(&v).map_err(|e| e.code)?);
+        }
+        // Blocks connected before undo data existed look the spent transactions up.
+        let mut spent = HashMap::new();
+        for tx in block.transactions.iter().filter(|tx| !tx.is_coinbase()) {
+            for input in &tx.vin {
+                let output = self.spent_output(block, input)?;
+                spent.insert((input.tx_id.clone(), input.idx_vout), output);
+            }
+        }
+        Ok(spent)
+    }
+
+    /// Return the unspent outputs that the inputs of a transaction spend.
+    fn unspent_outputs(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<HashMap<(String, usize), TXOutput>> {
+        let mut outputs = HashMap::new();
+        for vin in &transaction.vin {
+            let pub_key_hash = Wallet::hash_pub_key(&vin.public_key);
+            if let Some(output) = self.utxo.get(&pub_key_hash, &vin.tx_id, vin.idx_vout)? {
+                outputs.insert((vin.tx_id.clone(), vin.idx_vout), output);
+            }
+        }
+        Ok(outputs)
+    }
+
     /// Get wallet.
     pub fn get_wallet(&self, address: &str) -> Result<Option<Wallet>> {
         self.wallets.get(address)
@@ -61,7 +736,30 @@ impl Blockchain {
         &self.wallets
     }
 
+    /// Return the network that the chain runs on.
+    pub fn network(&self) -> Network {
+        self.params.network
+    }
+
+    /// Return the parameters of the network that the chain runs on.
+    pub fn params(&self) -> &ChainParams {
+        &self.params
+    }
+
+    /// Return the consensus engine of the chain.
+    pub fn consensus(&self) -> &dyn Consensus {
+        &*self.consensus
+    }
+
+    /// Return the hub of chain events.
+    pub fn events(&self) -> &ChainEvents {
+        &self.events
+    }
+
     /// Will mine a block to the Blockchain.
+    ///
+    /// The transactions are accepted once they are all verified and the block
+    /// holding them is sealed, so `TxEnteredMempool` is published only then.
     pub fn mine_block(&mut self, transactions: Vec<Transaction>) -> Result<()> {
         for tx in &transactions {
             if !self.verify_transaction(tx)? {
@@ -72,15 +770,84 @@ impl Blockchain {
         // Get the last block hash from db
         let pre_hash = self.get_last_hash()?;
 
-        // Mine a new block
-        let block = Block::new(transactions, pre_hash);
+        // Seal a new block
+        let height = self.height()? + 1;
+        let block = Block::new(
+            transactions,
+            pre_hash,
+            height,
+            &*self.consensus,
+            &self.wallets,
+        )?;
+        for tx in &block.transactions {
+            self.events.publish(ChainEvent::TxEnteredMempool {
+                tx_id: tx.id.clone(),
+            });
+        }
 
         // Store the new block to db
-        self.update_engine(&block)?;
+        self.update_engine(&block, Batch::new())?;
 
         Ok(())
     }
 
+    /// Generate `count` blocks that only reward the address, and return their hashes.
+    ///
+    /// Only networks with [`ChainParams::generate_on_demand`] allow it.
+    pub fn generate_blocks(&mut self, address: &Address, count: u64) -> Result<Vec<String>> {
+        if !self.params.generate_on_demand {
+            return Err(StringError(format!(
+                "{} does not generate blocks on demand",
+                self.params.network
+            )));
+        }
+        let mut hashes = vec![];
+        for _ in 0..count {
+            hashes.push(self.seal_block(address)?);
+        }
+        Ok(hashes)
+    }
+
+    /// Seal a block on the tip that only rewards the address, connect it and return its hash.
+    ///
+    /// Under proof of stake and proof of authority it fails unless the wallets
+    /// hold the key of the sealer of the next height.
+    pub fn seal_block(&mut self, address: &Address) -> Result<String> {
+        let height = self.height()? + 1;
+        let block = Block::new(
+            vec![self.coinbase(address, height)?],
+            self.get_last_hash()?,
+            height,
+            &*self.consensus,
+            &self.wallets,
+        )?;
+        self.update_engine(&block, Batch::new())?;
+        Ok(block.hash)
+    }
+
+    /// Write the connected blocks through to disk, which sled otherwise does
+    /// in the background and when the chain is dropped.
+    pub fn flush(&self) -> Result<()> {
+        self.store.flush()
+    }
+
+    /// Return an unsealed block on the tip that only rewards the address,
+    /// for an external miner to seal, see [`crate::stratum`].
+    pub fn block_template(&self, address: &Address) -> Result<Block> {
+        let height = self.height()? + 1;
+        Block::new_unsealed(vec![self.coinbase(address, height)?], self.tip.clone())
+    }
+
+    /// New the coinbase of a block at the height that rewards the address.
+    fn coinbase(&self, address: &Address, height: u64) -> Result<Transaction> {
+        // The height keeps the ids of coinbase transactions unique.
+        Transaction::new_coinbase_tx(
+            address,
+            format!("height {}", height),
+            self.params.block_subsidy(height),
+        )
+    }
+
     fn get_last_hash(&self) -> Result<String> {
         let last_hash = self.engine.get(LAST_HASH_OF_CHAIN)?;
         match last_hash {
@@ -91,63 +858,491 @@ impl Blockchain {
         }
     }
 
-    fn update_engine(&mut self, block: &Block) -> Result<()> {
-        self.tip = block.hash.clone();
-        self.engine.set(LAST_HASH_OF_CHAIN, &block.hash)?;
-        self.engine.set(&block.hash, block.serialize()?)?;
+    /// Write the genesis block of a new chain.
+    fn create(&mut self, genesis: &Block) -> Result<()> {
+        // A chain exists once its genesis block and markers are written together.
+        let mut batch = Batch::new();
+        let version = migration::latest(&self.migrations());
+        batch.put(&self.engine, SCHEMA_VERSION, version.to_string());
+        batch.put(&self.engine, NETWORK, self.params.network.to_string());
+        batch.put(&self.engine, CONSENSUS, self.consensus.name());
+        batch.put(&self.engine, HEADERS_INDEXED, "1");
+        batch.put(&self.engine, HISTORY_INDEXED, "1");
+        batch.put(&self.engine, TX_INDEXED, "1");
+        batch.put(&self.engine, UTXO_INDEXED, "1");
+        self.update_engine(genesis, batch)
+    }
 
-        Ok(())
+    /// Validate a block received from elsewhere and connect it on top of the tip.
+    ///
+    /// On a chain without blocks it must be a genesis block, which creates the chain.
+    pub fn connect_block(&mut self, block: &Block) -> Result<()> {
+        self.connect(block, true)
     }
 
-    /// Return an iterator over the Blockchain
-    pub fn iter(&self) -> BlockChainIterator {
-        BlockChainIterator {
-            cur_hash: self.tip.clone(),
-            engine: self.engine.clone(),
+    /// Validate and connect the block, checking signatures only if `check_signatures`.
+    fn connect(&mut self, block: &Block, check_signatures: bool) -> Result<()> {
+        let height = if self.tip.is_empty() {
+            0
+        } else {
+            self.height()? + 1
+        };
+        self.validate_block(block, height, check_signatures)?;
+        if self.tip.is_empty() {
+            self.create(block)
+        } else {
+            self.update_engine(block, Batch::new())
         }
     }
 
-    /// Find unspent transaction outputs.
-    pub fn find_utxo(&self, pub_key_hash: &[u8]) -> HashMap<String, Vec<(usize, TXOutput)>> {
-        let mut iter = self.iter();
-        let mut spent_txos: HashMap<String, Vec<usize>> = HashMap::new();
-
-        let mut utxo = HashMap::new();
-
-        loop {
-            let block = iter.next();
-
-            let Some(block) = block else { break };
-            for tx in block.transactions {
-                for (out_idx, output) in tx.vout.iter().enumerate() {
-                    // Check if an output was already referenced in an input.
-                    // Skip those that were referenced in inputs(their values were moved to other outputs,
-                    // thus we cannot count them).
-                    if let Some(idxs) = spent_txos.get(&tx.id) {
-                        if idxs.contains(&out_idx) {
-                            continue;
-                        }
-                    }
+    /// Check the block against the tip and the UTXO set: the link to the tip,
+    /// the seal, the checkpoints, transaction ids, the coinbase, and that every
+    /// input spends an unspent output of its own key, with a valid signature
+    /// if `check_signatures`.
+    fn validate_block(&self, block: &Block, height: u64, check_signatures: bool) -> Result<()> {
+        let invalid =
+            |reason: &str| InvalidBlock(format!("{} at height {}: {}", block.hash, height, reason));
+        if block.pre_hash != self.tip {
+            return Err(invalid("does not extend the tip"));
+        }
+        if !self.consensus.check_block(block, height)? {
+            return Err(invalid(&format!("invalid {} seal", self.consensus.name())));
+        }
+        if !self.params.matches_checkpoints(height, &block.hash) {
+            return Err(invalid("conflicts with a checkpoint"));
+        }
+        if !self.params.matches_genesis(height, &block.hash) {
+            return Err(invalid(&format!(
+                "is not the genesis block of {}",
+                self.params.network
+            )));
+        }
+        if block.transactions.is_empty() {
+            return Err(invalid("no transactions"));
+        }
+        if height == 0 && (block.transactions.len() != 1 || !block.transactions[0].is_coinbase()) {
+            return Err(invalid("a genesis block has only a coinbase"));
+        }
 
-                    if output.is_locked_with_key(pub_key_hash) {
-                        let outs_idx = utxo.entry(tx.id.clone()).or_insert(vec![]);
-                        outs_idx.push((out_idx, output.clone()));
-                    }
+        // Outputs created earlier in the block can be spent later in it.
+        let mut created: HashMap<(String, usize), TXOutput> = HashMap::new();
+        let mut spent = HashMap::new();
+        let mut ids = HashSet::new();
+        for (idx, tx) in block.transactions.iter().enumerate() {
+            if !tx.has_valid_id()? {
+                return Err(invalid(&format!("tx {} has a wrong id", tx.id)));
+            }
+            if self.txs.get(&tx.id)?.is_some() || !ids.insert(tx.id.clone()) {
+                return Err(invalid(&format!("tx {} is a duplicate", tx.id)));
+            }
+            // The consensus engine checks the change of a governance transaction below.
+            if tx.governance.is_some() {
+                if !tx.vin.is_empty() || !tx.vout.is_empty() {
+                    return Err(invalid(&format!(
+                        "tx {} is a governance transaction that moves coins",
+                        tx.id
+                    )));
                 }
-
-                // Coinbase transaction don't unlock outputs.
-                if !tx.is_coinbase() {
-                    // We gather all inputs that could unlock outputs locked with the provided address.
-                    for input in tx.vin {
-                        if input.use_key(pub_key_hash) {
-                            let idxs = spent_txos.entry(input.tx_id.clone()).or_insert(vec![]);
-                            idxs.push(input.idx_vout);
+                continue;
+            }
+            if tx.vin.is_empty() {
+                return Err(invalid(&format!("tx {} has no inputs", tx.id)));
+            }
+            // A chain loaded from a snapshot only indexes later transactions,
+            // but the unspent outputs of earlier ones are in the UTXO set.
+            for (idx_vout, output) in tx.vout.iter().enumerate() {
+                if self
+                    .utxo
+                    .get(output.pub_key_hash(), &tx.id, idx_vout)?
+                    .is_some()
+                {
+                    return Err(invalid(&format!("tx {} is a duplicate", tx.id)));
+                }
+            }
+            let output_value: i64 = tx.vout.iter().map(|o| o.value).sum();
+            if tx.vout.iter().any(|o| o.value <= 0) {
+                return Err(invalid(&format!("tx {} has a non-positive output", tx.id)));
+            }
+            if tx.is_coinbase() {
+                if idx != 0 {
+                    return Err(invalid("the coinbase is not the first transaction"));
+                }
+                if output_value > self.params.block_subsidy(height) {
+                    return Err(invalid("the coinbase pays more than the subsidy"));
+                }
+            } else {
+                let mut input_value = 0;
+                for input in &tx.vin {
+                    let outpoint = (input.tx_id.clone(), input.idx_vout);
+                    if spent.contains_key(&outpoint) {
+                        return Err(invalid(&format!("tx {} double spends", tx.id)));
+                    }
+                    let pub_key_hash = Wallet::hash_pub_key(&input.public_key);
+                    let output = match created.remove(&outpoint) {
+                        Some(output) => Some(output),
+                        None => self.utxo.get(&pub_key_hash, &input.tx_id, input.idx_vout)?,
+                    };
+                    match output {
+                        Some(output) if output.is_locked_with_key(&pub_key_hash) => {
+                            input_value += output.value;
+                            spent.insert(outpoint, output);
+                        }
+                        _ => {
+                            return Err(invalid(&format!(
+                                "tx {} spends a missing output {}:{}",
+                                tx.id, input.tx_id, input.idx_vout
+                            )))
                         }
                     }
                 }
+                if input_value < output_value {
+                    return Err(invalid(&format!(
+                        "tx {} spends more than its inputs",
+                        tx.id
+                    )));
+                }
+                if check_signatures && !tx.verify(&spent)? {
+                    return Err(invalid(&format!("tx {} has an invalid signature", tx.id)));
+                }
+            }
+            for (idx_vout, output) in tx.vout.iter().enumerate() {
+                created.insert((tx.id.clone(), idx_vout), output.clone());
+            }
+        }
+        self.consensus
+            .connect_block(&mut Batch::new(), block, height)
+            .map_err(|e| invalid(&e.to_string()))?;
+        Ok(())
+    }
+
+    /// Connect the block on top of the tip.
+    ///
+    /// The block body, its header, undo data, indexes and the new tip are written
+    /// in one batch, along with the writes already in `batch`. Old bodies are
+    /// pruned after it in pruned mode.
+    fn update_engine(&mut self, block: &Block, mut batch: Batch) -> Result<()> {
+        let height = if block.pre_hash.is_empty() {
+            0
+        } else {
+            self.height()? + 1
+        };
+        let spent = self.spent_outputs(block)?;
+        batch.put(&self.engine, &block.hash, block.serialize()?);
+        batch.put(&self.headers, &block.hash, ron::to_string(&block.header())?);
+        batch.put(&self.undo, &block.hash, ron::to_string(&spent)?);
+        self.history
+            .connect_block(&mut batch, block, height, |input| {
+                Ok(spent_output_of(&spent, input)?.value)
+            })?;
+        self.utxo.connect_block(&mut batch, block)?;
+        self.consensus.connect_block(&mut batch, block, height)?;
+        self.index_transactions(&mut batch, block);
+        self.set_height(&mut batch, height, &block.hash);
+        batch.put(&self.engine, LAST_HASH_OF_CHAIN, &block.hash);
+        self.store.apply_batch(&batch)?;
+        self.tip = block.hash.clone();
+
+        self.events.publish(ChainEvent::BlockConnected {
+            hash: block.hash.clone(),
+            height,
+        });
+        for tx in &block.transactions {
+            self.events.publish(ChainEvent::TxConfirmed {
+                tx_id: tx.id.clone(),
+                block_hash: block.hash.clone(),
+                height,
+            });
+        }
+
+        self.prune()
+    }
+
+    /// Disconnect the tip block from the chain and return it.
+    ///
+    /// The block body and header are kept, but it is removed from the indexes.
+    /// A pruned block or the block of a checkpoint can not be disconnected.
+    pub fn disconnect_tip(&mut self) -> Result<Block> {
+        let height = self.height()?;
+        if self.params.checkpoints.iter().any(|c| c.height == height) {
+            return Err(StringError(format!(
+                "cannot disconnect the checkpoint at height {}",
+                height
+            )));
+        }
+        self.disconnect()
+    }
+
+    /// Disconnect the tip block, see [`Blockchain::disconnect_tip`].
+    fn disconnect(&mut self) -> Result<Block> {
+        let height = self.height()?;
+        if height == 0 {
+            return Err(StringError(
+                "cannot disconnect the genesis block".to_owned(),
+            ));
+        }
+        let block = self
+            .get_block(&self.tip)?
+            .ok_or(BlockNotFound(self.tip.clone()))?;
+        let spent = self.undo_outputs(&block)?;
+        let mut batch = Batch::new();
+        self.history.disconnect_block(&mut batch, &block, height)?;
+        self.consensus
+            .disconnect_block(&mut batch, &block, height)?;
+        self.utxo
+            .disconnect_block(&mut batch, &block, |input| spent_output_of(&spent, input))?;
+        batch.delete(&self.undo, &block.hash);
+        for tx in &block.transactions {
+            batch.delete(&self.txs, &tx.id);
+        }
+        batch.delete(&self.heights, height.to_string());
+        batch.delete(&self.block_heights, &block.hash);
+        batch.put(&self.engine, LAST_HASH_OF_CHAIN, &block.pre_hash);
+        self.store.apply_batch(&batch)?;
+        self.tip = block.pre_hash.clone();
+
+        self.events.publish(ChainEvent::BlockDisconnected {
+            hash: block.hash.clone(),
+            height,
+        });
+
+        Ok(block)
+    }
+
+    /// Return at most `limit` history entries of the public key hash, newest first,
+    /// skipping the first `from` entries.
+    pub fn address_history(
+        &self,
+        pub_key_hash: &[u8],
+        from: usize,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>> {
+        self.history.history(pub_key_hash, from, limit)
+    }
+
+    /// Return the height of the tip, the genesis block is at height 0.
+    pub fn height(&self) -> Result<u64> {
+        self.get_block_height(&self.tip)?
+            .ok_or(StringError(format!("no height for block {}", self.tip)))
+    }
+
+    /// Return the sum of the fork-choice weights of all blocks in the chain, see [`Consensus::block_weight`].
+    pub fn total_work(&self) -> Result<BigInt> {
+        let mut work = BigInt::from(0);
+        for height in 0..=self.height()? {
+            let hash = self
+                .get_block_hash(height)?
+                .ok_or(StringError(format!("no block at height {}", height)))?;
+            let header = self.get_header(&hash)?.ok_or(BlockNotFound(hash))?;
+            work += self.consensus.block_weight(&header);
+        }
+        Ok(work)
+    }
+
+    /// Keep the bodies of only the last `depth` blocks, and prune the older ones now.
+    ///
+    /// `None` keeps every body from now on, but bodies already pruned are gone.
+    pub fn set_prune_depth(&mut self, depth: Option<u64>) -> Result<()> {
+        if depth == Some(0) {
+            return Err(StringError("the prune depth must be at least 1".to_owned()));
+        }
+        self.prune_depth = depth;
+        self.prune()
+    }
+
+    /// Return the height of the last block whose body is pruned, `None` if no block is pruned.
+    pub fn pruned_height(&self) -> Result<Option<u64>> {
+        match self.engine.get(PRUNED_HEIGHT)? {
+            Some(v) => {
+                let height = v
+                    .parse()
+                    .map_err(|_| StringError(format!("invalid pruned height {}", v)))?;
+                Ok(Some(height))
             }
+            None => Ok(None),
         }
-        utxo
+    }
+
+    /// Return the height of the tip when the chain was migrated to blocks that
+    /// commit to their transactions, `None` if the chain was created after.
+    ///
+    /// The blocks up to it were checked when they were connected, but their
+    /// headers can not recompute their hashes, so archives and snapshots of
+    /// them are refused by other nodes.
+    pub fn legacy_height(&self) -> Result<Option<u64>> {
+        match self.engine.get(LEGACY_HEIGHT)? {
+            Some(v) => {
+                let height = v
+                    .parse()
+                    .map_err(|_| StringError(format!("invalid legacy height {}", v)))?;
+                Ok(Some(height))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Delete the bodies and undo data of the blocks deeper than the prune depth in one batch.
+    ///
+    /// It runs once the UTXO set and the indexes are up to date, which keep
+    /// working without the bodies, and the headers are kept.
+    fn prune(&self) -> Result<()> {
+        let Some(depth) = self.prune_depth else {
+            return Ok(());
+        };
+        let height = self.height()?;
+        if height < depth {
+            return Ok(());
+        }
+        let last = height - depth;
+        let first = match self.pruned_height()? {
+            Some(pruned) => pruned + 1,
+            None => 0,
+        };
+        if first > last {
+            return Ok(());
+        }
+        info!("Pruning blocks {} to {}...", first, last);
+        let mut batch = Batch::new();
+        for height in first..=last {
+            let hash = self
+                .get_block_hash(height)?
+                .ok_or(StringError(format!("no block at height {}", height)))?;
+            batch.delete(&self.engine, &hash);
+            batch.delete(&self.undo, &hash);
+        }
+        batch.put(&self.engine, PRUNED_HEIGHT, last.to_string());
+        self.store.apply_batch(&batch)
+    }
+
+    /// Check that the headers link the genesis block to the tip and have valid seals,
+    /// and return the number of headers.
+    ///
+    /// It needs no bodies, so it also checks the pruned part of the chain.
+    /// The seals of blocks up to [`Blockchain::legacy_height`] are not
+    /// checked, since their hashes were computed over their transactions.
+    pub fn verify_headers(&self) -> Result<u64> {
+        let legacy_height = self.legacy_height()?;
+        let height = self.height()?;
+        let mut pre_hash = String::new();
+        for height in 0..=height {
+            let invalid = |hash: &str, reason: &str| {
+                InvalidBlock(format!("{} at height {}: {}", hash, height, reason))
+            };
+            let hash = self
+                .get_block_hash(height)?
+                .ok_or(StringError(format!("no block at height {}", height)))?;
+            let header = self.get_header(&hash)?.ok_or(BlockNotFound(hash.clone()))?;
+            if header.hash != hash || header.pre_hash != pre_hash {
+                return Err(invalid(&hash, "does not link to the previous block"));
+            }
+            if !matches!(legacy_height, Some(legacy) if height <= legacy)
+                && !self.consensus.check_header(&header, height)?
+            {
+                return Err(invalid(
+                    &hash,
+                    &format!("invalid {} seal", self.consensus.name()),
+                ));
+            }
+            if !self.params.matches_checkpoints(height, &hash) {
+                return Err(invalid(&hash, "conflicts with a checkpoint"));
+            }
+            pre_hash = hash;
+        }
+        Ok(height + 1)
+    }
+
+    /// Return the number of unspent outputs and the coins they hold.
+    pub fn utxo_stats(&self) -> Result<(u64, i64)> {
+        self.utxo.stats()
+    }
+
+    /// Return the hash of the UTXO set and the authority sets at the tip, see [`snapshot_hash`].
+    pub fn utxo_hash(&self) -> Result<String> {
+        Ok(snapshot_hash(
+            &self.utxo.outputs()?,
+            &self.consensus.authority_sets(self.height()?)?,
+        ))
+    }
+
+    /// Get a block by its hash.
+    ///
+    /// Return [`BlockPruned`] if only the header of the block is kept.
+    pub fn get_block(&self, hash: &str) -> Result<Option<Block>> {
+        read_block(&self.engine, &self.headers, hash)
+    }
+
+    /// Get the header of a block by its hash, also kept for pruned blocks.
+    pub fn get_header(&self, hash: &str) -> Result<Option<BlockHeader>> {
+        match self.headers.get(hash)? {
+            Some(v) => Ok(Some(ron::from_str(&v).map_err(|e| e.code)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the hash of the block at the given height.
+    pub fn get_block_hash(&self, height: u64) -> Result<Option<String>> {
+        self.heights.get(height.to_string())
+    }
+
+    /// Get the height of the block with the given hash.
+    pub fn get_block_height(&self, hash: &str) -> Result<Option<u64>> {
+        match self.block_heights.get(hash)? {
+            Some(v) => {
+                let height = v
+                    .parse()
+                    .map_err(|_| StringError(format!("invalid height {}", v)))?;
+                Ok(Some(height))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Return at most `limit` blocks in height order, starting at height `from`.
+    pub fn blocks(&self, from: u64, limit: usize) -> Result<Vec<(u64, Block)>> {
+        let mut blocks = vec![];
+        for height in (from..).take(limit) {
+            let Some(hash) = self.get_block_hash(height)? else {
+                break;
+            };
+            let block = self.get_block(&hash)?.ok_or(BlockNotFound(hash.clone()))?;
+            blocks.push((height, block));
+        }
+        Ok(blocks)
+    }
+
+    /// Find a transaction and the hash of the block that contains it.
+    pub fn find_transaction(&self, tx_id: &str) -> Result<Option<(Transaction, String)>> {
+        let Some(block_hash) = self.txs.get(tx_id)? else {
+            return Ok(None);
+        };
+        let block = self
+            .get_block(&block_hash)?
+            .ok_or(BlockNotFound(block_hash.clone()))?;
+        let tx = block
+            .transactions
+            .into_iter()
+            .find(|tx| tx.id == tx_id)
+            .ok_or(StringError(format!(
+                "no tx {} in block {}",
+                tx_id, block_hash
+            )))?;
+        Ok(Some((tx, block_hash)))
+    }
+
+    /// Return an iterator over the Blockchain
+    pub fn iter(&self) -> BlockChainIterator {
+        BlockChainIterator {
+            cur_hash: self.tip.clone(),
+            engine: self.engine.clone(),
+            headers: self.headers.clone(),
+        }
+    }
+
+    /// Find unspent transaction outputs.
+    pub fn find_utxo(
+        &self,
+        pub_key_hash: &[u8],
+    ) -> Result<HashMap<String, Vec<(usize, TXOutput)>>> {
+        self.utxo.find(pub_key_hash)
     }
 
     /// Iterate over all unspent transactions and accumulate their values.
@@ -155,19 +1350,17 @@ impl Blockchain {
     /// Return the accumulated value and map(K -> tx_id, V -> the vector of output index in the transaction).
     pub fn find_spendable_outputs(
         &self,
-        address: &str,
+        pub_key_hash: &[u8],
         amount: i64,
-    ) -> (i64, HashMap<String, Vec<usize>>) {
+    ) -> Result<(i64, HashMap<String, Vec<usize>>)> {
         let mut outputs_idx = HashMap::new();
         let mut acc = 0;
 
-        let pub_key_hash = Transaction::pub_key_hash_from_address(address);
-
-        let utxo = self.find_utxo(&pub_key_hash);
+        let utxo = self.find_utxo(pub_key_hash)?;
 
         'find_acc: for (tx_id, outputs) in utxo {
             for (output_idx, output) in outputs {
-                if output.is_locked_with_key(&pub_key_hash) && acc < amount {
+                if output.is_locked_with_key(pub_key_hash) && acc < amount {
                     acc += output.value;
                     let entry = outputs_idx.entry(tx_id.clone()).or_insert(vec![]);
                     entry.push(output_idx);
@@ -178,73 +1371,1053 @@ impl Blockchain {
                 }
             }
         }
-        (acc, outputs_idx)
+        Ok((acc, outputs_idx))
     }
 
-    fn get_transaction(&self, tx_id: &str) -> Option<Transaction> {
-        let iter = self.iter();
-        for block in iter {
-            for tx in &block.transactions {
-                if tx.id.eq(tx_id) {
-                    return Some(tx.clone());
-                }
-            }
-        }
-        None
+    fn get_transaction(&self, tx_id: &str) -> Result<Option<Transaction>> {
+        Ok(self.find_transaction(tx_id)?.map(|(tx, _)| tx))
     }
 
     /// Sign the transaction.
-    pub fn sign_transaction(&self, transaction: &mut Transaction, private_key: &str) {
-        let mut prev_txs = HashMap::new();
-        for vin in &transaction.vin {
-            let prev_tx = self.get_transaction(&vin.tx_id).unwrap();
-            prev_txs.insert(prev_tx.id.clone(), prev_tx);
-        }
-        transaction.sign(private_key, prev_txs)
+    ///
+    /// The outputs it spends come from the UTXO set, so their blocks may be pruned.
+    pub fn sign_transaction(&self, transaction: &mut Transaction, private_key: &str) -> Result<()> {
+        transaction.sign(private_key, &self.unspent_outputs(transaction)?)
     }
 
     fn verify_transaction(&self, transaction: &Transaction) -> Result<bool> {
-        let mut prev_txs = HashMap::new();
-        for vin in &transaction.vin {
-            let prev_tx = self
-                .get_transaction(&vin.tx_id)
-                .ok_or(StringError(format!("no such tx {}", vin.tx_id)))?;
-            prev_txs.insert(prev_tx.id.clone(), prev_tx);
-        }
-        let res = transaction.verify(prev_txs)?;
-        Ok(res)
+        transaction.verify(&self.unspent_outputs(transaction)?)
     }
 }
 
 impl IntoIterator for Blockchain {
-    type Item = Block;
+    type Item = Result<Block>;
     type IntoIter = BlockChainIterator;
 
     fn into_iter(self) -> Self::IntoIter {
         BlockChainIterator {
             cur_hash: self.tip,
             engine: self.engine,
+            headers: self.headers,
         }
     }
 }
 
+/// Iterates over the blocks from the tip to the genesis block, and stops after an error.
+///
+/// In pruned mode it ends with [`BlockPruned`] at the first pruned block.
 pub struct BlockChainIterator {
     cur_hash: String,
-    engine: SledEngine,
+    engine: Tree,
+    headers: Tree,
 }
 
 impl Iterator for BlockChainIterator {
-    type Item = Block;
+    type Item = Result<Block>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let block = self.engine.get(&self.cur_hash).unwrap();
-        match block {
-            Some(val) => {
-                let block = Block::deserialize(&val).unwrap();
+        if self.cur_hash.is_empty() {
+            return None;
+        }
+        let hash = std::mem::take(&mut self.cur_hash);
+        match read_block(&self.engine, &self.headers, &hash) {
+            Ok(Some(block)) => {
                 self.cur_hash = block.pre_hash.clone();
-                Some(block)
+                Some(Ok(block))
             }
-            None => None,
+            Ok(None) => Some(Err(BlockNotFound(hash))),
+            Err(e) => Some(Err(e)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{FaultyEngine, MemoryEngine};
+    use crate::error::Error::{InvalidTransaction, UnsupportedSchema};
+    use crate::{
+        AuthorityChange, Checkpoint, ConsensusParams, Direction, Governance, ProofOfWork, Validator,
+    };
+    use tempfile::TempDir;
+
+    /// Regtest whose genesis block rewards the address the chain is created with,
+    /// so the tests can spend it.
+    fn regtest() -> ChainParams {
+        ChainParams {
+            genesis: None,
+            ..ChainParams::regtest()
+        }
+    }
+
+    #[test]
+    fn test_connect_and_disconnect() {
+        let temp_dir = TempDir::new().unwrap();
+        let (from, to) = (Wallet::new(), Wallet::new());
+        let params = ChainParams {
+            genesis: None,
+            ..ChainParams::mainnet()
+        };
+        let mut chain = Blockchain::with_params(temp_dir.path(), &from.address(), params).unwrap();
+        chain.wallets().set(&from).unwrap();
+        chain.wallets().set(&to).unwrap();
+
+        let events = chain.events().subscribe();
+        let tx = Transaction::new(&from.address(), &to.address(), 4, &chain).unwrap();
+        chain.mine_block(vec![tx.clone()]).unwrap();
+        assert_eq!(chain.height().unwrap(), 1);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ChainEvent::TxEnteredMempool {
+                tx_id: tx.id.clone()
+            }
+        );
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ChainEvent::BlockConnected {
+                hash: chain.tip.clone(),
+                height: 1
+            }
+        );
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ChainEvent::TxConfirmed {
+                tx_id: tx.id.clone(),
+                block_hash: chain.tip.clone(),
+                height: 1
+            }
+        );
+
+        let utxo = chain.find_utxo(&to.pub_key_hash()).unwrap();
+        assert_eq!(utxo[&tx.id], vec![(0, tx.vout[0].clone())]);
+        assert_eq!(chain.utxo_stats().unwrap(), (2, 10));
+        assert_eq!(
+            chain.find_transaction(&tx.id).unwrap().unwrap().1,
+            chain.tip
+        );
+        assert_eq!(chain.total_work().unwrap(), BigInt::from(512));
+
+        let history = chain.address_history(&from.pub_key_hash(), 0, 10).unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].tx_id, tx.id);
+        assert_eq!(history[0].direction, Direction::Sent);
+        assert_eq!(history[0].amount, 10);
+        assert_eq!(history[1].direction, Direction::Received);
+        assert_eq!(history[1].amount, 6);
+        assert_eq!(
+            chain
+                .address_history(&from.pub_key_hash(), 1, 1)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let block = chain.disconnect_tip().unwrap();
+        assert_eq!(block.transactions[0], tx);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ChainEvent::BlockDisconnected {
+                hash: block.hash.clone(),
+                height: 1
+            }
+        );
+        assert_eq!(chain.height().unwrap(), 0);
+        assert!(chain
+            .address_history(&to.pub_key_hash(), 0, 10)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            chain
+                .address_history(&from.pub_key_hash(), 0, 10)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(chain.find_utxo(&to.pub_key_hash()).unwrap().is_empty());
+        assert_eq!(chain.utxo_stats().unwrap(), (1, 10));
+        assert!(chain.find_transaction(&tx.id).unwrap().is_none());
+        assert!(chain.disconnect_tip().is_err());
+        assert!(chain
+            .generate_blocks(&Address::parse(&to.address(), Network::Mainnet).unwrap(), 1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_regtest() {
+        let temp_dir = TempDir::new().unwrap();
+        let wallet = Wallet::new();
+        let address = Address::new(Network::Regtest, wallet.pub_key_hash());
+        let mut chain = Blockchain::with_params(
+            temp_dir.path(),
+            &address.to_string(),
+            ChainParams::regtest(),
+        )
+        .unwrap();
+        // Every network starts from its own fixed genesis block, which rewards nobody.
+        let genesis = ChainParams::regtest().genesis.unwrap();
+        assert_eq!(chain.tip, genesis.hash);
+        let main = Blockchain::new(temp_dir.path().join("main"), "").unwrap();
+        assert_eq!(main.tip, ChainParams::mainnet().genesis.unwrap().hash);
+        drop(main);
+
+        let hashes = chain.generate_blocks(&address, 3).unwrap();
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(chain.height().unwrap(), 3);
+        assert_eq!(chain.tip, hashes[2]);
+        let balance: i64 = chain
+            .find_utxo(address.pub_key_hash())
+            .unwrap()
+            .values()
+            .flatten()
+            .map(|(_, output)| output.value)
+            .sum();
+        assert_eq!(balance, 30);
+        drop(chain);
+
+        assert!(Blockchain::new(temp_dir.path(), &wallet.address()).is_err());
+        // A chain that starts with another genesis block is not a regtest chain.
+        let minted = temp_dir.path().join("minted");
+        let params = ChainParams {
+            genesis: None,
+            ..ChainParams::regtest()
+        };
+        drop(Blockchain::with_params(&minted, &address.to_string(), params).unwrap());
+        assert!(matches!(
+            Blockchain::open_with_params(&minted, ChainParams::regtest()),
+            Err(StringError(e)) if e.ends_with("not the genesis block of regtest")
+        ));
+
+        let chain = Blockchain::open_with_params(temp_dir.path(), ChainParams::regtest()).unwrap();
+        assert_eq!(chain.tip, hashes[2]);
+        drop(chain);
+        assert!(matches!(
+            Blockchain::open(temp_dir.path().join("none")),
+            Err(ChainNotFound)
+        ));
+        assert!(!temp_dir.path().join("none").exists());
+    }
+
+    #[test]
+    fn test_memory_engine() {
+        let store = Arc::new(MemoryEngine::new());
+        let wallet = Wallet::new();
+        let address = Address::new(Network::Regtest, wallet.pub_key_hash());
+        let wallets = || Wallets::with_engine(Arc::new(MemoryEngine::new())).unwrap();
+        assert!(matches!(
+            Blockchain::open_with_engine(store.clone(), wallets(), regtest()),
+            Err(ChainNotFound)
+        ));
+
+        let mut chain =
+            Blockchain::with_engine(store.clone(), wallets(), &address.to_string(), regtest())
+                .unwrap();
+        let hashes = chain.generate_blocks(&address, 2).unwrap();
+        drop(chain);
+
+        let chain = Blockchain::open_with_engine(store, wallets(), regtest()).unwrap();
+        assert_eq!(chain.tip, hashes[1]);
+        assert_eq!(chain.height().unwrap(), 2);
+        assert_eq!(chain.utxo_stats().unwrap(), (3, 30));
+    }
+
+    #[test]
+    fn test_proof_of_stake() {
+        let store = Arc::new(MemoryEngine::new());
+        let wallet_store = MemoryEngine::new();
+        let wallets = || Wallets::with_engine(Arc::new(wallet_store.clone())).unwrap();
+        let validator = Wallet::new();
+        wallets()
+            .on_network(Network::Regtest)
+            .set(&validator)
+            .unwrap();
+        let address = Address::new(Network::Regtest, validator.pub_key_hash());
+        let mut params = regtest();
+        params.consensus = ConsensusParams::ProofOfStake {
+            validators: vec![Validator {
+                address: address.to_string(),
+                stake: 5,
+            }],
+        };
+
+        let mut chain = Blockchain::with_engine(
+            store.clone(),
+            wallets(),
+            &address.to_string(),
+            params.clone(),
+        )
+        .unwrap();
+        chain.generate_blocks(&address, 2).unwrap();
+        let tip = chain.get_block(&chain.tip).unwrap().unwrap();
+        assert_eq!(tip.nonce, 0);
+        assert_eq!(tip.validator, validator.public_key());
+        assert_eq!(chain.total_work().unwrap(), BigInt::from(15));
+        assert_eq!(chain.verify_headers().unwrap(), 3);
+        drop(chain);
+
+        let chain = Blockchain::open_with_engine(store.clone(), wallets(), params).unwrap();
+        assert_eq!(chain.height().unwrap(), 2);
+        drop(chain);
+        assert!(matches!(
+            Blockchain::open_with_engine(store, wallets(), regtest()),
+            Err(StringError(e)) if e == "the chain runs on proof-of-stake, not proof-of-work"
+        ));
+    }
+
+    #[test]
+    fn test_proof_of_authority() {
+        let store = Arc::new(MemoryEngine::new());
+        let wallets = Wallets::with_engine(Arc::new(MemoryEngine::new()))
+            .unwrap()
+            .on_network(Network::Regtest);
+        let (wa, wb) = (Wallet::new(), Wallet::new());
+        wallets.set(&wa).unwrap();
+        wallets.set(&wb).unwrap();
+        let [a, b] = [&wa, &wb].map(|w| w.address_on(Network::Regtest));
+        let c = Wallet::new().address_on(Network::Regtest);
+        let mut params = regtest();
+        params.consensus = ConsensusParams::ProofOfAuthority {
+            authorities: vec![a.clone(), b.clone()],
+        };
+
+        let mut chain = Blockchain::with_engine(store, wallets, &a, params).unwrap();
+        chain
+            .generate_blocks(&Address::parse(&a, Network::Regtest).unwrap(), 1)
+            .unwrap();
+        assert_eq!(chain.total_work().unwrap(), BigInt::from(2));
+        // a seals the block at height 2, but b must approve the change too.
+        let mut governance = Governance::new(AuthorityChange::Add(c.clone()), 2);
+        governance.approvals.push(governance.approve(&wa).unwrap());
+        let sealer_only = Transaction::new_governance_tx(governance.clone()).unwrap();
+        assert!(matches!(
+            chain.mine_block(vec![sealer_only]),
+            Err(InvalidTransaction(e)) if e.ends_with("is approved by 1 of 2 authorities, it needs a majority")
+        ));
+        assert_eq!(chain.height().unwrap(), 1);
+        governance.approvals.push(governance.approve(&wb).unwrap());
+        let add = Transaction::new_governance_tx(governance).unwrap();
+        chain.mine_block(vec![add]).unwrap();
+        assert_eq!(
+            chain.consensus().sealers(2).unwrap(),
+            vec![a.clone(), b.clone()]
+        );
+        assert_eq!(
+            chain.consensus().sealers(3).unwrap(),
+            vec![a.clone(), b.clone(), c.clone()]
+        );
+        assert_eq!(chain.verify_headers().unwrap(), 3);
+
+        // The key of c, whose turn it is at height 5, is not in the wallets.
+        let to_a = Address::parse(&a, Network::Regtest).unwrap();
+        chain.generate_blocks(&to_a, 2).unwrap();
+        assert!(chain.generate_blocks(&to_a, 1).is_err());
+        // A transaction whose block fails to seal never enters the mempool.
+        let events = chain.events().subscribe();
+        let tx = Transaction::new(&a, &b, 1, &chain).unwrap();
+        assert!(chain.mine_block(vec![tx]).is_err());
+        assert!(events.try_recv().is_err());
+        chain.disconnect_tip().unwrap();
+
+        // Disconnecting the governance block restores the authorities.
+        chain.disconnect_tip().unwrap();
+        let governance = chain.disconnect_tip().unwrap();
+        assert_eq!(chain.consensus().sealers(3).unwrap(), vec![a.clone(), b]);
+        let wrong_turn = Block::new(
+            governance.transactions.clone(),
+            chain.tip.clone(),
+            3,
+            chain.consensus(),
+            chain.wallets(),
+        )
+        .unwrap();
+        assert!(matches!(
+            chain.connect_block(&wrong_turn),
+            Err(InvalidBlock(e)) if e.ends_with("invalid proof-of-authority seal")
+        ));
+        chain.connect_block(&governance).unwrap();
+        assert_eq!(chain.consensus().sealers(3).unwrap().len(), 3);
+
+        let stale =
+            Transaction::new_governance_tx(Governance::new(AuthorityChange::Remove(a), 2)).unwrap();
+        assert!(chain.mine_block(vec![stale]).is_err());
+    }
+
+    #[test]
+    fn test_governance_needs_proof_of_authority() {
+        let wallets = Wallets::with_engine(Arc::new(MemoryEngine::new())).unwrap();
+        let address = Address::new(Network::Regtest, Wallet::new().pub_key_hash()).to_string();
+        let mut chain =
+            Blockchain::with_engine(Arc::new(MemoryEngine::new()), wallets, &address, regtest())
+                .unwrap();
+        let tx = Transaction::new_governance_tx(Governance::new(AuthorityChange::Add(address), 1))
+            .unwrap();
+        assert!(matches!(
+            chain.mine_block(vec![tx]),
+            Err(InvalidTransaction(e)) if e.ends_with("which proof-of-work does not accept")
+        ));
+        assert_eq!(chain.height().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_schema_version() {
+        let wallet_store = MemoryEngine::new();
+        let wallets = || Wallets::with_engine(Arc::new(wallet_store.clone())).unwrap();
+        let (from, to) = (Wallet::new(), Wallet::new());
+        let from_address = from.address_on(Network::Regtest);
+        let to_address = to.address_on(Network::Regtest);
+        let mut chain = Blockchain::with_engine(
+            Arc::new(MemoryEngine::new()),
+            wallets(),
+            &from_address,
+            regtest(),
+        )
+        .unwrap();
+        assert_eq!(chain.schema_version().unwrap(), 2);
+        assert_eq!(chain.legacy_height().unwrap(), None);
+        assert_eq!(wallets().schema_version().unwrap(), 1);
+        chain.wallets().set(&from).unwrap();
+        chain.wallets().set(&to).unwrap();
+        let tx = Transaction::new(&from_address, &to_address, 4, &chain).unwrap();
+        chain.mine_block(vec![tx.clone()]).unwrap();
+
+        // A chain written before versioning has only its network, its tip and
+        // its blocks, which have no transaction root.
+        let store = Arc::new(MemoryEngine::new());
+        let engine = store.open_tree(BLOCK_TREE).unwrap();
+        let mut batch = Batch::new();
+        batch.put(&engine, NETWORK, "regtest");
+        batch.put(&engine, LAST_HASH_OF_CHAIN, &chain.tip);
+        for block in chain.iter() {
+            let block = block.unwrap();
+            let old = block
+                .serialize()
+                .unwrap()
+                .replace(&format!("tx_root:\"{}\",", block.tx_root), "");
+            assert_ne!(old, block.serialize().unwrap());
+            batch.put(&engine, &block.hash, old);
+        }
+        store.apply_batch(&batch).unwrap();
+
+        let migrated = Blockchain::open_with_engine(store.clone(), wallets(), regtest()).unwrap();
+        assert_eq!(migrated.schema_version().unwrap(), 2);
+        assert_eq!(migrated.legacy_height().unwrap(), Some(1));
+        assert_eq!(migrated.height().unwrap(), 1);
+        for block in chain.iter() {
+            let block = block.unwrap();
+            assert_eq!(
+                migrated.engine.get(&block.hash).unwrap(),
+                Some(block.serialize().unwrap())
+            );
+            assert_eq!(
+                migrated.get_header(&block.hash).unwrap(),
+                Some(block.header())
+            );
+        }
+        assert_eq!(migrated.verify_headers().unwrap(), 2);
+        assert_eq!(migrated.utxo_hash().unwrap(), chain.utxo_hash().unwrap());
+        assert!(migrated.find_transaction(&tx.id).unwrap().is_some());
+        let pub_key_hash = from.pub_key_hash();
+        assert_eq!(
+            migrated.address_history(&pub_key_hash, 0, 10).unwrap(),
+            chain.address_history(&pub_key_hash, 0, 10).unwrap()
+        );
+
+        migrated.engine.set(SCHEMA_VERSION, "99").unwrap();
+        drop(migrated);
+        assert!(matches!(
+            Blockchain::open_with_engine(store, wallets(), regtest()),
+            Err(UnsupportedSchema(name, 99, 2)) if name == "chain"
+        ));
+    }
+
+    #[test]
+    fn test_crash_recovery() {
+        let (store, wallet_store) = (MemoryEngine::new(), MemoryEngine::new());
+        let wallets = || Wallets::with_engine(Arc::new(wallet_store.clone())).unwrap();
+        let open = |writes| {
+            let faulty = FaultyEngine::new(Arc::new(store.clone()), writes);
+            Blockchain::open_with_engine(Arc::new(faulty), wallets(), regtest()).unwrap()
+        };
+        let reopen =
+            || Blockchain::open_with_engine(Arc::new(store.clone()), wallets(), regtest()).unwrap();
+        let (from, to) = (Wallet::new(), Wallet::new());
+        let from_address = from.address_on(Network::Regtest);
+        let to_address = to.address_on(Network::Regtest);
+        let chain =
+            Blockchain::with_engine(Arc::new(store.clone()), wallets(), &from_address, regtest())
+                .unwrap();
+        chain.wallets().set(&from).unwrap();
+        chain.wallets().set(&to).unwrap();
+        let genesis = chain.tip.clone();
+        drop(chain);
+
+        // Kill the writer after each number of writes until mining succeeds,
+        // the chain must have all of the block or none of it.
+        let mut writes = 0;
+        let tx = loop {
+            let mut chain = open(writes);
+            let tx = Transaction::new(&from_address, &to_address, 4, &chain).unwrap();
+            let mined = chain.mine_block(vec![tx.clone()]);
+            let chain = reopen();
+            if mined.is_ok() {
+                assert_eq!(chain.height().unwrap(), 1);
+                assert!(chain.find_transaction(&tx.id).unwrap().is_some());
+                assert_eq!(chain.utxo_stats().unwrap(), (2, 10));
+                break tx;
+            }
+            assert_eq!(chain.tip, genesis);
+            assert_eq!(chain.height().unwrap(), 0);
+            assert!(chain.find_transaction(&tx.id).unwrap().is_none());
+            assert_eq!(chain.utxo_stats().unwrap(), (1, 10));
+            assert_eq!(chain.iter().count(), 1);
+            writes += 1;
+        };
+        assert_eq!(writes, 1);
+
+        let mut writes = 0;
+        loop {
+            let disconnected = open(writes).disconnect_tip();
+            let chain = reopen();
+            if disconnected.is_ok() {
+                assert_eq!(chain.tip, genesis);
+                assert!(chain.find_transaction(&tx.id).unwrap().is_none());
+                assert_eq!(chain.utxo_stats().unwrap(), (1, 10));
+                break;
+            }
+            assert_eq!(chain.height().unwrap(), 1);
+            assert!(chain.find_transaction(&tx.id).unwrap().is_some());
+            assert_eq!(chain.utxo_stats().unwrap(), (2, 10));
+            writes += 1;
+        }
+        assert_eq!(writes, 1);
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        let wallet_store = MemoryEngine::new();
+        let wallets = || Wallets::with_engine(Arc::new(wallet_store.clone())).unwrap();
+        let (from, to) = (Wallet::new(), Wallet::new());
+        let from_address = from.address_on(Network::Regtest);
+        let to_address = to.address_on(Network::Regtest);
+        let mut chain = Blockchain::with_engine(
+            Arc::new(MemoryEngine::new()),
+            wallets(),
+            &from_address,
+            regtest(),
+        )
+        .unwrap();
+        chain.wallets().set(&from).unwrap();
+        chain.wallets().set(&to).unwrap();
+        let tx = Transaction::new(&from_address, &to_address, 4, &chain).unwrap();
+        chain.mine_block(vec![tx.clone()]).unwrap();
+        let address = Address::parse(&to_address, Network::Regtest).unwrap();
+        chain.generate_blocks(&address, 2).unwrap();
+        let mut archive = vec![];
+        assert_eq!(chain.export(&mut archive).unwrap(), 4);
+
+        let store = Arc::new(MemoryEngine::new());
+        let (mut imported, count) =
+            Blockchain::import_with_engine(store.clone(), wallets(), regtest(), &archive[..])
+                .unwrap();
+        assert_eq!(count, 4);
+        assert_eq!(imported.tip, chain.tip);
+        assert_eq!(imported.utxo_stats().unwrap(), chain.utxo_stats().unwrap());
+        assert!(imported.find_transaction(&tx.id).unwrap().is_some());
+
+        // A double spend and an inflated coinbase are rejected.
+        let double_spend = Block::new(
+            vec![tx],
+            imported.tip.clone(),
+            imported.height().unwrap() + 1,
+            imported.consensus(),
+            imported.wallets(),
+        )
+        .unwrap();
+        assert!(matches!(
+            imported.connect_block(&double_spend),
+            Err(InvalidBlock(_))
+        ));
+        let coinbase = Transaction::new_coinbase_tx(&address, "more".to_owned(), 1000).unwrap();
+        let inflated = Block::new(
+            vec![coinbase],
+            imported.tip.clone(),
+            imported.height().unwrap() + 1,
+            imported.consensus(),
+            imported.wallets(),
+        )
+        .unwrap();
+        assert!(matches!(
+            imported.connect_block(&inflated),
+            Err(InvalidBlock(_))
+        ));
+        drop(imported);
+
+        // Importing again connects nothing, and a tampered block is rejected.
+        let (_, count) =
+            Blockchain::import_with_engine(store, wallets(), regtest(), &archive[..]).unwrap();
+        assert_eq!(count, 0);
+        let mut blocks = ArchiveReader::new(&archive[..])
+            .unwrap()
+            .collect::<Result<Vec<Block>>>()
+            .unwrap();
+        blocks[1].transactions[0].vout[0].value += 1;
+        let mut writer = ArchiveWriter::new(vec![], Network::Regtest).unwrap();
+        for block in &blocks {
+            writer.write_block(block).unwrap();
+        }
+        let tampered = writer.finish().unwrap();
+        let store = Arc::new(MemoryEngine::new());
+        let result =
+            Blockchain::import_with_engine(store.clone(), wallets(), regtest(), &tampered[..]);
+        assert!(matches!(result, Err(InvalidBlock(_))));
+        let chain = Blockchain::open_with_engine(store, wallets(), regtest()).unwrap();
+        assert_eq!(chain.height().unwrap(), 0);
+        assert!(Blockchain::import_with_engine(
+            Arc::new(MemoryEngine::new()),
+            wallets(),
+            ChainParams::mainnet(),
+            &archive[..],
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_checkpoints_and_assume_valid() {
+        let wallets = || Wallets::with_engine(Arc::new(MemoryEngine::new())).unwrap();
+        let (from, to) = (Wallet::new(), Wallet::new());
+        let from_address = from.address_on(Network::Regtest);
+        let address = Address::parse(&from_address, Network::Regtest).unwrap();
+        let mut chain = Blockchain::with_engine(
+            Arc::new(MemoryEngine::new()),
+            wallets(),
+            &from_address,
+            regtest(),
+        )
+        .unwrap();
+        chain.wallets().set(&from).unwrap();
+        chain.wallets().set(&to).unwrap();
+        let genesis = chain.get_block(&chain.tip.clone()).unwrap().unwrap();
+
+        // A block that spends the genesis coinbase with a signature of the wrong key.
+        let mut tx =
+            Transaction::new(&from_address, &to.address_on(Network::Regtest), 4, &chain).unwrap();
+        let spent = HashMap::from([(
+            (genesis.transactions[0].id.clone(), 0),
+            genesis.transactions[0].vout[0].clone(),
+        )]);
+        tx.sign(&to.private_key().unwrap(), &spent).unwrap();
+        let forged = Block::new(
+            vec![tx],
+            genesis.hash.clone(),
+            1,
+            chain.consensus(),
+            chain.wallets(),
+        )
+        .unwrap();
+        assert!(matches!(chain.connect_block(&forged), Err(InvalidBlock(_))));
+        let coinbase = Transaction::new_coinbase_tx(&address, "height 2".to_owned(), 10).unwrap();
+        let next = Block::new(
+            vec![coinbase],
+            forged.hash.clone(),
+            2,
+            chain.consensus(),
+            chain.wallets(),
+        )
+        .unwrap();
+        let archive_of = |blocks: &[&Block]| {
+            let mut writer = ArchiveWriter::new(vec![], Network::Regtest).unwrap();
+            for block in blocks {
+                writer.write_block(block).unwrap();
+            }
+            writer.finish().unwrap()
+        };
+        let archive = archive_of(&[&genesis, &forged, &next]);
+        let import = |params: ChainParams| {
+            let store = Arc::new(MemoryEngine::new());
+            let result =
+                Blockchain::import_with_engine(store.clone(), wallets(), params, &archive[..]);
+            (store, result)
+        };
+
+        // Signatures are checked without an assume-valid block, or if it never connects.
+        for assume_valid in [None, Some("00".to_owned())] {
+            let params = ChainParams {
+                assume_valid,
+                ..regtest()
+            };
+            let (store, result) = import(params.clone());
+            assert!(matches!(result, Err(InvalidBlock(_))));
+            let chain = Blockchain::open_with_engine(store, wallets(), params).unwrap();
+            assert_eq!(chain.tip, genesis.hash);
+        }
+
+        // The ancestors of the assume-valid block skip them, other checks still run.
+        let params = ChainParams {
+            assume_valid: Some(next.hash.clone()),
+            ..regtest()
+        };
+        let (store, result) = import(params.clone());
+        let (imported, count) = result.unwrap();
+        assert_eq!((imported.tip.clone(), count), (next.hash.clone(), 3));
+        drop(imported);
+        let replay = Block::new(
+            forged.transactions.clone(),
+            forged.hash.clone(),
+            2,
+            chain.consensus(),
+            chain.wallets(),
+        )
+        .unwrap();
+        let replayed = archive_of(&[&genesis, &forged, &replay]);
+        let params = ChainParams {
+            assume_valid: Some(replay.hash.clone()),
+            ..regtest()
+        };
+        let store_replay = Arc::new(MemoryEngine::new());
+        assert!(matches!(
+            Blockchain::import_with_engine(
+                store_replay.clone(),
+                wallets(),
+                params.clone(),
+                &replayed[..]
+            ),
+            Err(InvalidBlock(_))
+        ));
+        let chain = Blockchain::open_with_engine(store_replay, wallets(), params).unwrap();
+        assert_eq!(chain.tip, genesis.hash);
+
+        // A branch that conflicts with a checkpoint is rejected.
+        let checkpoint = |hash: &str| ChainParams {
+            checkpoints: vec![Checkpoint {
+                height: 1,
+                hash: hash.to_owned(),
+            }],
+            assume_valid: Some(next.hash.clone()),
+            ..regtest()
+        };
+        let (store_conflict, result) = import(checkpoint("00"));
+        assert!(matches!(result, Err(InvalidBlock(_))));
+        let chain = Blockchain::open_with_engine(store_conflict, wallets(), regtest()).unwrap();
+        assert_eq!(chain.tip, genesis.hash);
+        assert!(Blockchain::open_with_engine(store.clone(), wallets(), checkpoint("00")).is_err());
+
+        let mut chain =
+            Blockchain::open_with_engine(store, wallets(), checkpoint(&forged.hash)).unwrap();
+        assert_eq!(chain.verify_headers().unwrap(), 3);
+        let snapshot = chain.utxo_snapshot(2).unwrap();
+        assert!(matches!(
+            Blockchain::load_snapshot_with_engine(
+                Arc::new(MemoryEngine::new()),
+                wallets(),
+                checkpoint("00"),
+                &snapshot,
+                &snapshot.hash(),
+            ),
+            Err(InvalidSnapshot(_))
+        ));
+        chain.disconnect_tip().unwrap();
+        assert!(chain.disconnect_tip().is_err());
+        assert_eq!(chain.tip, forged.hash);
+    }
+
+    #[test]
+    fn test_prune() {
+        let store = Arc::new(MemoryEngine::new());
+        let wallet_store = MemoryEngine::new();
+        let wallets = || Wallets::with_engine(Arc::new(wallet_store.clone())).unwrap();
+        let (from, to) = (Wallet::new(), Wallet::new());
+        let from_address = from.address_on(Network::Regtest);
+        let to_address = to.address_on(Network::Regtest);
+        let mut chain =
+            Blockchain::with_engine(store.clone(), wallets(), &from_address, regtest()).unwrap();
+        chain.wallets().set(&from).unwrap();
+        chain.wallets().set(&to).unwrap();
+        let genesis = chain.tip.clone();
+        let tx = Transaction::new(&from_address, &to_address, 4, &chain).unwrap();
+        chain.mine_block(vec![tx.clone()]).unwrap();
+        let address = Address::parse(&to_address, Network::Regtest).unwrap();
+        chain.generate_blocks(&address, 2).unwrap();
+        let stats = chain.utxo_stats().unwrap();
+
+        assert!(chain.set_prune_depth(Some(0)).is_err());
+        chain.set_prune_depth(Some(2)).unwrap();
+        assert_eq!(chain.pruned_height().unwrap(), Some(1));
+        assert!(matches!(chain.get_block(&genesis), Err(BlockPruned(_))));
+        assert_eq!(chain.get_header(&genesis).unwrap().unwrap().pre_hash, "");
+        assert!(matches!(
+            chain.find_transaction(&tx.id),
+            Err(BlockPruned(_))
+        ));
+        assert!(matches!(chain.blocks(0, 10), Err(BlockPruned(_))));
+        assert_eq!(chain.iter().count(), 3);
+        assert!(matches!(chain.export(vec![]), Err(BlockPruned(_))));
+        assert_eq!(chain.verify_headers().unwrap(), 4);
+        assert_eq!(chain.utxo_stats().unwrap(), stats);
+        assert_eq!(
+            chain
+                .address_history(&to.pub_key_hash(), 0, 10)
+                .unwrap()
+                .len(),
+            3
+        );
+
+        // Outputs of pruned blocks can still be spent, and the block is pruned
+        // once it is deep enough.
+        let spend = Transaction::new(&to_address, &from_address, 3, &chain).unwrap();
+        chain.mine_block(vec![spend.clone()]).unwrap();
+        assert_eq!(chain.pruned_height().unwrap(), Some(2));
+        let block = chain.disconnect_tip().unwrap();
+        assert_eq!(block.transactions[0], spend);
+        assert_eq!(chain.utxo_stats().unwrap(), stats);
+        chain.disconnect_tip().unwrap();
+        assert!(matches!(chain.disconnect_tip(), Err(BlockPruned(_))));
+        drop(chain);
+
+        let chain = Blockchain::open_with_engine(store, wallets(), regtest()).unwrap();
+        assert_eq!(chain.pruned_height().unwrap(), Some(2));
+        assert_eq!(chain.verify_headers().unwrap(), 3);
+
+        // A pruned header whose fields were changed no longer hashes to its hash.
+        let mut header = chain.get_header(&genesis).unwrap().unwrap();
+        header.timestamp += 1;
+        chain
+            .headers
+            .set(&genesis, ron::to_string(&header).unwrap())
+            .unwrap();
+        assert!(matches!(chain.verify_headers(), Err(InvalidBlock(_))));
+    }
+
+    #[test]
+    fn test_utxo_snapshot() {
+        let wallet_store = MemoryEngine::new();
+        let wallets = || Wallets::with_engine(Arc::new(wallet_store.clone())).unwrap();
+        let (from, to) = (Wallet::new(), Wallet::new());
+        let from_address = from.address_on(Network::Regtest);
+        let to_address = to.address_on(Network::Regtest);
+        let mut chain = Blockchain::with_engine(
+            Arc::new(MemoryEngine::new()),
+            wallets(),
+            &from_address,
+            regtest(),
+        )
+        .unwrap();
+        chain.wallets().set(&from).unwrap();
+        chain.wallets().set(&to).unwrap();
+        let tx = Transaction::new(&from_address, &to_address, 4, &chain).unwrap();
+        chain.mine_block(vec![tx]).unwrap();
+        let hash = chain.utxo_hash().unwrap();
+        let address = Address::parse(&to_address, Network::Regtest).unwrap();
+        chain.generate_blocks(&address, 2).unwrap();
+        assert_ne!(chain.utxo_hash().unwrap(), hash);
+
+        let snapshot = chain.utxo_snapshot(1).unwrap();
+        assert_eq!(snapshot.height(), 1);
+        assert_eq!(snapshot.hash(), hash);
+        assert_eq!(
+            chain.utxo_snapshot(3).unwrap().hash(),
+            chain.utxo_hash().unwrap()
+        );
+        assert!(chain.utxo_snapshot(4).is_err());
+
+        // A node loaded from the snapshot validates the blocks after it.
+        let store = Arc::new(MemoryEngine::new());
+        let loaded = Blockchain::load_snapshot_with_engine(
+            store.clone(),
+            wallets(),
+            regtest(),
+            &snapshot,
+            &snapshot.hash(),
+        )
+        .unwrap();
+        assert_eq!(loaded.height().unwrap(), 1);
+        assert_eq!(loaded.utxo_hash().unwrap(), hash);
+        assert_eq!(loaded.pruned_height().unwrap(), Some(1));
+        assert!(matches!(loaded.get_block(&loaded.tip), Err(BlockPruned(_))));
+        drop(loaded);
+        let mut archive = vec![];
+        chain.export(&mut archive).unwrap();
+        let (loaded, count) =
+            Blockchain::import_with_engine(store.clone(), wallets(), regtest(), &archive[..])
+                .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(loaded.tip, chain.tip);
+        assert_eq!(loaded.utxo_hash().unwrap(), chain.utxo_hash().unwrap());
+        assert_eq!(loaded.verify_headers().unwrap(), 4);
+        drop(loaded);
+
+        assert!(matches!(
+            Blockchain::load_snapshot_with_engine(
+                store,
+                wallets(),
+                regtest(),
+                &snapshot,
+                &snapshot.hash(),
+            ),
+            Err(InvalidSnapshot(_))
+        ));
+        // Only the hash of a trusted node vouches for the outputs.
+        assert!(matches!(
+            Blockchain::load_snapshot_with_engine(
+                Arc::new(MemoryEngine::new()),
+                wallets(),
+                regtest(),
+                &snapshot,
+                "00",
+            ),
+            Err(InvalidSnapshot(_))
+        ));
+        // Made-up headers do not hash to their hashes, even below the target.
+        let mut made_up = snapshot.clone();
+        made_up.headers[1].hash = "0".repeat(64);
+        assert!(
+            ProofOfWork::meets_target(&made_up.headers[1].hash, &ChainParams::mainnet()).unwrap()
+        );
+        assert!(matches!(
+            Blockchain::load_snapshot_with_engine(
+                Arc::new(MemoryEngine::new()),
+                wallets(),
+                regtest(),
+                &made_up,
+                &made_up.hash(),
+            ),
+            Err(InvalidSnapshot(_))
+        ));
+        made_up.headers[1] = BlockHeader {
+            timestamp: made_up.headers[1].timestamp + 1,
+            ..snapshot.headers[1].clone()
+        };
+        assert!(matches!(
+            Blockchain::load_snapshot_with_engine(
+                Arc::new(MemoryEngine::new()),
+                wallets(),
+                regtest(),
+                &made_up,
+                &made_up.hash(),
+            ),
+            Err(InvalidSnapshot(_))
+        ));
+        let mut unlinked = snapshot.clone();
+        unlinked.headers.swap(0, 1);
+        assert!(Blockchain::load_snapshot_with_engine(
+            Arc::new(MemoryEngine::new()),
+            wallets(),
+            regtest(),
+            &unlinked,
+            &unlinked.hash(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_proof_of_authority_snapshot() {
+        let wallet_store = MemoryEngine::new();
+        let wallets = || {
+            Wallets::with_engine(Arc::new(wallet_store.clone()))
+                .unwrap()
+                .on_network(Network::Regtest)
+        };
+        let (wa, wb) = (Wallet::new(), Wallet::new());
+        wallets().set(&wa).unwrap();
+        wallets().set(&wb).unwrap();
+        let [a, b] = [&wa, &wb].map(|w| w.address_on(Network::Regtest));
+        let params = ChainParams {
+            consensus: ConsensusParams::ProofOfAuthority {
+                authorities: vec![a.clone()],
+            },
+            ..regtest()
+        };
+        let mut chain =
+            Blockchain::with_engine(Arc::new(MemoryEngine::new()), wallets(), &a, params.clone())
+                .unwrap();
+
+        // a adds b at height 1, so b seals the block at height 3.
+        let mut governance = Governance::new(AuthorityChange::Add(b.clone()), 1);
+        governance.approvals.push(governance.approve(&wa).unwrap());
+        chain
+            .mine_block(vec![Transaction::new_governance_tx(governance).unwrap()])
+            .unwrap();
+        let to_a = Address::parse(&a, Network::Regtest).unwrap();
+        chain.generate_blocks(&to_a, 1).unwrap();
+        let snapshot = chain.utxo_snapshot(2).unwrap();
+        assert_eq!(
+            snapshot.authority_sets,
+            vec![(2, vec![a.clone(), b.clone()])]
+        );
+        assert_eq!(snapshot.hash(), chain.utxo_hash().unwrap());
+        assert!(chain.utxo_snapshot(0).unwrap().authority_sets.is_empty());
+        chain.generate_blocks(&to_a, 2).unwrap();
+
+        // A node loaded from the snapshot keeps validating blocks under the new authorities.
+        let store = Arc::new(MemoryEngine::new());
+        let loaded = Blockchain::load_snapshot_with_engine(
+            store.clone(),
+            wallets(),
+            params.clone(),
+            &snapshot,
+            &snapshot.hash(),
+        )
+        .unwrap();
+        assert_eq!(loaded.consensus().sealers(3).unwrap(), vec![a.clone(), b]);
+        assert_eq!(loaded.utxo_hash().unwrap(), snapshot.hash());
+        drop(loaded);
+        let mut archive = vec![];
+        chain.export(&mut archive).unwrap();
+        let (loaded, count) =
+            Blockchain::import_with_engine(store, wallets(), params.clone(), &archive[..]).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(loaded.tip, chain.tip);
+        assert_eq!(loaded.utxo_hash().unwrap(), chain.utxo_hash().unwrap());
+
+        // The trusted hash commits to the authority sets.
+        let mut forged = snapshot.clone();
+        forged.authority_sets[0].1 = vec![a.clone()];
+        assert!(matches!(
+            Blockchain::load_snapshot_with_engine(
+                Arc::new(MemoryEngine::new()),
+                wallets(),
+                params.clone(),
+                &forged,
+                &snapshot.hash(),
+            ),
+            Err(InvalidSnapshot(_))
+        ));
+        // Without the sets, the block sealed by b does not check.
+        forged.authority_sets.clear();
+        let store = Arc::new(MemoryEngine::new());
+        Blockchain::load_snapshot_with_engine(
+            store.clone(),
+            wallets(),
+            params.clone(),
+            &forged,
+            &forged.hash(),
+        )
+        .unwrap();
+        assert!(Blockchain::import_with_engine(store, wallets(), params, &archive[..]).is_err());
+        // Other engines keep no authority sets.
+        let mut pow = snapshot.clone();
+        pow.headers.truncate(1);
+        assert!(matches!(
+            Blockchain::load_snapshot_with_engine(
+                Arc::new(MemoryEngine::new()),
+                wallets(),
+                regtest(),
+                &pow,
+                &pow.hash(),
+            ),
+            Err(InvalidSnapshot(e)) if e.ends_with("which proof-of-work does not keep")
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_block() {
+        let temp_dir = TempDir::new().unwrap();
+        let address = Wallet::new().address();
+        let chain = Blockchain::new(temp_dir.path(), &address).unwrap();
+        chain.engine.set(&chain.tip, "garbage").unwrap();
+
+        assert!(matches!(chain.get_block(&chain.tip), Err(CorruptBlock(_))));
+        let blocks: Vec<Result<Block>> = chain.iter().collect();
+        assert_eq!(blocks.len(), 1);
+        assert!(matches!(blocks[0], Err(CorruptBlock(_))));
+        assert!(matches!(chain.find_transaction("none"), Ok(None)));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::Error;
 use crate::Result;
 use num::{BigInt, Num};
 use ripemd::Ripemd160;
@@ -11,8 +12,8 @@ pub fn append_str(buffer: &mut Vec<u8>, data: &str) {
 }
 
 /// Convert hex string(hash) to BigInt.
-pub fn hex_to_big_int(hex: &str) -> BigInt {
-    BigInt::from_str_radix(hex, 16).unwrap()
+pub fn hex_to_big_int(hex: &str) -> Result<BigInt> {
+    BigInt::from_str_radix(hex, 16).map_err(|_| Error::StringError(format!("invalid hex: {}", hex)))
 }
 
 /// Convert [u8] to hash.
@@ -46,6 +47,25 @@ pub fn base58_decode(data: &str) -> Result<Vec<u8>> {
     Ok(v)
 }
 
+/// Encode [u8] to a lowercase hex string.
+pub fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a hex string to [u8].
+pub fn hex_decode(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(Error::StringError(format!("invalid hex: {}", hex)));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| Error::StringError(format!("invalid hex: {}", hex)))
+        })
+        .collect()
+}
+
 /// Calculate SHA256 hash.
 pub fn sha256_digest(data: &[u8]) -> Vec<u8> {
     let mut hasher = Sha256::new();
@@ -72,7 +92,7 @@ mod tests {
     #[test]
     fn test_hex_to_big_int() {
         let hex_str = "00000041662c5fc2883535dc19ba8a33ac993b535da9899e593ff98e1eda56a1".to_owned();
-        let b = hex_to_big_int(&hex_str);
+        let b = hex_to_big_int(&hex_str).unwrap();
         println!("b {}", b);
 
         let mut target = BigInt::from(1);
@@ -80,6 +100,16 @@ mod tests {
         println!("t {}", target);
 
         assert!(b.lt(&target));
+        assert!(hex_to_big_int("xyz").is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        let data = vec![0x00, 0x1f, 0xab, 0xff];
+        assert_eq!(hex_encode(&data), "001fabff");
+        assert_eq!(hex_decode("001fabff").unwrap(), data);
+        assert!(hex_decode("0g").is_err());
+        assert!(hex_decode("abc").is_err());
     }
 
     #[test]
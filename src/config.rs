@@ -0,0 +1,193 @@
+//! The config file and the layout of the data directory.
+//!
+//! The config file is TOML at `<datadir>/rchain.toml`. Command line flags
+//! override environment variables, which override the config file.
+//!
+//! ```toml
+//! network = "regtest"
+//! rpc_port = 23030
+//! mining_address = "..."
+//! log_level = "info"
+//! prune = 288
+//! unlock_timeout = 600
+//! pow_hash = "blake3"
+//! checkpoints = [{ height = 1000, hash = "..." }]
+//! assume_valid = "..."
+//!
+//! [consensus]
+//! engine = "proof-of-stake"
+//! validators = [{ address = "...", stake = 100 }]
+//! ```
+
+use crate::engine::{CHAIN_DIR, WALLETS_DIR};
+use crate::{Checkpoint, ConsensusParams, Network, PowHash, Result};
+use serde::Deserialize;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// The name of the config file in the data directory.
+pub const CONFIG_FILE: &str = "rchain.toml";
+
+/// Settings read from the config file, all of them optional.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The network to run on.
+    pub network: Option<Network>,
+
+    /// The port of the REST API.
+    pub rpc_port: Option<u16>,
+
+    /// The address that generated blocks reward.
+    pub mining_address: Option<String>,
+
+    /// The log filter, in the syntax of `RUST_LOG`.
+    pub log_level: Option<String>,
+
+    /// The number of recent blocks whose bodies are kept in pruned mode.
+    pub prune: Option<u64>,
+
+    /// How many seconds encrypted wallets stay unlocked.
+    pub unlock_timeout: Option<u64>,
+
+    /// The hash function of proof of work, which overrides the one of the network.
+    pub pow_hash: Option<PowHash>,
+
+    /// The consensus engine, which overrides the one of the network.
+    pub consensus: Option<ConsensusParams>,
+
+    /// Checkpoints in addition to the ones of the network.
+    #[serde(default)]
+    pub checkpoints: Vec<Checkpoint>,
+
+    /// The assume-valid block hash, which overrides the one of the network.
+    pub assume_valid: Option<String>,
+}
+
+impl Config {
+    /// Load the config file, a missing file is an empty config.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(v) => Self::parse(&v),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Parse a config from TOML.
+    pub fn parse(toml: &str) -> Result<Self> {
+        Ok(toml::from_str(toml)?)
+    }
+}
+
+/// Return the directory of the network in the data directory.
+///
+/// Mainnet uses the data directory itself, other networks a subdirectory.
+pub fn network_dir(datadir: impl AsRef<Path>, network: Network) -> PathBuf {
+    let datadir = datadir.as_ref();
+    match network {
+        Network::Mainnet => datadir.to_path_buf(),
+        _ => datadir.join(network.to_string()),
+    }
+}
+
+/// Return the path of the chain database in the directory of a network.
+pub fn chain_path(network_dir: impl AsRef<Path>) -> PathBuf {
+    network_dir.as_ref().join(CHAIN_DIR)
+}
+
+/// Return the path of the wallet database in the directory of a network.
+pub fn wallets_path(network_dir: impl AsRef<Path>) -> PathBuf {
+    network_dir.as_ref().join(WALLETS_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_config() {
+        let config = Config::parse(
+            r#"
+            network = "regtest"
+            rpc_port = 8080
+            log_level = "info"
+            prune = 288
+            unlock_timeout = 600
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.network, Some(Network::Regtest));
+        assert_eq!(config.rpc_port, Some(8080));
+        assert_eq!(config.mining_address, None);
+        assert_eq!(config.log_level.as_deref(), Some("info"));
+        assert_eq!(config.prune, Some(288));
+        assert_eq!(config.unlock_timeout, Some(600));
+
+        assert_eq!(config.consensus, None);
+
+        let config = Config::parse(
+            r#"
+            [consensus]
+            engine = "proof-of-stake"
+            validators = [{ address = "a", stake = 60 }, { address = "b", stake = 40 }]
+            "#,
+        )
+        .unwrap();
+        let ConsensusParams::ProofOfStake { validators } = config.consensus.unwrap() else {
+            panic!("not proof of stake");
+        };
+        assert_eq!(validators.len(), 2);
+        assert_eq!(validators[1].stake, 40);
+        let config =
+            Config::parse("[consensus]\nengine = \"proof-of-authority\"\nauthorities = [\"a\"]")
+                .unwrap();
+        assert_eq!(
+            config.consensus,
+            Some(ConsensusParams::ProofOfAuthority {
+                authorities: vec!["a".to_owned()]
+            })
+        );
+        assert!(Config::parse("[consensus]\nengine = \"proof-of-luck\"").is_err());
+        assert_eq!(
+            Config::parse("pow_hash = \"double-sha256\"")
+                .unwrap()
+                .pow_hash,
+            Some(PowHash::DoubleSha256)
+        );
+        assert!(Config::parse("pow_hash = \"md5\"").is_err());
+        let config = Config::parse(
+            r#"
+            checkpoints = [{ height = 10, hash = "ab" }]
+            assume_valid = "cd"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.checkpoints,
+            vec![Checkpoint {
+                height: 10,
+                hash: "ab".to_owned()
+            }]
+        );
+        assert_eq!(config.assume_valid.as_deref(), Some("cd"));
+        assert!(Config::parse("checkpoints = [{ height = 10 }]").is_err());
+
+        assert!(Config::parse("network = \"moon\"").is_err());
+        assert!(Config::parse("unknown = 1").is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(CONFIG_FILE);
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+        fs::write(&path, "rpc_port = 1").unwrap();
+        assert_eq!(Config::load(&path).unwrap().rpc_port, Some(1));
+
+        assert_eq!(network_dir("d", Network::Mainnet), PathBuf::from("d"));
+        assert_eq!(
+            network_dir("d", Network::Regtest),
+            PathBuf::from("d").join("regtest")
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! The consensus engines that seal and check blocks.
+//!
+//! A [`Consensus`] decides how a block is sealed, which headers are valid and
+//! how much a block weighs in fork choice. [`crate::ProofOfWork`] mining is
+//! one engine, stake-weighted [`crate::PosConsensus`] and turn-taking
+//! [`crate::PoaConsensus`] are others, see [`crate::ConsensusParams`] to
+//! choose one.
+
+use crate::common::{append_str, hash_utf8, hex_decode, hex_encode};
+use crate::engine::{Batch, StorageEngine};
+use crate::error::Error::{InvalidKey, InvalidSnapshot, InvalidTransaction};
+use crate::wallet::{Wallet, Wallets};
+use crate::{Block, BlockHeader, Result};
+use num::BigInt;
+use p256::ecdsa::signature::{Signer, Verifier};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use p256::{PublicKey, SecretKey};
+use std::str::FromStr;
+
+/// A consensus engine.
+pub trait Consensus: Send + Sync {
+    /// Return the name of the engine, which the chain stores to refuse opening with another one.
+    fn name(&self) -> &'static str;
+
+    /// Open the state that the engine keeps in the chain database.
+    ///
+    /// Until it is opened, the engine knows only its parameters, which is
+    /// enough for the genesis block.
+    fn open(&mut self, _store: &dyn StorageEngine) -> Result<()> {
+        Ok(())
+    }
+
+    /// Fill in the hash of the block at the height and the proof that the engine requires.
+    ///
+    /// The transactions, the previous hash and the timestamp are already set.
+    /// Engines that sign blocks take the key from the wallets.
+    fn seal(&self, block: &mut Block, height: u64, wallets: &Wallets) -> Result<()>;
+
+    /// Return the hash of the block, which commits to its header fields and
+    /// through [`Block::tx_root`] to its transactions.
+    fn block_hash(&self, block: &Block) -> Result<String>;
+
+    /// Check that the hash of a header at the height commits to its fields, and its proof.
+    ///
+    /// It needs only the header, so it also checks blocks whose body is pruned.
+    fn check_header(&self, header: &BlockHeader, height: u64) -> Result<bool>;
+
+    /// Return the weight of a block in fork choice, the chain with the most total weight wins.
+    fn block_weight(&self, header: &BlockHeader) -> BigInt;
+
+    /// Return the addresses that may seal the block at the height, empty if anyone may.
+    fn sealers(&self, _height: u64) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    /// Check that the block commits to its transactions and that its header is valid.
+    fn check_block(&self, block: &Block, height: u64) -> Result<bool> {
+        Ok(block.compute_tx_root()? == block.tx_root
+            && self.block_hash(block)? == block.hash
+            && self.check_header(&block.header(), height)?)
+    }
+
+    /// Add the changes that connecting the block at the height makes to the
+    /// state of the engine to the batch.
+    ///
+    /// Return an error if the block has governance transactions the engine
+    /// does not accept, which only proof of authority does.
+    fn connect_block(&self, _batch: &mut Batch, block: &Block, _height: u64) -> Result<()> {
+        match block.transactions.iter().find(|tx| tx.governance.is_some()) {
+            Some(tx) => Err(InvalidTransaction(format!(
+                "tx {} is a governance transaction, which {} does not accept",
+                tx.id,
+                self.name()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Add the undoing of [`Consensus::connect_block`] for the block at the height to the batch.
+    fn disconnect_block(&self, _batch: &mut Batch, _block: &Block, _height: u64) -> Result<()> {
+        Ok(())
+    }
+
+    /// Return the authority sets that blocks up to the height put in effect,
+    /// keyed by the height they take effect, for a UTXO snapshot at the height.
+    ///
+    /// Only proof of authority has any.
+    fn authority_sets(&self, _height: u64) -> Result<Vec<(u64, Vec<String>)>> {
+        Ok(vec![])
+    }
+
+    /// Add the authority sets of a UTXO snapshot at the height to the batch,
+    /// see [`Consensus::authority_sets`].
+    fn load_authority_sets(
+        &self,
+        _batch: &mut Batch,
+        sets: &[(u64, Vec<String>)],
+        _height: u64,
+    ) -> Result<()> {
+        if !sets.is_empty() {
+            return Err(InvalidSnapshot(format!(
+                "the snapshot has authority sets, which {} does not keep",
+                self.name()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Return the hash of a block that a validator signs, which commits to the
+/// previous hash, the transaction root, the timestamp and the validator's public key.
+pub(crate) fn signed_block_hash(header: &BlockHeader) -> Result<String> {
+    let mut data = vec![];
+    append_str(&mut data, &header.pre_hash);
+    append_str(&mut data, &header.tx_root);
+    append_str(&mut data, &format!("{:x}", header.timestamp));
+    append_str(&mut data, &header.validator);
+    Ok(hash_utf8(&data))
+}
+
+/// Set the validator of the block to the wallet, and sign its hash with the wallet key.
+pub(crate) fn sign_block(block: &mut Block, wallet: &Wallet) -> Result<()> {
+    block.validator = wallet.public_key();
+    block.hash = signed_block_hash(&block.header())?;
+    block.signature = sign_hash(&block.hash, wallet)?;
+    Ok(())
+}
+
+/// Whether the hash of the header commits to its fields, and its validator signed it.
+pub(crate) fn has_valid_signature(header: &BlockHeader) -> bool {
+    matches!(signed_block_hash(header), Ok(hash) if hash == header.hash)
+        && is_valid_signature(&header.validator, &header.hash, &header.signature)
+}
+
+/// Sign the hash with the wallet key, and return the signature in hex.
+pub(crate) fn sign_hash(hash: &str, wallet: &Wallet) -> Result<String> {
+    let secret_key = wallet
+        .private_key()?
+        .parse::<SecretKey>()
+        .map_err(|e| InvalidKey(e.to_string()))?;
+    let signing_key: SigningKey = secret_key.into();
+    let signature: Signature = signing_key.sign(hash.as_bytes());
+    Ok(hex_encode(&signature.to_bytes()))
+}
+
+/// Whether the hex signature of the hash is valid for the public key.
+pub(crate) fn is_valid_signature(public_key: &str, hash: &str, signature: &str) -> bool {
+    let Ok(public_key) = PublicKey::from_str(public_key) else {
+        return false;
+    };
+    let Some(signature) = hex_decode(signature)
+        .ok()
+        .and_then(|v| Signature::from_slice(&v).ok())
+    else {
+        return false;
+    };
+    let verifying_key: VerifyingKey = public_key.into();
+    verifying_key.verify(hash.as_bytes(), &signature).is_ok()
+}
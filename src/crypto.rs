@@ -0,0 +1,153 @@
+//! Passphrase-based encryption of private keys.
+//!
+//! A master key is derived from the passphrase with Argon2id, and every
+//! private key is sealed with ChaCha20-Poly1305 under that master key.
+
+use crate::common::{hex_decode, hex_encode};
+use crate::error::Error::{InvalidPassphrase, StringError};
+use crate::Result;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// The len of the salt fed to the KDF.
+const SALT_LEN: usize = 16;
+
+/// The plaintext sealed to check a passphrase.
+const CHECK_PLAINTEXT: &[u8] = b"rchain wallet";
+
+/// Data sealed with a master key.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Sealed {
+    nonce: String,
+    ciphertext: String,
+}
+
+/// The parameters to derive a master key from a passphrase.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct KeyDerivation {
+    salt: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+
+    /// A known plaintext sealed with the master key.
+    check: Sealed,
+}
+
+/// A key derived from a passphrase.
+#[derive(Clone)]
+pub(crate) struct MasterKey(Key);
+
+impl KeyDerivation {
+    /// New key derivation parameters with a random salt,
+    /// and return the master key of the passphrase.
+    pub(crate) fn new(passphrase: &str) -> Result<(Self, MasterKey)> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let params = Params::default();
+        let key = Self::kdf(
+            passphrase,
+            &salt,
+            params.m_cost(),
+            params.t_cost(),
+            params.p_cost(),
+        )?;
+        let check = key.seal(CHECK_PLAINTEXT, &[])?;
+        let derivation = KeyDerivation {
+            salt: hex_encode(&salt),
+            m_cost: params.m_cost(),
+            t_cost: params.t_cost(),
+            p_cost: params.p_cost(),
+            check,
+        };
+        Ok((derivation, key))
+    }
+
+    /// Derive the master key of the passphrase.
+    ///
+    /// Return `InvalidPassphrase` if the passphrase is wrong.
+    pub(crate) fn derive(&self, passphrase: &str) -> Result<MasterKey> {
+        let salt = hex_decode(&self.salt)?;
+        let key = Self::kdf(passphrase, &salt, self.m_cost, self.t_cost, self.p_cost)?;
+        match key.open(&self.check, &[]) {
+            Ok(v) if v == CHECK_PLAINTEXT => Ok(key),
+            _ => Err(InvalidPassphrase),
+        }
+    }
+
+    fn kdf(
+        passphrase: &str,
+        salt: &[u8],
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    ) -> Result<MasterKey> {
+        let params = Params::new(m_cost, t_cost, p_cost, Some(32))
+            .map_err(|e| StringError(e.to_string()))?;
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+        let mut key = Key::default();
+        argon2
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| StringError(e.to_string()))?;
+        Ok(MasterKey(key))
+    }
+}
+
+impl MasterKey {
+    /// Encrypt and authenticate the plaintext together with the associated data.
+    pub(crate) fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Result<Sealed> {
+        let cipher = ChaCha20Poly1305::new(&self.0);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|e| StringError(e.to_string()))?;
+        Ok(Sealed {
+            nonce: hex_encode(&nonce),
+            ciphertext: hex_encode(&ciphertext),
+        })
+    }
+
+    /// Decrypt the sealed data, it fails if the data or the associated data was modified.
+    pub(crate) fn open(&self, sealed: &Sealed, aad: &[u8]) -> Result<Vec<u8>> {
+        let cipher = ChaCha20Poly1305::new(&self.0);
+        let nonce: [u8; 12] = hex_decode(&sealed.nonce)?
+            .try_into()
+            .map_err(|_| StringError("invalid nonce".to_owned()))?;
+        let ciphertext = hex_decode(&sealed.ciphertext)?;
+        cipher
+            .decrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| InvalidPassphrase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let (derivation, key) = KeyDerivation::new("passphrase").unwrap();
+        let sealed = key.seal(b"secret", b"address").unwrap();
+        assert_eq!(key.open(&sealed, b"address").unwrap(), b"secret");
+        assert!(key.open(&sealed, b"another").is_err());
+
+        let key = derivation.derive("passphrase").unwrap();
+        assert_eq!(key.open(&sealed, b"address").unwrap(), b"secret");
+        assert!(matches!(derivation.derive("wrong"), Err(InvalidPassphrase)));
+    }
+}
@@ -1,9 +1,18 @@
+//! Storage engines.
+//!
+//! A [`StorageEngine`] is a key-value store of named trees. The chain and the
+//! wallets only talk to it through [`Tree`] handles and [`Batch`]es, so they run
+//! on sled on disk, or on [`MemoryEngine`] in tests and simulations.
+
+use crate::error::Error::StringError;
 use crate::Result;
-use std::borrow::ToOwned;
-use std::env::current_dir;
-use std::path::PathBuf;
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::Transactional;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
 use std::str::from_utf8;
-use std::string::ToString;
+use std::sync::{Arc, Mutex};
 
 /// The isolated keyspace that stores block data.
 pub const BLOCK_TREE: &str = "block_tree";
@@ -11,80 +20,535 @@ pub const BLOCK_TREE: &str = "block_tree";
 /// The key that stores the last block hash of the chain.
 pub const LAST_HASH_OF_CHAIN: &str = "l";
 
-/// The database that stores persistent blockchain
-#[derive(Debug, Clone)]
-pub struct SledEngine {
-    tree: sled::Tree,
+/// The isolated keyspace that maps a block height to the block hash.
+pub const HEIGHT_TREE: &str = "height_tree";
+
+/// The isolated keyspace that maps a block hash to the block height.
+pub const BLOCK_HEIGHT_TREE: &str = "block_height_tree";
+
+/// The isolated keyspace that maps a block hash to the block header, kept when the body is pruned.
+pub const HEADER_TREE: &str = "header_tree";
+
+/// The key that marks the headers as built, in the block tree.
+pub const HEADERS_INDEXED: &str = "headers_indexed";
+
+/// The isolated keyspace that maps a block hash to the outputs that its inputs spend.
+pub const UNDO_TREE: &str = "undo_tree";
+
+/// The key that stores the height of the last block whose body is pruned, in the block tree.
+pub const PRUNED_HEIGHT: &str = "pruned_height";
+
+/// The key that stores the height of the tip when blocks began to commit to
+/// their transactions, in the block tree.
+///
+/// The hashes of the blocks up to it were computed over their transactions,
+/// so their headers can not recompute them.
+pub const LEGACY_HEIGHT: &str = "legacy_height";
+
+/// The isolated keyspace that stores the transaction history of addresses.
+pub const HISTORY_TREE: &str = "history_tree";
+
+/// The key that marks the history index has been built.
+pub const HISTORY_INDEXED: &str = "history_indexed";
+
+/// The isolated keyspace of the unspent transaction outputs.
+pub const UTXO_TREE: &str = "utxo_tree";
+
+/// The key that marks the UTXO set has been built.
+pub const UTXO_INDEXED: &str = "utxo_indexed";
+
+/// The isolated keyspace that maps a transaction id to the hash of its block.
+pub const TX_TREE: &str = "tx_tree";
+
+/// The key that marks the transaction index has been built.
+pub const TX_INDEXED: &str = "tx_indexed";
+
+/// The isolated keyspace of the proof-of-authority sets, keyed by the height they take effect.
+pub const AUTHORITY_TREE: &str = "authority_tree";
+
+/// The key of the schema version of a database, see [`crate::migration`].
+///
+/// The chain stores it in [`BLOCK_TREE`], and the wallets in their meta tree.
+pub const SCHEMA_VERSION: &str = "schema_version";
+
+/// The key of the network that the chain runs on, stored in [`BLOCK_TREE`].
+///
+/// Chains created before networks existed have none and run on mainnet.
+pub const NETWORK: &str = "network";
+
+/// The key of the name of the consensus engine of the chain, stored in [`BLOCK_TREE`].
+///
+/// Chains created before consensus engines existed have none and run on proof of work.
+pub const CONSENSUS: &str = "consensus";
+
+/// The directory of the chain database in the directory of a network.
+pub const CHAIN_DIR: &str = "chain";
+
+/// The directory of the wallet database in the directory of a network.
+pub const WALLETS_DIR: &str = "wallets";
+
+/// A key-value store of named trees.
+pub trait StorageEngine: Send + Sync {
+    /// Open the tree with the name, creating it if it does not exist.
+    fn open_tree(&self, name: &str) -> Result<Tree>;
+
+    /// Apply all operations of the batch, or none of them.
+    fn apply_batch(&self, batch: &Batch) -> Result<()>;
+
+    /// Return the names of all trees.
+    fn tree_names(&self) -> Result<Vec<String>>;
+
+    /// Write the applied batches through to durable storage, if the engine buffers them.
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
-impl SledEngine {
-    /// New a default SledEngine in current directory.
-    pub fn new(tree_name: &str) -> Result<Self> {
-        let db = sled::open(current_dir().unwrap())?;
-        let tree = db.open_tree(tree_name)?;
-        Ok(SledEngine { tree })
+/// One named keyspace of a storage engine.
+pub trait StorageTree: Send + Sync {
+    /// Get the value of the key.
+    fn get(&self, key: &str) -> Result<Option<String>>;
+
+    /// Put a pair of key-value, and return the last value.
+    fn put(&self, key: &str, value: &str) -> Result<Option<String>>;
+
+    /// Delete a key, and return the last value.
+    fn delete(&self, key: &str) -> Result<Option<String>>;
+
+    /// Return the pairs whose key starts with the prefix, in key order.
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>>;
+}
+
+/// A handle to a tree of a storage engine, cheap to clone.
+#[derive(Clone)]
+pub struct Tree {
+    name: String,
+    inner: Arc<dyn StorageTree>,
+}
+
+impl fmt::Debug for Tree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tree").field("name", &self.name).finish()
     }
+}
 
-    /// New a SledEngine with sled db.
-    pub fn with_db(tree_name: &str, db: &sled::Db) -> Result<Self> {
-        let tree = db.open_tree(tree_name)?;
-        Ok(SledEngine { tree })
+impl Tree {
+    /// New a handle to a tree.
+    pub fn new(name: &str, inner: impl StorageTree + 'static) -> Self {
+        Tree {
+            name: name.to_owned(),
+            inner: Arc::new(inner),
+        }
     }
 
-    /// New a SledEngine with a path.
-    pub fn with_path(path: impl Into<PathBuf>, tree_name: &str) -> Result<Self> {
-        let path = path.into();
-        let db = sled::open(path)?;
-        let tree = db.open_tree(tree_name)?;
-        Ok(SledEngine { tree })
+    /// Return the name of the tree.
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
     /// Get the string value of the given key.
     ///
     /// Return `None' if the key does not exist.
     pub fn get(&self, key: impl Into<String>) -> Result<Option<String>> {
-        let key = key.into();
-        let val = self.tree.get(key)?;
-        match val {
-            Some(v) => {
-                let str = from_utf8(&v)?;
-                Ok(Some(str.to_string()))
-            }
-            None => Ok(None),
-        }
+        self.inner.get(&key.into())
     }
 
     /// Set a pair of key-value.
     ///
     /// Return the last value if it was set.
     pub fn set(&self, key: impl Into<String>, val: impl Into<String>) -> Result<Option<String>> {
-        let key = key.into();
-        let val = val.into();
-        let last_value = self.tree.insert(key, val.into_bytes())?;
-        match last_value {
-            Some(v) => {
-                let val_str = std::str::from_utf8(&v)?;
-                Ok(Some(val_str.to_owned()))
+        self.inner.put(&key.into(), &val.into())
+    }
+
+    /// Remove a key.
+    ///
+    /// Return the last value if it was set.
+    pub fn remove(&self, key: impl Into<String>) -> Result<Option<String>> {
+        self.inner.delete(&key.into())
+    }
+
+    /// List the pairs whose key starts with the prefix, in key order.
+    pub fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        self.inner.scan_prefix(prefix)
+    }
+
+    /// List all pairs, in key order.
+    pub fn list(&self) -> Result<Vec<(String, String)>> {
+        self.scan_prefix("")
+    }
+}
+
+/// Operations on the trees of an engine, applied atomically.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Batch {
+    /// `(tree, key, value)`, a `None` value deletes the key.
+    ops: Vec<(String, String, Option<String>)>,
+}
+
+impl Batch {
+    /// New an empty batch.
+    pub fn new() -> Self {
+        Batch::default()
+    }
+
+    /// Put a pair of key-value into the tree.
+    pub fn put(&mut self, tree: &Tree, key: impl Into<String>, value: impl Into<String>) {
+        self.ops
+            .push((tree.name.clone(), key.into(), Some(value.into())));
+    }
+
+    /// Delete a key from the tree.
+    pub fn delete(&mut self, tree: &Tree, key: impl Into<String>) {
+        self.ops.push((tree.name.clone(), key.into(), None));
+    }
+
+    /// Append the operations of another batch, applied after those of this one.
+    pub fn append(&mut self, other: Batch) {
+        self.ops.extend(other.ops);
+    }
+
+    /// Return whether the batch has no operations.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Return the names of the trees the batch writes, without duplicates.
+    fn tree_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = vec![];
+        for (name, _, _) in &self.ops {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
             }
-            None => Ok(None),
         }
+        names
     }
+}
 
-    /// List all wallets that have benn stored.
-    pub fn list(&self) -> Vec<(String, String)> {
-        let mut vec = vec![];
-        let iter = self.tree.iter();
-        for v in iter {
-            match v {
-                Ok((k, v)) => {
-                    let key = from_utf8(&k).unwrap();
-                    let val = from_utf8(&v).unwrap();
-                    vec.push((key.to_owned(), val.to_owned()));
+/// The storage engine on a sled database.
+#[derive(Clone, Debug)]
+pub struct SledEngine {
+    db: sled::Db,
+}
+
+impl SledEngine {
+    /// Open the sled database at the path.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::with_db(sled::open(path)?))
+    }
+
+    /// New an engine on an opened sled database.
+    pub fn with_db(db: sled::Db) -> Self {
+        SledEngine { db }
+    }
+}
+
+impl StorageEngine for SledEngine {
+    fn open_tree(&self, name: &str) -> Result<Tree> {
+        let tree = self.db.open_tree(name)?;
+        Ok(Tree::new(name, SledTree { tree }))
+    }
+
+    fn apply_batch(&self, batch: &Batch) -> Result<()> {
+        // A sled transaction needs at least one tree.
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let names = batch.tree_names();
+        let trees = names
+            .iter()
+            .map(|name| self.db.open_tree(name))
+            .collect::<std::result::Result<Vec<sled::Tree>, _>>()?;
+        trees[..]
+            .transaction(|views| {
+                for (name, key, value) in &batch.ops {
+                    let idx = names
+                        .iter()
+                        .position(|n| n == name)
+                        .expect("a tree of the batch");
+                    match value {
+                        Some(v) => views[idx].insert(key.as_bytes(), v.as_bytes())?,
+                        None => views[idx].remove(key.as_bytes())?,
+                    };
                 }
-                Err(_) => continue,
-            }
+                Ok::<(), ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e: TransactionError<()>| match e {
+                TransactionError::Storage(e) => e.into(),
+                TransactionError::Abort(_) => StringError("transaction aborted".to_owned()),
+            })
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        self.db.tree_names().into_iter().map(to_string).collect()
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+/// A tree of a sled database.
+struct SledTree {
+    tree: sled::Tree,
+}
+
+fn to_string(v: sled::IVec) -> Result<String> {
+    Ok(from_utf8(&v)?.to_owned())
+}
+
+impl StorageTree for SledTree {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.tree.get(key)?.map(to_string).transpose()
+    }
+
+    fn put(&self, key: &str, value: &str) -> Result<Option<String>> {
+        self.tree
+            .insert(key, value.as_bytes())?
+            .map(to_string)
+            .transpose()
+    }
+
+    fn delete(&self, key: &str) -> Result<Option<String>> {
+        self.tree.remove(key)?.map(to_string).transpose()
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        let mut vec = vec![];
+        for v in self.tree.scan_prefix(prefix) {
+            let (k, v) = v?;
+            vec.push((to_string(k)?, to_string(v)?));
+        }
+        Ok(vec)
+    }
+}
+
+type MemoryTrees = Arc<Mutex<HashMap<String, BTreeMap<String, String>>>>;
+
+/// A storage engine in memory, nothing is written to disk.
+///
+/// Clones share the same data, like handles to one database.
+#[derive(Clone, Default)]
+pub struct MemoryEngine {
+    trees: MemoryTrees,
+}
+
+impl MemoryEngine {
+    /// New an empty engine.
+    pub fn new() -> Self {
+        MemoryEngine::default()
+    }
+}
+
+impl StorageEngine for MemoryEngine {
+    fn open_tree(&self, name: &str) -> Result<Tree> {
+        lock(&self.trees).entry(name.to_owned()).or_default();
+        let tree = MemoryTree {
+            name: name.to_owned(),
+            trees: self.trees.clone(),
+        };
+        Ok(Tree::new(name, tree))
+    }
+
+    fn apply_batch(&self, batch: &Batch) -> Result<()> {
+        // One lock over all trees makes the batch atomic.
+        let mut trees = lock(&self.trees);
+        for (name, key, value) in &batch.ops {
+            let tree = trees.entry(name.clone()).or_default();
+            match value {
+                Some(v) => tree.insert(key.clone(), v.clone()),
+                None => tree.remove(key),
+            };
+        }
+        Ok(())
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        Ok(lock(&self.trees).keys().cloned().collect())
+    }
+}
+
+fn lock(
+    trees: &MemoryTrees,
+) -> std::sync::MutexGuard<'_, HashMap<String, BTreeMap<String, String>>> {
+    trees.lock().expect("lock memory trees")
+}
+
+/// A tree of a memory engine.
+struct MemoryTree {
+    name: String,
+    trees: MemoryTrees,
+}
+
+impl MemoryTree {
+    fn with<T>(&self, f: impl FnOnce(&mut BTreeMap<String, String>) -> T) -> T {
+        f(lock(&self.trees).entry(self.name.clone()).or_default())
+    }
+}
+
+impl StorageTree for MemoryTree {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.with(|tree| tree.get(key).cloned()))
+    }
+
+    fn put(&self, key: &str, value: &str) -> Result<Option<String>> {
+        Ok(self.with(|tree| tree.insert(key.to_owned(), value.to_owned())))
+    }
+
+    fn delete(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.with(|tree| tree.remove(key)))
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        Ok(self.with(|tree| {
+            tree.range(prefix.to_owned()..)
+                .take_while(|(k, _)| k.starts_with(prefix))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect()
+        }))
+    }
+}
+
+/// A storage engine that crashes after a number of writes, for crash-recovery tests.
+///
+/// Once it crashes every write fails, as if the writer was killed, and the
+/// inner engine keeps only what was written before.
+#[cfg(test)]
+#[derive(Clone)]
+pub(crate) struct FaultyEngine {
+    inner: Arc<dyn StorageEngine>,
+    writes_left: Arc<Mutex<usize>>,
+}
+
+#[cfg(test)]
+impl FaultyEngine {
+    /// New an engine that lets `writes` writes through to the inner engine.
+    pub(crate) fn new(inner: Arc<dyn StorageEngine>, writes: usize) -> Self {
+        FaultyEngine {
+            inner,
+            writes_left: Arc::new(Mutex::new(writes)),
+        }
+    }
+
+    fn write(&self) -> Result<()> {
+        let mut left = self.writes_left.lock().expect("lock writes left");
+        if *left == 0 {
+            return Err(StringError("crashed".to_owned()));
         }
+        *left -= 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl StorageEngine for FaultyEngine {
+    fn open_tree(&self, name: &str) -> Result<Tree> {
+        let tree = FaultyTree {
+            tree: self.inner.open_tree(name)?,
+            engine: self.clone(),
+        };
+        Ok(Tree::new(name, tree))
+    }
+
+    fn apply_batch(&self, batch: &Batch) -> Result<()> {
+        self.write()?;
+        self.inner.apply_batch(batch)
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        self.inner.tree_names()
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A tree of a faulty engine.
+#[cfg(test)]
+struct FaultyTree {
+    tree: Tree,
+    engine: FaultyEngine,
+}
+
+#[cfg(test)]
+impl StorageTree for FaultyTree {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.tree.get(key)
+    }
+
+    fn put(&self, key: &str, value: &str) -> Result<Option<String>> {
+        self.engine.write()?;
+        self.tree.set(key, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<Option<String>> {
+        self.engine.write()?;
+        self.tree.remove(key)
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        self.tree.scan_prefix(prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_engine(engine: &dyn StorageEngine) {
+        let tree = engine.open_tree("a").unwrap();
+        assert_eq!(tree.set("k1", "v1").unwrap(), None);
+        assert_eq!(tree.set("k1", "v2").unwrap(), Some("v1".to_owned()));
+        tree.set("k2", "v3").unwrap();
+        tree.set("j", "v4").unwrap();
+        assert_eq!(tree.get("k1").unwrap(), Some("v2".to_owned()));
+        assert_eq!(
+            tree.scan_prefix("k").unwrap(),
+            vec![
+                ("k1".to_owned(), "v2".to_owned()),
+                ("k2".to_owned(), "v3".to_owned())
+            ]
+        );
+        assert_eq!(tree.remove("k2").unwrap(), Some("v3".to_owned()));
+        assert_eq!(tree.list().unwrap().len(), 2);
+
+        // Trees with other names are isolated.
+        let other = engine.open_tree("b").unwrap();
+        assert_eq!(other.get("k1").unwrap(), None);
+        let names = engine.tree_names().unwrap();
+        assert!(names.contains(&"a".to_owned()) && names.contains(&"b".to_owned()));
+
+        let mut batch = Batch::new();
+        batch.put(&other, "k", "v");
+        batch.delete(&tree, "k1");
+        engine.apply_batch(&batch).unwrap();
+        assert_eq!(other.get("k").unwrap(), Some("v".to_owned()));
+        assert_eq!(tree.get("k1").unwrap(), None);
+        engine.apply_batch(&Batch::new()).unwrap();
+    }
+
+    #[test]
+    fn test_engines() {
+        let temp_dir = TempDir::new().unwrap();
+        test_engine(&SledEngine::open(temp_dir.path().join("a")).unwrap());
+        test_engine(&MemoryEngine::new());
+
+        // Copying an empty database applies an empty batch.
+        let empty = SledEngine::open(temp_dir.path().join("empty")).unwrap();
+        let copy = SledEngine::open(temp_dir.path().join("copy")).unwrap();
+        crate::migration::copy_engine(&empty, &copy).unwrap();
+    }
 
-        vec
+    #[test]
+    fn test_faulty_engine() {
+        let inner = MemoryEngine::new();
+        let engine = FaultyEngine::new(Arc::new(inner.clone()), 1);
+        let tree = engine.open_tree("a").unwrap();
+        tree.set("k1", "v1").unwrap();
+        assert!(tree.set("k2", "v2").is_err());
+        assert!(engine.apply_batch(&Batch::new()).is_err());
+        assert_eq!(inner.open_tree("a").unwrap().list().unwrap().len(), 1);
     }
 }
@@ -13,6 +13,14 @@ pub enum Error {
     #[error("sled error: {0}")]
     Sled(#[from] sled::Error),
 
+    /// IO error.
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// JSON serialization or deserialization error.
+    #[error("serde_json error: {0}")]
+    Json(#[from] serde_json::Error),
+
     /// From convert [u8] to str
     #[error("utf8 error: {0}")]
     Utf8(#[from] Utf8Error),
@@ -33,9 +41,73 @@ pub enum Error {
     #[error("base58 decode error: {0}")]
     Base58Decode(#[from] bs58::decode::Error),
 
+    /// The address can not be parsed, or belongs to another network.
+    #[error("invalid address {0}")]
+    InvalidAddress(String),
+
+    /// Error of parsing the config file.
+    #[error("config error: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    /// The private key can not be parsed or does not match.
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+
+    /// The address is watch-only, so there is no private key to sign with.
+    #[error("{0} is watch-only and has no private key to sign with")]
+    WatchOnly(String),
+
+    /// The wallet is encrypted and has not been unlocked.
+    #[error("wallet is locked, unlock it with the passphrase first")]
+    WalletLocked,
+
+    /// The passphrase does not match the encrypted wallets.
+    #[error("invalid passphrase")]
+    InvalidPassphrase,
+
     /// Invalid transaction.
     #[error("invalid transaction: {0}")]
     InvalidTransaction(String),
+
+    /// A stored block can not be read.
+    #[error("corrupt block {0}")]
+    CorruptBlock(String),
+
+    /// The block is not in the chain.
+    #[error("no such block {0}")]
+    BlockNotFound(String),
+
+    /// The body of the block was deleted in pruned mode.
+    #[error("block {0} is pruned, only its header is kept")]
+    BlockPruned(String),
+
+    /// The transaction is not in the chain.
+    #[error("no such transaction {0}")]
+    TxNotFound(String),
+
+    /// There is no wallet for the address.
+    #[error("no wallet for {0}")]
+    WalletNotFound(String),
+
+    /// The block breaks a consensus rule.
+    #[error("invalid block {0}")]
+    InvalidBlock(String),
+
+    /// A chain archive can not be read.
+    #[error("invalid archive: {0}")]
+    InvalidArchive(String),
+
+    /// A UTXO set snapshot can not be read or does not fit the chain.
+    #[error("invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+
+    /// The database was written by a newer binary, with a schema this one does not know.
+    #[error("the {0} database has schema version {1}, but this binary supports up to {2}, upgrade rchain")]
+    UnsupportedSchema(String, u64, u64),
+
+    /// There is no chain to open.
+    #[error("no chain found, create one with create-blockchain")]
+    ChainNotFound,
 }
 
 /// Alias for a Result with the error type Error.
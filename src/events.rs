@@ -0,0 +1,175 @@
+//! Events of chain changes.
+//!
+//! Subscribers get an in-process channel, the event server streams
+//! the same events as newline-delimited JSON over TCP.
+
+use crate::Result;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A change of the chain.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChainEvent {
+    /// A block was connected to the tip.
+    BlockConnected {
+        /// The hash of the block.
+        hash: String,
+        /// The height of the block.
+        height: u64,
+    },
+
+    /// The tip block was disconnected.
+    BlockDisconnected {
+        /// The hash of the block.
+        hash: String,
+        /// The height the block had.
+        height: u64,
+    },
+
+    /// A transaction was verified and is waiting to be mined.
+    TxEnteredMempool {
+        /// The id of the transaction.
+        tx_id: String,
+    },
+
+    /// A transaction was included in a connected block.
+    TxConfirmed {
+        /// The id of the transaction.
+        tx_id: String,
+        /// The hash of the block.
+        block_hash: String,
+        /// The height of the block.
+        height: u64,
+    },
+}
+
+/// Publish-subscribe hub of chain events.
+///
+/// Clones share the same subscribers.
+#[derive(Clone, Default)]
+pub struct ChainEvents {
+    subscribers: Arc<Mutex<Vec<Sender<ChainEvent>>>>,
+}
+
+impl ChainEvents {
+    /// New a hub without subscribers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to all events published from now on.
+    ///
+    /// Dropping the receiver unsubscribes.
+    pub fn subscribe(&self) -> Receiver<ChainEvent> {
+        let (tx, rx) = channel();
+        self.subscribers.lock().expect("lock subscribers").push(tx);
+        rx
+    }
+
+    /// Send an event to every subscriber.
+    pub fn publish(&self, event: ChainEvent) {
+        let mut subscribers = self.subscribers.lock().expect("lock subscribers");
+        subscribers.retain(|s| s.send(event.clone()).is_ok());
+    }
+}
+
+/// A TCP server that streams chain events as newline-delimited JSON.
+///
+/// Every connection gets its own subscription and one JSON object per line.
+pub struct EventServer {
+    listener: TcpListener,
+    events: ChainEvents,
+}
+
+impl EventServer {
+    /// Bind an event server to the address.
+    pub fn bind(events: ChainEvents, addr: &str) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(EventServer { listener, events })
+    }
+
+    /// Return the address the server is listening on.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept connections until the listener fails.
+    pub fn run(&self) {
+        info!("Event server listening on {:?}", self.listener.local_addr());
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let events = self.events.subscribe();
+                    thread::spawn(move || {
+                        if let Err(e) = Self::stream_events(stream, events) {
+                            info!("event stream closed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("failed to accept: {}", e),
+            }
+        }
+    }
+
+    fn stream_events(mut stream: TcpStream, events: Receiver<ChainEvent>) -> Result<()> {
+        for event in events {
+            let mut line = serde_json::to_vec(&event)?;
+            line.push(b'\n');
+            stream.write_all(&line)?;
+            stream.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn test_subscribe() {
+        let events = ChainEvents::new();
+        let rx = events.subscribe();
+        let dropped = events.subscribe();
+        drop(dropped);
+
+        let event = ChainEvent::TxEnteredMempool {
+            tx_id: "id".to_owned(),
+        };
+        events.publish(event.clone());
+        assert_eq!(rx.recv().unwrap(), event);
+        assert_eq!(events.subscribers.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_event_server() {
+        let events = ChainEvents::new();
+        let server = EventServer::bind(events.clone(), "127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let stream = TcpStream::connect(addr).unwrap();
+        // Wait until the connection is subscribed.
+        while events.subscribers.lock().unwrap().is_empty() {
+            thread::yield_now();
+        }
+        events.publish(ChainEvent::BlockConnected {
+            hash: "hash".to_owned(),
+            height: 1,
+        });
+
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        assert_eq!(
+            line,
+            "{\"type\":\"block_connected\",\"hash\":\"hash\",\"height\":1}\n"
+        );
+    }
+}
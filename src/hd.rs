@@ -0,0 +1,171 @@
+//! Hierarchical deterministic keys.
+//!
+//! Keys are derived from a BIP39 mnemonic as in BIP32, using the SLIP-10
+//! variant for the P-256 curve.
+
+use crate::error::Error::StringError;
+use crate::Result;
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use p256::elliptic_curve::ff::PrimeField;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{FieldBytes, NonZeroScalar, Scalar, SecretKey};
+use rand_core::{OsRng, RngCore};
+use sha2::Sha512;
+
+/// The HMAC key used to derive the master key, as defined by SLIP-10.
+const MASTER_HMAC_KEY: &[u8] = b"Nist256p1 seed";
+
+/// Indexes at or above this one derive hardened children.
+pub const HARDENED: u32 = 1 << 31;
+
+/// The entropy len of a generated mnemonic, 16 bytes give 12 words.
+const MNEMONIC_ENTROPY_LEN: usize = 16;
+
+/// Generate a random 12-word mnemonic.
+pub fn generate_mnemonic() -> Result<String> {
+    let mut entropy = [0u8; MNEMONIC_ENTROPY_LEN];
+    OsRng.fill_bytes(&mut entropy);
+    let mnemonic = Mnemonic::from_entropy(&entropy).map_err(|e| StringError(e.to_string()))?;
+    Ok(mnemonic.to_string())
+}
+
+/// Check the mnemonic and turn it into a 64-byte seed.
+pub fn mnemonic_to_seed(mnemonic: &str, passphrase: &str) -> Result<Vec<u8>> {
+    let mnemonic = Mnemonic::parse(mnemonic).map_err(|e| StringError(e.to_string()))?;
+    Ok(mnemonic.to_seed(passphrase).to_vec())
+}
+
+/// A private key together with its chain code.
+#[derive(Clone, Debug)]
+pub struct ExtendedKey {
+    secret_key: SecretKey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    /// Derive the master key from a seed.
+    pub fn master(seed: &[u8]) -> Result<Self> {
+        let mut data = seed.to_vec();
+        loop {
+            let (left, right) = hmac_sha512(MASTER_HMAC_KEY, &data)?;
+            if let Some(scalar) = Option::<NonZeroScalar>::from(NonZeroScalar::from_repr(left)) {
+                return Ok(ExtendedKey {
+                    secret_key: SecretKey::from(scalar),
+                    chain_code: right,
+                });
+            }
+            // The key is invalid, derive again from the whole output.
+            data = [&left[..], &right].concat();
+        }
+    }
+
+    /// Derive the child key at the index, indexes from [`HARDENED`] are hardened.
+    pub fn derive_child(&self, index: u32) -> Result<Self> {
+        let mut data = if index >= HARDENED {
+            [&[0u8][..], &self.secret_key.to_bytes()].concat()
+        } else {
+            let public_key = self.secret_key.public_key().to_encoded_point(true);
+            public_key.as_bytes().to_vec()
+        };
+        data.extend(index.to_be_bytes());
+        let parent = self.secret_key.to_nonzero_scalar();
+        loop {
+            let (left, right) = hmac_sha512(&self.chain_code, &data)?;
+            let tweak = Option::<Scalar>::from(Scalar::from_repr(left));
+            if let Some(tweak) = tweak {
+                let child = tweak + parent.as_ref();
+                if let Some(child) = Option::<NonZeroScalar>::from(NonZeroScalar::new(child)) {
+                    return Ok(ExtendedKey {
+                        secret_key: SecretKey::from(child),
+                        chain_code: right,
+                    });
+                }
+            }
+            // The key is invalid, derive again from the chain code output.
+            data = [&[1u8][..], &right].concat();
+            data.extend(index.to_be_bytes());
+        }
+    }
+
+    /// Derive the key along a path of indexes.
+    pub fn derive_path(&self, path: &[u32]) -> Result<Self> {
+        let mut key = self.clone();
+        for index in path {
+            key = key.derive_child(*index)?;
+        }
+        Ok(key)
+    }
+
+    /// Return the private key.
+    pub fn secret_key(&self) -> &SecretKey {
+        &self.secret_key
+    }
+
+    /// Return the chain code.
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> Result<(FieldBytes, [u8; 32])> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).map_err(|e| StringError(e.to_string()))?;
+    mac.update(data);
+    let output = mac.finalize().into_bytes();
+    let mut left = FieldBytes::default();
+    left.copy_from_slice(&output[..32]);
+    let mut right = [0u8; 32];
+    right.copy_from_slice(&output[32..]);
+    Ok((left, right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::hex_encode;
+
+    fn hex_decode(hex: &str) -> Vec<u8> {
+        crate::common::hex_decode(hex).unwrap()
+    }
+
+    /// Test vector 1 for nist256p1 from SLIP-10.
+    #[test]
+    fn test_slip10_vector() {
+        let seed = hex_decode("000102030405060708090a0b0c0d0e0f");
+        let master = ExtendedKey::master(&seed).unwrap();
+        assert_eq!(
+            hex_encode(&master.secret_key().to_bytes()),
+            "612091aaa12e22dd2abef664f8a01a82cae99ad7441b7ef8110424915c268bc2"
+        );
+        assert_eq!(
+            hex_encode(master.chain_code()),
+            "beeb672fe4621673f722f38529c07392fecaa61015c80c34f29ce8b41b3cb6ea"
+        );
+
+        let child = master.derive_path(&[HARDENED]).unwrap();
+        assert_eq!(
+            hex_encode(&child.secret_key().to_bytes()),
+            "6939694369114c67917a182c59ddb8cafc3004e63ca5d3b84403ba8613debc0c"
+        );
+
+        let child = child.derive_child(1).unwrap();
+        assert_eq!(
+            hex_encode(&child.secret_key().to_bytes()),
+            "284e9d38d07d21e4e281b645089a94f4cf5a5a81369acf151a1c3a57f18b2129"
+        );
+        assert_eq!(
+            hex_encode(child.chain_code()),
+            "4187afff1aafa8445010097fb99d23aee9f599450c7bd140b6826ac22ba21d0c"
+        );
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        let mnemonic = generate_mnemonic().unwrap();
+        assert_eq!(mnemonic.split_whitespace().count(), 12);
+        let seed = mnemonic_to_seed(&mnemonic, "").unwrap();
+        assert_eq!(seed, mnemonic_to_seed(&mnemonic, "").unwrap());
+        assert_ne!(seed, mnemonic_to_seed(&mnemonic, "passphrase").unwrap());
+        assert!(mnemonic_to_seed("not a mnemonic", "").is_err());
+    }
+}
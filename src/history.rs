@@ -0,0 +1,150 @@
+//! Transaction history of addresses.
+
+use crate::common::hex_encode;
+use crate::engine::{Batch, Tree};
+use crate::transaction::TXInput;
+use crate::wallet::Wallet;
+use crate::{Block, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The direction of coins in a history entry.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    /// Coins paid to the address, including change.
+    Received,
+
+    /// Coins spent from the address.
+    Sent,
+}
+
+/// One entry in the transaction history of an address.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    /// The id of the transaction.
+    pub tx_id: String,
+
+    /// The height of the block that contains the transaction.
+    pub height: u64,
+
+    /// Whether the coins were received or sent.
+    pub direction: Direction,
+
+    /// The number of coins received or sent.
+    pub amount: i64,
+}
+
+/// Index from a public key hash to its history entries.
+///
+/// Keys are `pub_key_hash/height/tx_id/direction` so that a prefix scan
+/// returns the entries of an address in height order.
+#[derive(Clone)]
+pub(crate) struct HistoryIndex {
+    tree: Tree,
+}
+
+impl HistoryIndex {
+    pub(crate) fn new(tree: Tree) -> Self {
+        HistoryIndex { tree }
+    }
+
+    /// Add the entries of a block to the batch.
+    ///
+    /// `spent_value` returns the value of the output that an input spends.
+    pub(crate) fn connect_block(
+        &self,
+        batch: &mut Batch,
+        block: &Block,
+        height: u64,
+        spent_value: impl Fn(&TXInput) -> Result<i64>,
+    ) -> Result<()> {
+        for (pub_key_hash, entry) in Self::entries(block, height, spent_value)? {
+            batch.put(
+                &self.tree,
+                Self::key(&pub_key_hash, &entry),
+                ron::to_string(&entry)?,
+            );
+        }
+        Ok(())
+    }
+
+    /// Remove the entries of a block in the batch.
+    pub(crate) fn disconnect_block(
+        &self,
+        batch: &mut Batch,
+        block: &Block,
+        height: u64,
+    ) -> Result<()> {
+        // The amount is not part of the key.
+        for (pub_key_hash, entry) in Self::entries(block, height, |_| Ok(0))? {
+            batch.delete(&self.tree, Self::key(&pub_key_hash, &entry));
+        }
+        Ok(())
+    }
+
+    /// Return at most `limit` entries of the public key hash, newest first,
+    /// skipping the first `from` entries.
+    pub(crate) fn history(
+        &self,
+        pub_key_hash: &[u8],
+        from: usize,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>> {
+        let prefix = format!("{}/", hex_encode(pub_key_hash));
+        let mut entries = vec![];
+        for (_, v) in self
+            .tree
+            .scan_prefix(&prefix)?
+            .into_iter()
+            .rev()
+            .skip(from)
+            .take(limit)
+        {
+            let entry: HistoryEntry = ron::from_str(&v).map_err(|e| e.code)?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    fn key(pub_key_hash: &[u8], entry: &HistoryEntry) -> String {
+        format!(
+            "{}/{:020}/{}/{:?}",
+            hex_encode(pub_key_hash),
+            entry.height,
+            entry.tx_id,
+            entry.direction
+        )
+    }
+
+    /// Sum the coins received and sent by every address in each transaction of the block.
+    fn entries(
+        block: &Block,
+        height: u64,
+        spent_value: impl Fn(&TXInput) -> Result<i64>,
+    ) -> Result<Vec<(Vec<u8>, HistoryEntry)>> {
+        let mut entries = vec![];
+        for tx in &block.transactions {
+            let mut amounts: BTreeMap<(Vec<u8>, Direction), i64> = BTreeMap::new();
+            for output in &tx.vout {
+                let key = (output.pub_key_hash().to_vec(), Direction::Received);
+                *amounts.entry(key).or_insert(0) += output.value;
+            }
+            if !tx.is_coinbase() {
+                for input in &tx.vin {
+                    let key = (Wallet::hash_pub_key(&input.public_key), Direction::Sent);
+                    *amounts.entry(key).or_insert(0) += spent_value(input)?;
+                }
+            }
+            for ((pub_key_hash, direction), amount) in amounts {
+                let entry = HistoryEntry {
+                    tx_id: tx.id.clone(),
+                    height,
+                    direction,
+                    amount,
+                };
+                entries.push((pub_key_hash, entry));
+            }
+        }
+        Ok(entries)
+    }
+}
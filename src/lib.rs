@@ -2,17 +2,40 @@
 
 //! A blockchain building in Rust
 
-pub use block::Block;
+pub use address::Address;
+pub use block::{Block, BlockHeader};
 pub use blockchain::Blockchain;
-pub use error::Result;
-pub use proof_of_work::ProofOfWork;
-pub use transaction::Transaction;
+pub use consensus::Consensus;
+pub use error::{Error, Result};
+pub use history::{Direction, HistoryEntry};
+pub use params::{ChainParams, Checkpoint, ConsensusParams, Genesis, Network, Validator};
+pub use proof_of_authority::PoaConsensus;
+pub use proof_of_stake::PosConsensus;
+pub use proof_of_work::{PowConsensus, PowHash, ProofOfWork};
+pub use transaction::{Approval, AuthorityChange, Governance, TXInput, TXOutput, Transaction};
 
+mod address;
+pub mod archive;
 mod block;
 mod blockchain;
 mod common;
-mod engine;
+pub mod config;
+mod consensus;
+mod crypto;
+pub mod engine;
 mod error;
+pub mod events;
+pub mod hd;
+mod history;
+pub mod migration;
+mod params;
+mod proof_of_authority;
+mod proof_of_stake;
 mod proof_of_work;
+pub mod rest;
+pub mod snapshot;
+pub mod stratum;
 mod transaction;
+mod utxo;
+pub mod view;
 pub mod wallet;
@@ -0,0 +1,163 @@
+//! Schema versions and migrations of the chain and wallet databases.
+//!
+//! Each database stores its schema version under [`SCHEMA_VERSION`], and
+//! databases written before versioning have none and are at version 0.
+//! Opening a database runs the migrations above its version in order, and a
+//! database newer than the binary is refused, since its records may not
+//! deserialize.
+//!
+//! Migrations upgrade a database in place. To keep the original, copy it with
+//! [`copy_engine`] and open the copy instead.
+
+use crate::engine::{Batch, StorageEngine, Tree, SCHEMA_VERSION};
+use crate::error::Error::{StringError, UnsupportedSchema};
+use crate::Result;
+use log::info;
+
+/// One upgrade of a database to the next schema version.
+pub struct Migration<'a> {
+    to: u64,
+    description: &'static str,
+    run: Box<dyn Fn() -> Result<Batch> + 'a>,
+}
+
+impl<'a> Migration<'a> {
+    /// New a migration to version `to`.
+    ///
+    /// `run` returns the writes that finish the migration, which are applied
+    /// in one batch with the new version. Writes it applies itself must be
+    /// safe to repeat, since an interrupted migration runs again.
+    pub fn new(to: u64, description: &'static str, run: impl Fn() -> Result<Batch> + 'a) -> Self {
+        Migration {
+            to,
+            description,
+            run: Box::new(run),
+        }
+    }
+}
+
+/// Return the schema version stored in the tree, 0 if there is none.
+pub fn version(meta: &Tree) -> Result<u64> {
+    match meta.get(SCHEMA_VERSION)? {
+        Some(v) => v
+            .parse()
+            .map_err(|_| StringError(format!("invalid schema version {}", v))),
+        None => Ok(0),
+    }
+}
+
+/// Return the schema version that the migrations lead to.
+pub fn latest(migrations: &[Migration]) -> u64 {
+    migrations.last().map_or(0, |m| m.to)
+}
+
+/// Upgrade the database whose version is in `meta` with the migrations above
+/// its version, and return the version it had.
+///
+/// `name` names the database in logs and errors. Return [`UnsupportedSchema`]
+/// if the database is newer than the last migration.
+pub fn migrate(
+    store: &dyn StorageEngine,
+    meta: &Tree,
+    name: &str,
+    migrations: &[Migration],
+) -> Result<u64> {
+    let from = version(meta)?;
+    let latest = latest(migrations);
+    if from > latest {
+        return Err(UnsupportedSchema(name.to_owned(), from, latest));
+    }
+    for migration in migrations.iter().filter(|m| m.to > from) {
+        info!(
+            "Migrating the {} database to version {}: {}...",
+            name, migration.to, migration.description
+        );
+        let mut batch = (migration.run)()?;
+        batch.put(meta, SCHEMA_VERSION, migration.to.to_string());
+        store.apply_batch(&batch)?;
+    }
+    Ok(from)
+}
+
+/// Return a batch that rewrites every record of the tree.
+///
+/// `rewrite` returns the new value of a record, or `None` to delete it.
+/// Migrations use it to change the layout of stored records.
+pub fn rewrite_tree(
+    tree: &Tree,
+    rewrite: impl Fn(&str, &str) -> Result<Option<String>>,
+) -> Result<Batch> {
+    let mut batch = Batch::new();
+    for (k, v) in tree.list()? {
+        match rewrite(&k, &v)? {
+            Some(new) if new != v => batch.put(tree, k, new),
+            Some(_) => {}
+            None => batch.delete(tree, k),
+        }
+    }
+    Ok(batch)
+}
+
+/// Copy every tree of one engine into another, in one batch.
+///
+/// Migrating the copy leaves the original as it was.
+pub fn copy_engine(from: &dyn StorageEngine, to: &dyn StorageEngine) -> Result<()> {
+    let mut batch = Batch::new();
+    for name in from.tree_names()? {
+        let source = from.open_tree(&name)?;
+        let target = to.open_tree(&name)?;
+        for (k, v) in source.list()? {
+            batch.put(&target, k, v);
+        }
+    }
+    to.apply_batch(&batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::MemoryEngine;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_migrate() {
+        let store = MemoryEngine::new();
+        let meta = store.open_tree("meta").unwrap();
+        let records = store.open_tree("records").unwrap();
+        records.set("a", "1").unwrap();
+        records.set("b", "old").unwrap();
+        let runs = Cell::new(0);
+        let migrations = [
+            Migration::new(1, "count", || {
+                runs.set(runs.get() + 1);
+                Ok(Batch::new())
+            }),
+            Migration::new(2, "double numbers, drop the rest", || {
+                rewrite_tree(&records, |_, v| {
+                    Ok(v.parse::<u64>().ok().map(|n| (n * 2).to_string()))
+                })
+            }),
+        ];
+        assert_eq!(latest(&migrations), 2);
+
+        assert_eq!(migrate(&store, &meta, "test", &migrations).unwrap(), 0);
+        assert_eq!(version(&meta).unwrap(), 2);
+        assert_eq!(
+            records.list().unwrap(),
+            vec![("a".to_owned(), "2".to_owned())]
+        );
+        assert_eq!(migrate(&store, &meta, "test", &migrations).unwrap(), 2);
+        assert_eq!(runs.get(), 1);
+        assert_eq!(records.get("a").unwrap(), Some("2".to_owned()));
+
+        let copy = MemoryEngine::new();
+        copy_engine(&store, &copy).unwrap();
+        assert_eq!(version(&copy.open_tree("meta").unwrap()).unwrap(), 2);
+
+        meta.set(SCHEMA_VERSION, "3").unwrap();
+        assert!(matches!(
+            migrate(&store, &meta, "test", &migrations),
+            Err(UnsupportedSchema(_, 3, 2))
+        ));
+    }
+}
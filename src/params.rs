@@ -0,0 +1,473 @@
+//! Parameters of the networks a chain can run on.
+//!
+//! Everything that differs between mainnet, testnet and regtest lives in
+//! [`ChainParams`], so the rest of the crate never hardcodes a network.
+
+use crate::consensus::Consensus;
+use crate::error::Error::StringError;
+use crate::wallet::Wallets;
+use crate::{
+    Address, Block, PoaConsensus, PosConsensus, PowConsensus, PowHash, Result, Transaction,
+};
+use num::BigInt;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// A network that a chain runs on.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    /// The main network.
+    #[default]
+    Mainnet,
+
+    /// The public test network.
+    Testnet,
+
+    /// A local network for regression tests.
+    Regtest,
+}
+
+impl Network {
+    /// Return the parameters of the network.
+    pub fn params(self) -> ChainParams {
+        match self {
+            Network::Mainnet => ChainParams::mainnet(),
+            Network::Testnet => ChainParams::testnet(),
+            Network::Regtest => ChainParams::regtest(),
+        }
+    }
+
+    /// Return the version byte of addresses on the network.
+    pub fn address_version(self) -> u8 {
+        self.params().address_version
+    }
+
+    /// Return the coin type of HD wallet paths on the network.
+    pub fn hd_coin_type(self) -> u32 {
+        self.params().hd_coin_type
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Network {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(StringError(format!("unknown network {}", s))),
+        }
+    }
+}
+
+/// The consensus engine of a network, proof of work by default.
+///
+/// In the config file:
+///
+/// ```toml
+/// [consensus]
+/// engine = "proof-of-stake"
+/// validators = [{ address = "...", stake = 60 }, { address = "...", stake = 40 }]
+/// ```
+///
+/// or
+///
+/// ```toml
+/// [consensus]
+/// engine = "proof-of-authority"
+/// authorities = ["...", "..."]
+/// ```
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag = "engine", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum ConsensusParams {
+    /// Blocks are mined below the target, see [`crate::PowConsensus`].
+    #[default]
+    ProofOfWork,
+
+    /// Validators sign blocks in turns weighted by stake, see [`crate::PosConsensus`].
+    ProofOfStake {
+        /// The validators, at least one.
+        validators: Vec<Validator>,
+    },
+
+    /// Authorities sign blocks in strict turns, see [`crate::PoaConsensus`].
+    ProofOfAuthority {
+        /// The addresses of the authorities of the genesis block, at least one.
+        authorities: Vec<String>,
+    },
+}
+
+/// A validator of a proof-of-stake network.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Validator {
+    /// The address of the wallet that signs the blocks of the validator.
+    pub address: String,
+
+    /// The stake, which weighs how often the validator proposes blocks.
+    pub stake: u64,
+}
+
+/// A block that every chain of a network must have, see [`ChainParams::checkpoints`].
+///
+/// In the config file:
+///
+/// ```toml
+/// checkpoints = [{ height = 1000, hash = "..." }]
+/// ```
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Checkpoint {
+    /// The height of the block.
+    pub height: u64,
+
+    /// The hash of the block.
+    pub hash: String,
+}
+
+/// The fixed genesis block of a network, see [`ChainParams::fixed_genesis_block`].
+///
+/// Every node of the network builds the same block from it, instead of one
+/// that rewards its own address at its own clock.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Genesis {
+    /// The timestamp of the block.
+    pub timestamp: u64,
+
+    /// The address that the coinbase rewards, whose public key hash is all zeros
+    /// so nobody holds its key.
+    pub address: &'static str,
+
+    /// The nonce that meets the proof-of-work target.
+    pub nonce: u64,
+
+    /// The hash of the block, which every stored chain of the network must start with.
+    pub hash: &'static str,
+}
+
+/// The parameters of a network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainParams {
+    /// The network.
+    pub network: Network,
+
+    /// The arbitrary data in the coinbase of the genesis block.
+    pub genesis_coinbase_data: &'static str,
+
+    /// The fixed genesis block, `None` for a chain whose creator mints it,
+    /// like one whose config changes the consensus engine or the hash function.
+    pub genesis: Option<Genesis>,
+
+    /// The number of leading zero bits that a block hash needs.
+    ///
+    /// There is no target adjusting algorithm, the difficulty is fixed.
+    pub target_bits: u32,
+
+    /// The hash function of proof of work, SHA-256 by default.
+    pub pow_hash: PowHash,
+
+    /// The coins a coinbase transaction creates at height 0.
+    pub subsidy: i64,
+
+    /// The subsidy halves every `halving_interval` blocks.
+    pub halving_interval: u64,
+
+    /// The version byte of addresses.
+    pub address_version: u8,
+
+    /// The coin type of HD wallet paths, `m/44'/coin_type'/0'`.
+    ///
+    /// The test networks share 1', the SLIP-44 value for testnets,
+    /// so mainnet seeds never derive test network keys.
+    pub hd_coin_type: u32,
+
+    /// The default port of the REST API.
+    pub rest_port: u16,
+
+    /// The default port of the event stream.
+    pub event_port: u16,
+
+    /// The default port of the Stratum mining server.
+    pub stratum_port: u16,
+
+    /// Whether blocks can be generated on demand, without any transaction.
+    pub generate_on_demand: bool,
+
+    /// The consensus engine.
+    pub consensus: ConsensusParams,
+
+    /// Blocks that every chain must have, a branch that conflicts with one is rejected.
+    ///
+    /// The built-in networks have none, their fixed genesis block is checked
+    /// on its own, see [`ChainParams::genesis`].
+    pub checkpoints: Vec<Checkpoint>,
+
+    /// The hash of a block whose ancestors skip signature checks on import,
+    /// see [`crate::Blockchain::import_with_engine`].
+    pub assume_valid: Option<String>,
+}
+
+impl ChainParams {
+    /// The parameters of the main network.
+    pub fn mainnet() -> Self {
+        ChainParams {
+            network: Network::Mainnet,
+            genesis_coinbase_data:
+                "The Times 03/Jan/2009 Chancellor on brink of second bailout for bank",
+            genesis: Some(Genesis {
+                timestamp: 1_767_225_600,
+                address: "1111111111111111111114oLvT2",
+                nonce: 522,
+                hash: "0073a8d75b785746241e1b8fae2a0babc9616835bd08327b68b316d00759f607",
+            }),
+            target_bits: 8,
+            pow_hash: PowHash::Sha256,
+            subsidy: 10,
+            halving_interval: 210_000,
+            address_version: 0x00,
+            hd_coin_type: 7077,
+            rest_port: 3030,
+            event_port: 3031,
+            stratum_port: 3032,
+            generate_on_demand: false,
+            consensus: ConsensusParams::ProofOfWork,
+            checkpoints: vec![],
+            assume_valid: None,
+        }
+    }
+
+    /// The parameters of the test network.
+    pub fn testnet() -> Self {
+        ChainParams {
+            network: Network::Testnet,
+            genesis_coinbase_data: "rchain testnet genesis",
+            genesis: Some(Genesis {
+                timestamp: 1_767_225_601,
+                address: "mfWxJ45yp2SFn7UciZyNpvDKrzbhyfKrY8",
+                nonce: 255,
+                hash: "007cba99f975e2a5b32d38263e65d5c495512f59572a1bcdc7c308bd7c18eee7",
+            }),
+            target_bits: 8,
+            pow_hash: PowHash::Sha256,
+            subsidy: 10,
+            halving_interval: 210_000,
+            address_version: 0x6f,
+            hd_coin_type: 1,
+            rest_port: 13030,
+            event_port: 13031,
+            stratum_port: 13032,
+            generate_on_demand: false,
+            consensus: ConsensusParams::ProofOfWork,
+            checkpoints: vec![],
+            assume_valid: None,
+        }
+    }
+
+    /// The parameters of regtest, where any hash meets the target
+    /// and blocks are generated on demand.
+    pub fn regtest() -> Self {
+        ChainParams {
+            network: Network::Regtest,
+            genesis_coinbase_data: "rchain regtest genesis",
+            genesis: Some(Genesis {
+                timestamp: 1_767_225_602,
+                address: "R9HC5WtHbpoa51NCUAz86XLCmGTbkf45NT",
+                nonce: 0,
+                hash: "10496446f18a69c75b96e85519d26f5a33f6c59992f937e8ac0047946e2417f9",
+            }),
+            target_bits: 0,
+            pow_hash: PowHash::Sha256,
+            subsidy: 10,
+            halving_interval: 150,
+            address_version: 0x3c,
+            hd_coin_type: 1,
+            rest_port: 23030,
+            event_port: 23031,
+            stratum_port: 23032,
+            generate_on_demand: true,
+            consensus: ConsensusParams::ProofOfWork,
+            checkpoints: vec![],
+            assume_valid: None,
+        }
+    }
+
+    /// Return the subsidy of a block at the height.
+    pub fn block_subsidy(&self, height: u64) -> i64 {
+        let halvings = height / self.halving_interval;
+        if halvings >= i64::BITS as u64 {
+            return 0;
+        }
+        self.subsidy >> halvings
+    }
+
+    /// Return the expected number of hashes to mine a block, `2^target_bits`.
+    pub fn block_work(&self) -> BigInt {
+        BigInt::from(1) << self.target_bits
+    }
+
+    /// Return whether the block at the height matches every checkpoint at that height.
+    pub fn matches_checkpoints(&self, height: u64, hash: &str) -> bool {
+        self.checkpoints
+            .iter()
+            .all(|c| c.height != height || c.hash == hash)
+    }
+
+    /// Return the consensus engine of the network.
+    pub fn consensus(&self) -> Result<Box<dyn Consensus>> {
+        Ok(match &self.consensus {
+            ConsensusParams::ProofOfWork => Box::new(PowConsensus::new(self)),
+            ConsensusParams::ProofOfStake { validators } => {
+                Box::new(PosConsensus::new(self.network, validators)?)
+            }
+            ConsensusParams::ProofOfAuthority { authorities } => {
+                Box::new(PoaConsensus::new(self.network, authorities)?)
+            }
+        })
+    }
+
+    /// Return the fixed genesis block of the network, `None` if it has none.
+    pub fn fixed_genesis_block(&self) -> Result<Option<Block>> {
+        let Some(genesis) = &self.genesis else {
+            return Ok(None);
+        };
+        let coinbase = Transaction::new_coinbase_tx(
+            &Address::parse(genesis.address, self.network)?,
+            self.genesis_coinbase_data.to_owned(),
+            self.block_subsidy(0),
+        )?;
+        let mut block = Block::new_unsealed(vec![coinbase], String::new())?;
+        block.timestamp = genesis.timestamp;
+        block.nonce = genesis.nonce;
+        let consensus = self.consensus()?;
+        block.hash = consensus.block_hash(&block)?;
+        // Another engine or hash function needs a genesis block of its own.
+        if block.hash != genesis.hash || !consensus.check_block(&block, 0)? {
+            return Err(StringError(format!(
+                "the genesis block of {} is not valid under {}",
+                self.network,
+                consensus.name()
+            )));
+        }
+        Ok(Some(block))
+    }
+
+    /// Check that the block at the height is the fixed genesis block, if the network has one.
+    pub fn matches_genesis(&self, height: u64, hash: &str) -> bool {
+        match &self.genesis {
+            Some(genesis) if height == 0 => genesis.hash == hash,
+            _ => true,
+        }
+    }
+
+    /// New a genesis block that rewards the address, for a chain without a fixed one.
+    ///
+    /// Under proof of stake or authority, the wallets must hold the key of its proposer.
+    pub fn genesis_block(&self, address: &Address, wallets: &Wallets) -> Result<Block> {
+        let coinbase = Transaction::new_coinbase_tx(
+            address,
+            self.genesis_coinbase_data.to_owned(),
+            self.block_subsidy(0),
+        )?;
+        Block::new_genesis(coinbase, &*self.consensus()?, wallets)
+    }
+
+    /// Return the default address of the REST API.
+    pub fn default_rest_addr(&self) -> String {
+        format!("127.0.0.1:{}", self.rest_port)
+    }
+
+    /// Return the default address of the event stream.
+    pub fn default_event_addr(&self) -> String {
+        format!("127.0.0.1:{}", self.event_port)
+    }
+
+    /// Return the default address of the Stratum mining server.
+    pub fn default_stratum_addr(&self) -> String {
+        format!("127.0.0.1:{}", self.stratum_port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::MemoryEngine;
+    use crate::wallet::Wallet;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_params() {
+        for network in [Network::Mainnet, Network::Testnet, Network::Regtest] {
+            assert_eq!(network.to_string().parse::<Network>().unwrap(), network);
+            assert_eq!(network.params().network, network);
+        }
+        assert!("moon".parse::<Network>().is_err());
+
+        let params = ChainParams::regtest();
+        assert_eq!(params.block_subsidy(0), 10);
+        assert_eq!(params.block_subsidy(150), 5);
+        assert_eq!(params.block_subsidy(150 * 64), 0);
+
+        let address = Address::new(Network::Regtest, Wallet::new().pub_key_hash());
+        let wallets = Wallets::with_engine(Arc::new(MemoryEngine::new())).unwrap();
+        let genesis = params.genesis_block(&address, &wallets).unwrap();
+        assert_eq!(genesis.nonce, 0);
+        let consensus = params.consensus().unwrap();
+        assert_eq!(consensus.name(), "proof-of-work");
+        assert!(consensus.check_block(&genesis, 0).unwrap());
+
+        let params = ChainParams {
+            checkpoints: vec![Checkpoint {
+                height: 0,
+                hash: genesis.hash.clone(),
+            }],
+            ..params
+        };
+        assert!(params.matches_checkpoints(0, &genesis.hash));
+        assert!(!params.matches_checkpoints(0, "00"));
+        assert!(params.matches_checkpoints(1, "00"));
+    }
+
+    #[test]
+    fn test_fixed_genesis() {
+        for network in [Network::Mainnet, Network::Testnet, Network::Regtest] {
+            let params = network.params();
+            let genesis = params.fixed_genesis_block().unwrap().unwrap();
+            assert_eq!(genesis.hash, params.genesis.as_ref().unwrap().hash);
+            assert!(params
+                .consensus()
+                .unwrap()
+                .check_block(&genesis, 0)
+                .unwrap());
+            assert!(params.matches_genesis(0, &genesis.hash));
+            assert!(!params.matches_genesis(0, "00"));
+            assert!(params.matches_genesis(1, "00"));
+        }
+
+        // Another hash function needs a genesis block of its own.
+        let params = ChainParams {
+            pow_hash: PowHash::Blake3,
+            ..ChainParams::mainnet()
+        };
+        assert!(params.fixed_genesis_block().is_err());
+        let params = ChainParams {
+            genesis: None,
+            ..params
+        };
+        assert!(params.fixed_genesis_block().unwrap().is_none());
+        assert!(params.matches_genesis(0, "00"));
+    }
+}
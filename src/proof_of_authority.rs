@@ -0,0 +1,349 @@
+//! Proof of authority, for permissioned networks.
+//!
+//! A configured set of authorities, see [`crate::ConsensusParams::ProofOfAuthority`],
+//! take turns sealing blocks: the block at height `h` is signed by authority
+//! `h % n` with its wallet key, so blocks need no mining and every block weighs
+//! the same. Governance transactions, see [`crate::Governance`], add or remove
+//! authorities from the block after theirs once a majority of the authorities
+//! approve them. The chain keeps every set that was in effect, keyed by the
+//! height it took effect, in [`AUTHORITY_TREE`].
+
+use crate::block::{Block, BlockHeader};
+use crate::consensus::{has_valid_signature, sign_block, signed_block_hash, Consensus};
+use crate::engine::{Batch, StorageEngine, Tree, AUTHORITY_TREE};
+use crate::error::Error::{InvalidSnapshot, InvalidTransaction, StringError};
+use crate::transaction::AuthorityChange;
+use crate::wallet::{Wallet, Wallets};
+use crate::{Address, Network, Result};
+use num::BigInt;
+
+/// The proof-of-authority consensus engine.
+pub struct PoaConsensus {
+    network: Network,
+
+    /// The authorities of the genesis block, in turn order.
+    authorities: Vec<String>,
+
+    /// The authority sets that governance put in effect, `None` until the engine is opened.
+    changes: Option<Tree>,
+}
+
+/// Return the key of the authority set in effect from the height, which sorts by height.
+fn change_key(height: u64) -> String {
+    format!("{:020}", height)
+}
+
+impl PoaConsensus {
+    /// New the engine with the authorities of the genesis block, as addresses on the network.
+    pub fn new(network: Network, authorities: &[String]) -> Result<Self> {
+        Self::check_authorities(network, authorities)?;
+        Ok(PoaConsensus {
+            network,
+            authorities: authorities.to_vec(),
+            changes: None,
+        })
+    }
+
+    /// Check that there is at least one authority, and that they are distinct addresses on the network.
+    fn check_authorities(network: Network, authorities: &[String]) -> Result<()> {
+        if authorities.is_empty() {
+            return Err(StringError(
+                "proof of authority needs an authority".to_owned(),
+            ));
+        }
+        for (i, authority) in authorities.iter().enumerate() {
+            Address::parse(authority, network)?;
+            if authorities[..i].contains(authority) {
+                return Err(StringError(format!(
+                    "authority {} is listed twice",
+                    authority
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the authorities in effect at the height, in turn order.
+    pub fn authorities(&self, height: u64) -> Result<Vec<String>> {
+        let Some(changes) = &self.changes else {
+            return Ok(self.authorities.clone());
+        };
+        let key = change_key(height);
+        match changes.list()?.into_iter().rev().find(|(k, _)| *k <= key) {
+            Some((_, v)) => Ok(ron::from_str(&v).map_err(|e| e.code)?),
+            None => Ok(self.authorities.clone()),
+        }
+    }
+
+    /// Return the address of the authority whose turn it is at the height.
+    pub fn in_turn(&self, height: u64) -> Result<String> {
+        let authorities = self.authorities(height)?;
+        Ok(authorities[(height % authorities.len() as u64) as usize].clone())
+    }
+
+    fn changes(&self) -> Result<&Tree> {
+        self.changes.as_ref().ok_or(StringError(
+            "the proof-of-authority state is not open".to_owned(),
+        ))
+    }
+}
+
+impl Consensus for PoaConsensus {
+    fn name(&self) -> &'static str {
+        "proof-of-authority"
+    }
+
+    fn open(&mut self, store: &dyn StorageEngine) -> Result<()> {
+        self.changes = Some(store.open_tree(AUTHORITY_TREE)?);
+        Ok(())
+    }
+
+    /// Sign the block with the wallet of the authority in turn, which must be in the wallets and unlocked.
+    fn seal(&self, block: &mut Block, height: u64, wallets: &Wallets) -> Result<()> {
+        let address = self.in_turn(height)?;
+        let wallet = wallets.get(&address)?.ok_or(StringError(format!(
+            "it is the turn of authority {} at height {}, but its key is not in the wallets",
+            address, height
+        )))?;
+        sign_block(block, &wallet)
+    }
+
+    fn block_hash(&self, block: &Block) -> Result<String> {
+        signed_block_hash(&block.header())
+    }
+
+    /// Check that the authority in turn at the height signed the header.
+    fn check_header(&self, header: &BlockHeader, height: u64) -> Result<bool> {
+        let pub_key_hash = Wallet::hash_pub_key(header.validator.as_bytes());
+        let signer = Address::new(self.network, pub_key_hash).to_string();
+        Ok(signer == self.in_turn(height)? && has_valid_signature(header))
+    }
+
+    /// Every block weighs the same, since only the authority in turn can seal it.
+    fn block_weight(&self, _header: &BlockHeader) -> BigInt {
+        BigInt::from(1)
+    }
+
+    fn sealers(&self, height: u64) -> Result<Vec<String>> {
+        self.authorities(height)
+    }
+
+    /// Apply the governance transactions of the block in order, and put the
+    /// new authority set in effect from the next block.
+    ///
+    /// Each change needs the approvals of a majority of the authorities in
+    /// effect at the height, so the authority that seals it can not change
+    /// the authorities alone.
+    fn connect_block(&self, batch: &mut Batch, block: &Block, height: u64) -> Result<()> {
+        let current = self.authorities(height)?;
+        let mut authorities = current.clone();
+        let mut changed = false;
+        for tx in &block.transactions {
+            let Some(governance) = &tx.governance else {
+                continue;
+            };
+            let invalid = |reason: &str| InvalidTransaction(format!("tx {} {}", tx.id, reason));
+            if governance.height != height {
+                return Err(invalid(&format!("is for height {}", governance.height)));
+            }
+            let mut approvers = vec![];
+            for approval in &governance.approvals {
+                let Some(approver) = governance.approver(approval, self.network)? else {
+                    return Err(invalid("has an invalid approval"));
+                };
+                if !current.contains(&approver) {
+                    return Err(invalid(&format!(
+                        "is approved by {}, not an authority",
+                        approver
+                    )));
+                }
+                if approvers.contains(&approver) {
+                    return Err(invalid(&format!("is approved twice by {}", approver)));
+                }
+                approvers.push(approver);
+            }
+            if approvers.len() * 2 <= current.len() {
+                return Err(invalid(&format!(
+                    "is approved by {} of {} authorities, it needs a majority",
+                    approvers.len(),
+                    current.len()
+                )));
+            }
+            match &governance.change {
+                AuthorityChange::Add(address) => {
+                    if authorities.contains(address) {
+                        return Err(invalid(&format!("adds {}, already an authority", address)));
+                    }
+                    Address::parse(address, self.network)?;
+                    authorities.push(address.clone());
+                }
+                AuthorityChange::Remove(address) => {
+                    if !authorities.contains(address) {
+                        return Err(invalid(&format!("removes {}, not an authority", address)));
+                    }
+                    if authorities.len() == 1 {
+                        return Err(invalid("removes the last authority"));
+                    }
+                    authorities.retain(|a| a != address);
+                }
+            }
+            changed = true;
+        }
+        if changed {
+            batch.put(
+                self.changes()?,
+                change_key(height + 1),
+                ron::to_string(&authorities)?,
+            );
+        }
+        Ok(())
+    }
+
+    fn disconnect_block(&self, batch: &mut Batch, _block: &Block, height: u64) -> Result<()> {
+        batch.delete(self.changes()?, change_key(height + 1));
+        Ok(())
+    }
+
+    fn authority_sets(&self, height: u64) -> Result<Vec<(u64, Vec<String>)>> {
+        let last = change_key(height + 1);
+        let mut sets = vec![];
+        for (key, value) in self.changes()?.list()? {
+            if key > last {
+                break;
+            }
+            let from = key
+                .parse()
+                .map_err(|_| StringError(format!("invalid authority set key {}", key)))?;
+            sets.push((from, ron::from_str(&value).map_err(|e| e.code)?));
+        }
+        Ok(sets)
+    }
+
+    /// Check that the sets take effect in height order after the genesis block
+    /// and no later than the block after the snapshot, and that each is valid.
+    fn load_authority_sets(
+        &self,
+        batch: &mut Batch,
+        sets: &[(u64, Vec<String>)],
+        height: u64,
+    ) -> Result<()> {
+        let mut last = 0;
+        for (from, authorities) in sets {
+            if *from <= last || *from > height + 1 {
+                return Err(InvalidSnapshot(format!(
+                    "the authority set from height {} is out of order",
+                    from
+                )));
+            }
+            Self::check_authorities(self.network, authorities).map_err(|e| {
+                InvalidSnapshot(format!("the authority set from height {}: {}", from, e))
+            })?;
+            batch.put(
+                self.changes()?,
+                change_key(*from),
+                ron::to_string(authorities)?,
+            );
+            last = *from;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::MemoryEngine;
+    use crate::{Governance, Transaction};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_proof_of_authority() {
+        let wallets = Wallets::with_engine(Arc::new(MemoryEngine::new()))
+            .unwrap()
+            .on_network(Network::Regtest);
+        let (wa, wb, wc) = (Wallet::new(), Wallet::new(), Wallet::new());
+        for wallet in [&wa, &wb, &wc] {
+            wallets.set(wallet).unwrap();
+        }
+        let [a, b, c] = [&wa, &wb, &wc].map(|w| w.address_on(Network::Regtest));
+        let store = MemoryEngine::new();
+        let mut poa = PoaConsensus::new(Network::Regtest, &[a.clone(), b.clone()]).unwrap();
+        poa.open(&store).unwrap();
+
+        // The authorities take turns.
+        assert_eq!(poa.in_turn(0).unwrap(), a);
+        assert_eq!(poa.in_turn(1).unwrap(), b);
+        let block = Block::new(vec![], String::new(), 1, &poa, &wallets).unwrap();
+        assert!(poa.check_block(&block, 1).unwrap());
+        assert!(!poa.check_block(&block, 2).unwrap());
+        assert_eq!(poa.block_weight(&block.header()), BigInt::from(1));
+
+        // A change that only the sealer approves is rejected.
+        let governance = |change, approvers: &[&Wallet]| {
+            let mut governance = Governance::new(change, 1);
+            for wallet in approvers {
+                let approval = governance.approve(wallet).unwrap();
+                governance.approvals.push(approval);
+            }
+            Transaction::new_governance_tx(governance).unwrap()
+        };
+        let add_c = || AuthorityChange::Add(c.clone());
+        for approvers in [&[][..], &[&wb], &[&wb, &wb], &[&wb, &wc]] {
+            let block = Block {
+                transactions: vec![governance(add_c(), approvers)],
+                ..block.clone()
+            };
+            assert!(matches!(
+                poa.connect_block(&mut Batch::new(), &block, 1),
+                Err(InvalidTransaction(_))
+            ));
+        }
+        let mut forged = governance(add_c(), &[&wa, &wb]);
+        forged.governance.as_mut().unwrap().change = AuthorityChange::Add(a.clone());
+        let block = Block {
+            transactions: vec![forged],
+            ..block
+        };
+        assert!(matches!(
+            poa.connect_block(&mut Batch::new(), &block, 1),
+            Err(InvalidTransaction(e)) if e.ends_with("has an invalid approval")
+        ));
+
+        // Adding c at height 1 puts it in turn order from height 2.
+        let block = Block {
+            transactions: vec![governance(add_c(), &[&wa, &wb])],
+            ..block
+        };
+        let mut batch = Batch::new();
+        poa.connect_block(&mut batch, &block, 1).unwrap();
+        store.apply_batch(&batch).unwrap();
+        assert_eq!(poa.authorities(1).unwrap(), vec![a.clone(), b.clone()]);
+        assert_eq!(
+            poa.authorities(2).unwrap(),
+            vec![a.clone(), b.clone(), c.clone()]
+        );
+        assert_eq!(poa.in_turn(5).unwrap(), c);
+        assert!(poa.connect_block(&mut Batch::new(), &block, 2).is_err());
+
+        let mut batch = Batch::new();
+        poa.disconnect_block(&mut batch, &block, 1).unwrap();
+        store.apply_batch(&batch).unwrap();
+        assert_eq!(poa.authorities(5).unwrap(), vec![a.clone(), b.clone()]);
+
+        let remove =
+            |address: &str| governance(AuthorityChange::Remove(address.to_owned()), &[&wa, &wb]);
+        let block = Block {
+            transactions: vec![remove(&a), remove(&b)],
+            ..block
+        };
+        assert!(poa.connect_block(&mut Batch::new(), &block, 1).is_err());
+        let block = Block {
+            transactions: vec![remove(&c)],
+            ..block
+        };
+        assert!(poa.connect_block(&mut Batch::new(), &block, 1).is_err());
+
+        assert!(PoaConsensus::new(Network::Regtest, &[]).is_err());
+        assert!(PoaConsensus::new(Network::Regtest, &[a.clone(), a]).is_err());
+    }
+}
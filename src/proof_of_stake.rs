@@ -0,0 +1,177 @@
+//! Stake-weighted proof of stake, for networks whose validators are known.
+//!
+//! The validators and their stakes are fixed in [`crate::ConsensusParams::ProofOfStake`].
+//! The proposer of each block is drawn from them, weighted by stake, with the
+//! hash of the previous block as the seed, so every node agrees on it without
+//! any mining. The proposer signs the block hash with its wallet key, and the
+//! block weighs its stake in fork choice.
+
+use crate::block::{Block, BlockHeader};
+use crate::common::{hash_str, hex_to_big_int};
+use crate::consensus::{has_valid_signature, sign_block, signed_block_hash, Consensus};
+use crate::error::Error::StringError;
+use crate::wallet::{Wallet, Wallets};
+use crate::{Address, Network, Result, Validator};
+use num::{BigInt, ToPrimitive};
+
+/// The proof-of-stake consensus engine.
+pub struct PosConsensus {
+    network: Network,
+
+    /// The public key hashes of the validators and their stakes, in config order.
+    validators: Vec<(Vec<u8>, u64)>,
+}
+
+impl PosConsensus {
+    /// New the engine with the validators of a network.
+    ///
+    /// There must be at least one validator, and every stake must be positive.
+    pub fn new(network: Network, validators: &[Validator]) -> Result<Self> {
+        if validators.is_empty() {
+            return Err(StringError("proof of stake needs a validator".to_owned()));
+        }
+        let mut parsed = vec![];
+        for validator in validators {
+            if validator.stake == 0 {
+                return Err(StringError(format!(
+                    "validator {} has no stake",
+                    validator.address
+                )));
+            }
+            let address = Address::parse(&validator.address, network)?;
+            parsed.push((address.pub_key_hash().to_vec(), validator.stake));
+        }
+        Ok(PosConsensus {
+            network,
+            validators: parsed,
+        })
+    }
+
+    /// Return the public key hash and the stake of the proposer of the block after `pre_hash`.
+    pub fn proposer(&self, pre_hash: &str) -> Result<(&[u8], u64)> {
+        let total: u64 = self.validators.iter().map(|(_, stake)| stake).sum();
+        let seed = hex_to_big_int(&hash_str(pre_hash))? % BigInt::from(total);
+        let mut draw = seed.to_u64().expect("the seed is below the total stake");
+        for (pub_key_hash, stake) in &self.validators {
+            if draw < *stake {
+                return Ok((pub_key_hash, *stake));
+            }
+            draw -= stake;
+        }
+        unreachable!("the draw is below the total stake")
+    }
+
+    /// Return the stake of the validator with the public key hash, 0 if it is not a validator.
+    fn stake_of(&self, pub_key_hash: &[u8]) -> u64 {
+        self.validators
+            .iter()
+            .find(|(v, _)| v == pub_key_hash)
+            .map_or(0, |(_, stake)| *stake)
+    }
+}
+
+impl Consensus for PosConsensus {
+    fn name(&self) -> &'static str {
+        "proof-of-stake"
+    }
+
+    /// Sign the block with the wallet of the proposer, which must be in the wallets and unlocked.
+    fn seal(&self, block: &mut Block, _height: u64, wallets: &Wallets) -> Result<()> {
+        let (pub_key_hash, _) = self.proposer(&block.pre_hash)?;
+        let address = Address::new(self.network, pub_key_hash.to_vec()).to_string();
+        let wallet = wallets.get(&address)?.ok_or(StringError(format!(
+            "validator {} proposes the next block, but its key is not in the wallets",
+            address
+        )))?;
+        sign_block(block, &wallet)
+    }
+
+    fn block_hash(&self, block: &Block) -> Result<String> {
+        signed_block_hash(&block.header())
+    }
+
+    /// Check that the proposer after the previous block signed the header.
+    fn check_header(&self, header: &BlockHeader, _height: u64) -> Result<bool> {
+        let (proposer, _) = self.proposer(&header.pre_hash)?;
+        Ok(
+            Wallet::hash_pub_key(header.validator.as_bytes()) == proposer
+                && has_valid_signature(header),
+        )
+    }
+
+    /// The stake of the validator that signed the block.
+    fn block_weight(&self, header: &BlockHeader) -> BigInt {
+        BigInt::from(self.stake_of(&Wallet::hash_pub_key(header.validator.as_bytes())))
+    }
+
+    fn sealers(&self, _height: u64) -> Result<Vec<String>> {
+        Ok(self
+            .validators
+            .iter()
+            .map(|(pub_key_hash, _)| Address::new(self.network, pub_key_hash.clone()).to_string())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::hex_encode;
+    use crate::engine::MemoryEngine;
+    use crate::{ChainParams, ConsensusParams};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_proof_of_stake() {
+        let wallets = Wallets::with_engine(Arc::new(MemoryEngine::new()))
+            .unwrap()
+            .on_network(Network::Regtest);
+        let (a, b) = (Wallet::new(), Wallet::new());
+        wallets.set(&a).unwrap();
+        wallets.set(&b).unwrap();
+        let validators = vec![
+            Validator {
+                address: a.address_on(Network::Regtest),
+                stake: 3,
+            },
+            Validator {
+                address: b.address_on(Network::Regtest),
+                stake: 1,
+            },
+        ];
+        let mut params = ChainParams::regtest();
+        params.consensus = ConsensusParams::ProofOfStake {
+            validators: validators.clone(),
+        };
+        let pos = PosConsensus::new(Network::Regtest, &validators).unwrap();
+
+        // Both validators propose, the one with more stake more often.
+        let mut proposed = [0, 0];
+        for i in 0..200 {
+            let (proposer, _) = pos.proposer(&i.to_string()).unwrap();
+            proposed[usize::from(proposer != a.pub_key_hash())] += 1;
+        }
+        assert!(proposed[0] > proposed[1] && proposed[1] > 0);
+
+        let address = Address::new(Network::Regtest, a.pub_key_hash());
+        let genesis = params.genesis_block(&address, &wallets).unwrap();
+        assert_eq!(genesis.nonce, 0);
+        assert!(pos.check_block(&genesis, 0).unwrap());
+        let (proposer, stake) = pos.proposer("").unwrap();
+        assert_eq!(Wallet::hash_pub_key(genesis.validator.as_bytes()), proposer);
+        assert_eq!(pos.block_weight(&genesis.header()), BigInt::from(stake));
+
+        let mut forged = genesis.clone();
+        forged.transactions.clear();
+        assert!(!pos.check_block(&forged, 0).unwrap());
+        let mut forged = genesis.clone();
+        forged.signature = hex_encode(&[0; 64]);
+        assert!(!pos.check_block(&forged, 0).unwrap());
+
+        // A node without the key of the proposer can not seal.
+        let empty = Wallets::with_engine(Arc::new(MemoryEngine::new())).unwrap();
+        assert!(params.genesis_block(&address, &empty).is_err());
+
+        assert!(PosConsensus::new(Network::Regtest, &[]).is_err());
+    }
+}
@@ -1,24 +1,112 @@
-use crate::block::Block;
-use crate::common::{append_str, hash_utf8, hex_to_big_int};
+use crate::block::{Block, BlockHeader};
+use crate::common::{append_str, hash_utf8, hex_encode, hex_to_big_int};
+use crate::consensus::Consensus;
+use crate::error::Error::StringError;
+use crate::wallet::Wallets;
+use crate::{ChainParams, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
 use log::info;
 use num::BigInt;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::borrow::Borrow;
 use std::cmp::Ordering;
+use std::fmt;
 use std::ops::ShlAssign;
+use std::time::{Duration, Instant};
 
-/// This is an arbitrary number that takes less than 256 bits in memory.
-///
-/// Here we won't implement a target adjusting algorithm.
+const MAX_NONCE: u64 = u64::MAX;
+
+/// The memory of an Argon2 proof-of-work hash in KiB, which is what makes it memory-hard.
+const ARGON2_M_COST: u32 = 4096;
+
+/// The number of passes of an Argon2 proof-of-work hash.
+const ARGON2_T_COST: u32 = 1;
+
+/// The fixed salt of Argon2 proof-of-work hashes, the block data is the password.
+const ARGON2_SALT: &[u8] = b"rchain proof of work";
+
+/// The hash function of proof-of-work, a parameter of the chain.
 ///
-/// For now, so we can just define the difficulty as a global constant.
-const TARGET_BITS: i32 = 8;
+/// Every function produces a 256-bit hash, so the target means the same for all of them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowHash {
+    /// SHA-256, the hash of chains created before the function was a parameter.
+    #[default]
+    Sha256,
 
-const MAX_NONCE: u64 = u64::MAX;
+    /// SHA-256 of SHA-256, like Bitcoin.
+    DoubleSha256,
+
+    /// BLAKE3, much faster than SHA-256 in software.
+    Blake3,
+
+    /// Argon2id with 4 MiB of memory, which makes mining memory-bound to resist ASICs.
+    Argon2,
+}
+
+impl PowHash {
+    /// Every hash function, in the order `hash-rate` reports them.
+    pub const ALL: [PowHash; 4] = [
+        PowHash::Sha256,
+        PowHash::DoubleSha256,
+        PowHash::Blake3,
+        PowHash::Argon2,
+    ];
+
+    /// Return the hex hash of the data.
+    pub fn hash(&self, data: &[u8]) -> Result<String> {
+        match self {
+            PowHash::Sha256 => Ok(hash_utf8(data)),
+            PowHash::DoubleSha256 => Ok(hex_encode(&Sha256::digest(Sha256::digest(data)))),
+            PowHash::Blake3 => Ok(blake3::hash(data).to_hex().to_string()),
+            PowHash::Argon2 => {
+                let params = Params::new(ARGON2_M_COST, ARGON2_T_COST, 1, Some(32))
+                    .map_err(|e| StringError(e.to_string()))?;
+                let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+                let mut hash = [0; 32];
+                argon2
+                    .hash_password_into(data, ARGON2_SALT, &mut hash)
+                    .map_err(|e| StringError(e.to_string()))?;
+                Ok(hex_encode(&hash))
+            }
+        }
+    }
+
+    /// Hash for the duration, and return the number of hashes per second.
+    pub fn hash_rate(&self, duration: Duration) -> Result<f64> {
+        let start = Instant::now();
+        let mut count: u64 = 0;
+        while count == 0 || start.elapsed() < duration {
+            self.hash(&count.to_le_bytes())?;
+            count += 1;
+        }
+        Ok(count as f64 / start.elapsed().as_secs_f64())
+    }
+}
 
-/// Proof of work
+impl fmt::Display for PowHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PowHash::Sha256 => "sha256",
+            PowHash::DoubleSha256 => "double-sha256",
+            PowHash::Blake3 => "blake3",
+            PowHash::Argon2 => "argon2",
+        })
+    }
+}
+
+/// Proof of work over the fields of a block header, which commit to the
+/// transactions through [`BlockHeader::tx_root`].
 pub struct ProofOfWork {
-    block: Block,
+    header: BlockHeader,
+
+    /// The difficulty, see [`ChainParams::target_bits`].
+    target_bits: u32,
+
+    /// The hash function, see [`ChainParams::pow_hash`].
+    pow_hash: PowHash,
 
     /// Use a big integer because of the way we'll compare a hash to the target:
     ///
@@ -27,52 +115,78 @@ pub struct ProofOfWork {
 }
 
 impl ProofOfWork {
-    /// New a proof-of-work
-    pub fn new(block: Block) -> Self {
-        let mut target = BigInt::from(1);
-        target.shl_assign(256 - TARGET_BITS);
+    /// New a proof-of-work of the header with the difficulty of the chain.
+    pub fn new(header: BlockHeader, params: &ChainParams) -> Self {
+        let target_bits = params.target_bits;
+        let target = Self::target(params);
 
         let mut hasher = Sha256::new();
         hasher.update(format!("{:?}", target).as_bytes());
 
-        ProofOfWork { block, target }
+        ProofOfWork {
+            header,
+            target_bits,
+            pow_hash: params.pow_hash,
+            target,
+        }
+    }
+
+    /// Check that a block hash is below the target of the chain.
+    pub fn meets_target(hash: &str, params: &ChainParams) -> Result<bool> {
+        Ok(hex_to_big_int(hash)? < Self::target(params))
+    }
+
+    fn target(params: &ChainParams) -> BigInt {
+        let mut target = BigInt::from(1);
+        target.shl_assign(256 - params.target_bits);
+        target
     }
 
     /// Merge block fields with target and nonce.
-    pub fn prepare_data(&self, nonce: u64) -> Vec<u8> {
+    pub fn prepare_data(&self, nonce: u64) -> Result<Vec<u8>> {
+        let mut data = self.work_data()?;
+        append_str(&mut data, format!("{:x}", nonce).as_str());
+
+        Ok(data)
+    }
+
+    /// Merge block fields with target, the data that [`ProofOfWork::prepare_data`]
+    /// appends the nonce to in lowercase hex.
+    pub fn work_data(&self) -> Result<Vec<u8>> {
         let mut data = vec![];
 
-        append_str(&mut data, self.block.pre_hash.as_str());
-        append_str(
-            &mut data,
-            self.block.serialize_transactions().unwrap().as_str(),
-        );
-        append_str(&mut data, format!("{:x}", self.block.timestamp).as_str());
-        append_str(&mut data, format!("{:x}", TARGET_BITS).as_str());
-        append_str(&mut data, format!("{:x}", nonce).as_str());
+        append_str(&mut data, self.header.pre_hash.as_str());
+        append_str(&mut data, self.header.tx_root.as_str());
+        append_str(&mut data, format!("{:x}", self.header.timestamp).as_str());
+        append_str(&mut data, format!("{:x}", self.target_bits).as_str());
+
+        Ok(data)
+    }
 
-        data
+    /// Return the target in hex, a hash meets it if it is below as a number.
+    pub fn target_hex(&self) -> String {
+        format!("{:x}", self.target)
     }
 
     /// Get the nonce which is for the requirement and hash.
-    pub fn run(&self) -> (u64, String) {
+    pub fn run(&self) -> Result<(u64, String)> {
         let mut nonce: u64 = 0;
         let mut hash_res = String::new();
         info!("Mining the block...");
 
         // limited by MAX_NONCE due to avoid a possible overflow of nonce.
         while nonce < MAX_NONCE {
-            let prepare_data = self.prepare_data(nonce);
+            let prepare_data = self.prepare_data(nonce)?;
 
-            // hash the prepare data with SHA-256.
-            let hash = hash_utf8(prepare_data.as_slice());
+            // hash the prepare data with the hash function of the chain.
+            let hash = self.pow_hash.hash(prepare_data.as_slice())?;
 
             // convert the hash(hex string) to big int.
-            let hash_int = hex_to_big_int(&hash);
+            let hash_int = hex_to_big_int(&hash)?;
 
             // compare the integer with the target.
             // the requirement sounds like "first few bits of a hash must be zeros",
-            // and the number of zero bits depends on TARGET_BITS which is also the difficulty of mining.
+            // and the number of zero bits depends on target_bits which is also the difficulty of mining.
             if hash_int.lt(self.target.borrow()) {
                 hash_res = hash;
                 break;
@@ -80,15 +194,144 @@ impl ProofOfWork {
             nonce += 1;
         }
 
-        (nonce, hash_res)
+        Ok((nonce, hash_res))
+    }
+
+    /// Return the hash of the header with its nonce.
+    pub fn hash(&self) -> Result<String> {
+        let data = self.prepare_data(self.header.nonce)?;
+        self.pow_hash.hash(data.as_slice())
     }
 
     /// Validate proof of works.
-    pub fn validate(&self) -> bool {
-        let data = self.prepare_data(self.block.nonce);
-        let hash = hash_utf8(data.as_slice());
-        let hash_int = hex_to_big_int(&hash);
+    pub fn validate(&self) -> Result<bool> {
+        let data = self.prepare_data(self.header.nonce)?;
+        let hash = self.pow_hash.hash(data.as_slice())?;
+        let hash_int = hex_to_big_int(&hash)?;
+
+        Ok(hash_int.cmp(&self.target) == Ordering::Less)
+    }
+}
+
+/// The proof-of-work consensus engine, which mines blocks below the target of the chain.
+pub struct PowConsensus {
+    params: ChainParams,
+}
+
+impl PowConsensus {
+    /// New the engine with the difficulty of the chain.
+    pub fn new(params: &ChainParams) -> Self {
+        PowConsensus {
+            params: params.clone(),
+        }
+    }
+}
+
+impl Consensus for PowConsensus {
+    /// The name includes the hash function unless it is SHA-256, so a chain
+    /// refuses to open with another hash function.
+    fn name(&self) -> &'static str {
+        match self.params.pow_hash {
+            PowHash::Sha256 => "proof-of-work",
+            PowHash::DoubleSha256 => "proof-of-work-double-sha256",
+            PowHash::Blake3 => "proof-of-work-blake3",
+            PowHash::Argon2 => "proof-of-work-argon2",
+        }
+    }
+
+    fn seal(&self, block: &mut Block, _height: u64, _wallets: &Wallets) -> Result<()> {
+        let (nonce, hash) = ProofOfWork::new(block.header(), &self.params).run()?;
+        info!("The block get nonce {}", nonce);
+        block.hash = hash;
+        block.nonce = nonce;
+        Ok(())
+    }
+
+    fn block_hash(&self, block: &Block) -> Result<String> {
+        ProofOfWork::new(block.header(), &self.params).hash()
+    }
+
+    /// Recompute the hash from the header fields, then check it against the target.
+    fn check_header(&self, header: &BlockHeader, _height: u64) -> Result<bool> {
+        let pow = ProofOfWork::new(header.clone(), &self.params);
+        Ok(pow.hash()? == header.hash && ProofOfWork::meets_target(&header.hash, &self.params)?)
+    }
+
+    /// The difficulty is fixed, so every block has the same work.
+    fn block_weight(&self, _header: &BlockHeader) -> BigInt {
+        self.params.block_work()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::MemoryEngine;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_pow_hash() {
+        let data = b"abc";
+        assert_eq!(
+            PowHash::Sha256.hash(data).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            PowHash::DoubleSha256.hash(data).unwrap(),
+            "4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358"
+        );
+        assert_eq!(
+            PowHash::Blake3.hash(data).unwrap(),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        let argon2 = PowHash::Argon2.hash(data).unwrap();
+        assert_eq!(argon2.len(), 64);
+        assert_eq!(PowHash::Argon2.hash(data).unwrap(), argon2);
+        assert_ne!(PowHash::Argon2.hash(b"abd").unwrap(), argon2);
+        assert!(PowHash::Blake3.hash_rate(Duration::ZERO).unwrap() > 0.0);
+
+        // Each hash function seals blocks that only it accepts.
+        let wallets = Wallets::with_engine(Arc::new(MemoryEngine::new())).unwrap();
+        let mut params = ChainParams::regtest();
+        params.target_bits = 4;
+        let sha256 = PowConsensus::new(&params);
+        params.pow_hash = PowHash::Argon2;
+        let argon2 = PowConsensus::new(&params);
+        assert_eq!(argon2.name(), "proof-of-work-argon2");
+        let block = Block::new(vec![], String::new(), 0, &argon2, &wallets).unwrap();
+        assert!(argon2.check_block(&block, 0).unwrap());
+        assert!(!sha256.check_block(&block, 0).unwrap());
+    }
+
+    #[test]
+    fn test_check_header() {
+        let wallets = Wallets::with_engine(Arc::new(MemoryEngine::new())).unwrap();
+        let mut params = ChainParams::regtest();
+        params.target_bits = 8;
+        let pow = PowConsensus::new(&params);
+        let block = Block::new(vec![], String::new(), 0, &pow, &wallets).unwrap();
+        let header = block.header();
+        assert!(pow.check_header(&header, 0).unwrap());
+
+        // A made-up hash below the target does not recompute from the header.
+        let forged = BlockHeader {
+            hash: format!("00{}", "0".repeat(62)),
+            ..header.clone()
+        };
+        assert!(ProofOfWork::meets_target(&forged.hash, &params).unwrap());
+        assert!(!pow.check_header(&forged, 0).unwrap());
 
-        hash_int.cmp(&self.target) == Ordering::Less
+        // Neither does a header whose transaction root was swapped.
+        let swapped = BlockHeader {
+            tx_root: "00".to_owned(),
+            ..header
+        };
+        assert!(!pow.check_header(&swapped, 0).unwrap());
+        let block = Block {
+            transactions: vec![],
+            tx_root: "00".to_owned(),
+            ..block
+        };
+        assert!(!pow.check_block(&block, 0).unwrap());
     }
 }
@@ -0,0 +1,230 @@
+//! Read-only REST API of the block explorer.
+//!
+//! All responses are JSON, built from the types in [`crate::view`].
+
+use crate::error::Error::{BlockPruned, StringError};
+use crate::view::{BlockView, TransactionView, UtxoView};
+use crate::{Address, Blockchain, Result};
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use tiny_http::{Header, Method, Response, Server};
+
+/// The number of items in a page if `limit` is not given.
+const DEFAULT_PAGE_LIMIT: usize = 10;
+
+/// The max number of items in a page.
+const MAX_PAGE_LIMIT: usize = 100;
+
+/// A REST server over a blockchain.
+pub struct RestServer {
+    server: Server,
+    chain: Blockchain,
+}
+
+impl RestServer {
+    /// Bind a REST server to the address.
+    pub fn bind(chain: Blockchain, addr: &str) -> Result<Self> {
+        let server = Server::http(addr).map_err(|e| StringError(e.to_string()))?;
+        Ok(RestServer { server, chain })
+    }
+
+    /// Return the address the server is listening on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Serve requests until the server is dropped.
+    pub fn run(&self) {
+        info!("REST server listening on {:?}", self.local_addr());
+        for request in self.server.incoming_requests() {
+            let (status, body) = match request.method() {
+                Method::Get => handle(&self.chain, request.url()),
+                _ => (405, json!({ "error": "method not allowed" })),
+            };
+            let header =
+                Header::from_bytes("Content-Type", "application/json").expect("a valid header");
+            let response = Response::from_string(body.to_string())
+                .with_status_code(status)
+                .with_header(header);
+            if let Err(e) = request.respond(response) {
+                warn!("failed to respond: {}", e);
+            }
+        }
+    }
+}
+
+/// The error of a REST request.
+enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    Gone(String),
+    Internal(crate::error::Error),
+}
+
+impl From<crate::error::Error> for ApiError {
+    fn from(e: crate::error::Error) -> Self {
+        match e {
+            BlockPruned(_) => ApiError::Gone(e.to_string()),
+            e => ApiError::Internal(e),
+        }
+    }
+}
+
+/// Route a GET request to the chain.
+///
+/// Return the HTTP status code and the JSON body.
+pub fn handle(chain: &Blockchain, url: &str) -> (u16, Value) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let res = match segments.as_slice() {
+        ["blocks"] => get_blocks(chain, query),
+        ["block", hash] => get_block(chain, hash),
+        ["tx", id] => get_tx(chain, id),
+        ["address", address, "utxos"] => get_utxos(chain, address),
+        ["address", address, "history"] => get_history(chain, address, query),
+        _ => Err(ApiError::NotFound(format!("no such endpoint {}", path))),
+    };
+    match res {
+        Ok(v) => (200, v),
+        Err(ApiError::BadRequest(e)) => (400, json!({ "error": e })),
+        Err(ApiError::NotFound(e)) => (404, json!({ "error": e })),
+        Err(ApiError::Gone(e)) => (410, json!({ "error": e })),
+        Err(ApiError::Internal(e)) => (500, json!({ "error": e.to_string() })),
+    }
+}
+
+fn get_blocks(chain: &Blockchain, query: &str) -> std::result::Result<Value, ApiError> {
+    let from = query_param(query, "from")?.unwrap_or(0);
+    let limit = page_limit(query)?;
+    let blocks = chain.blocks(from, limit)?;
+    let next = if chain.get_block_hash(from + blocks.len() as u64)?.is_some() {
+        Some(from + blocks.len() as u64)
+    } else {
+        None
+    };
+    let blocks: Vec<BlockView> = blocks
+        .iter()
+        .map(|(height, block)| BlockView::new(block, *height, chain.network()))
+        .collect();
+    Ok(json!({ "blocks": blocks, "next": next }))
+}
+
+fn get_block(chain: &Blockchain, hash: &str) -> std::result::Result<Value, ApiError> {
+    let block = chain
+        .get_block(hash)?
+        .ok_or(ApiError::NotFound(format!("no such block {}", hash)))?;
+    let height = chain
+        .get_block_height(hash)?
+        .ok_or(ApiError::NotFound(format!("no such block {}", hash)))?;
+    Ok(json!(BlockView::new(&block, height, chain.network())))
+}
+
+fn get_tx(chain: &Blockchain, id: &str) -> std::result::Result<Value, ApiError> {
+    let (tx, block_hash) = chain
+        .find_transaction(id)?
+        .ok_or(ApiError::NotFound(format!("no such tx {}", id)))?;
+    let height = chain.get_block_height(&block_hash)?;
+    Ok(json!({
+        "block_hash": block_hash,
+        "height": height,
+        "transaction": TransactionView::new(&tx, chain.network()),
+    }))
+}
+
+fn get_utxos(chain: &Blockchain, address: &str) -> std::result::Result<Value, ApiError> {
+    let address = parse_address(chain, address)?;
+    let utxos = UtxoView::list(chain.find_utxo(address.pub_key_hash())?, chain.network());
+    let balance: i64 = utxos.iter().map(|u| u.output.value).sum();
+    Ok(json!({ "address": address.to_string(), "balance": balance, "utxos": utxos }))
+}
+
+fn get_history(
+    chain: &Blockchain,
+    address: &str,
+    query: &str,
+) -> std::result::Result<Value, ApiError> {
+    let address = parse_address(chain, address)?;
+    let from = query_param(query, "from")?.unwrap_or(0) as usize;
+    let limit = page_limit(query)?;
+    // Fetch one more entry to know if there is a next page.
+    let mut entries = chain.address_history(address.pub_key_hash(), from, limit + 1)?;
+    let next = if entries.len() > limit {
+        entries.truncate(limit);
+        Some(from + limit)
+    } else {
+        None
+    };
+    Ok(json!({ "address": address.to_string(), "history": entries, "next": next }))
+}
+
+fn parse_address(chain: &Blockchain, address: &str) -> std::result::Result<Address, ApiError> {
+    Address::parse(address, chain.network()).map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+fn page_limit(query: &str) -> std::result::Result<usize, ApiError> {
+    let limit = query_param(query, "limit")?.unwrap_or(DEFAULT_PAGE_LIMIT as u64) as usize;
+    Ok(limit.min(MAX_PAGE_LIMIT))
+}
+
+/// Parse a numeric parameter from the query string.
+fn query_param(query: &str, name: &str) -> std::result::Result<Option<u64>, ApiError> {
+    for pair in query.split('&') {
+        if let Some((key, value)) = pair.split_once('=') {
+            if key == name {
+                let value = value
+                    .parse()
+                    .map_err(|_| ApiError::BadRequest(format!("invalid {}: {}", name, value)))?;
+                return Ok(Some(value));
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::Wallet;
+    use crate::ChainParams;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_handle() {
+        let temp_dir = TempDir::new().unwrap();
+        let address = Wallet::new().address();
+        let params = ChainParams {
+            genesis: None,
+            ..ChainParams::mainnet()
+        };
+        let chain = Blockchain::with_params(temp_dir.path(), &address, params).unwrap();
+
+        let (status, body) = handle(&chain, "/blocks?from=0&limit=5");
+        assert_eq!(status, 200);
+        assert_eq!(body["blocks"][0]["hash"], json!(chain.tip));
+        assert_eq!(body["next"], Value::Null);
+
+        let (status, body) = handle(&chain, &format!("/block/{}", chain.tip));
+        assert_eq!(status, 200);
+        assert_eq!(body["height"], json!(0));
+        let tx_id = body["transactions"][0]["id"].as_str().unwrap().to_owned();
+
+        let (status, body) = handle(&chain, &format!("/tx/{}", tx_id));
+        assert_eq!(status, 200);
+        assert_eq!(body["transaction"]["vout"][0]["address"], json!(address));
+
+        let (status, body) = handle(&chain, &format!("/address/{}/utxos", address));
+        assert_eq!(status, 200);
+        assert_eq!(body["balance"], json!(10));
+
+        let (status, body) = handle(&chain, &format!("/address/{}/history", address));
+        assert_eq!(status, 200);
+        assert_eq!(body["history"][0]["tx_id"], json!(tx_id));
+        assert_eq!(body["history"][0]["direction"], json!("Received"));
+
+        assert_eq!(handle(&chain, "/block/unknown").0, 404);
+        assert_eq!(handle(&chain, "/blocks?limit=x").0, 400);
+        assert_eq!(handle(&chain, "/address/who/utxos").0, 400);
+        assert_eq!(handle(&chain, "/unknown").0, 404);
+    }
+}
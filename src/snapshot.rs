@@ -0,0 +1,314 @@
+//! UTXO set snapshots, the file format of `dump-utxos` and `load-utxos`.
+//!
+//! A snapshot is the magic `RUTX`, a format version byte, the network name
+//! prefixed with its length as one byte, the number of headers, of unspent
+//! outputs and of authority sets as big-endian `u64`s, and then every header in
+//! height order followed by every output and every authority set, each as its
+//! RON serialization prefixed with its length as a big-endian `u32`. The hash
+//! of the snapshot and then the SHA-256 of everything before it are the trailer.
+//!
+//! Version 1 snapshots have no authority sets, nor their count.
+
+use crate::common::hex_encode;
+use crate::error::Error::InvalidSnapshot;
+use crate::{BlockHeader, Network, Result, TXOutput};
+use sha2::{Digest, Sha256};
+use std::io::{ErrorKind, Read, Write};
+
+const MAGIC: &[u8; 4] = b"RUTX";
+
+const VERSION: u8 = 2;
+
+/// Headers and outputs are far smaller, a larger length means a corrupt snapshot.
+const MAX_ITEM_LEN: u32 = 1 << 20;
+
+/// The UTXO set of a chain at a height, with the headers up to that height.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UtxoSnapshot {
+    /// The network of the chain.
+    pub network: Network,
+
+    /// The headers from the genesis block to the block of the snapshot, never empty.
+    pub headers: Vec<BlockHeader>,
+
+    /// The unspent outputs as `(tx_id, idx_vout, output)`.
+    pub outputs: Vec<(String, usize, TXOutput)>,
+
+    /// The authority sets of a proof-of-authority chain, keyed by the height they
+    /// take effect, see [`crate::Consensus::authority_sets`].
+    pub authority_sets: Vec<(u64, Vec<String>)>,
+}
+
+impl UtxoSnapshot {
+    /// Return the height of the snapshot.
+    pub fn height(&self) -> u64 {
+        self.headers.len() as u64 - 1
+    }
+
+    /// Return the hash of the block of the snapshot.
+    pub fn tip(&self) -> &str {
+        &self.headers[self.headers.len() - 1].hash
+    }
+
+    /// Return the hash of the UTXO set and the authority sets, see [`snapshot_hash`].
+    pub fn hash(&self) -> String {
+        snapshot_hash(&self.outputs, &self.authority_sets)
+    }
+
+    /// Write the snapshot.
+    pub fn write(&self, writer: impl Write) -> Result<()> {
+        let mut writer = HashingWriter {
+            writer,
+            hasher: Sha256::new(),
+        };
+        let network = self.network.to_string();
+        writer.write(MAGIC)?;
+        writer.write(&[VERSION, network.len() as u8])?;
+        writer.write(network.as_bytes())?;
+        writer.write(&(self.headers.len() as u64).to_be_bytes())?;
+        writer.write(&(self.outputs.len() as u64).to_be_bytes())?;
+        writer.write(&(self.authority_sets.len() as u64).to_be_bytes())?;
+        for header in &self.headers {
+            writer.write_item(&ron::to_string(header)?)?;
+        }
+        for output in &self.outputs {
+            writer.write_item(&ron::to_string(output)?)?;
+        }
+        for set in &self.authority_sets {
+            writer.write_item(&ron::to_string(set)?)?;
+        }
+        writer.write(self.hash().as_bytes())?;
+        let checksum = writer.hasher.finalize_reset();
+        writer.writer.write_all(&checksum)?;
+        writer.writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a snapshot, checking its checksum and the hash of its UTXO set.
+    pub fn read(reader: impl Read) -> Result<Self> {
+        let mut reader = HashingReader {
+            reader,
+            hasher: Sha256::new(),
+        };
+        let mut header = [0; 6];
+        reader.read(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(InvalidSnapshot("not a UTXO snapshot".to_owned()));
+        }
+        let version = header[4];
+        if version == 0 || version > VERSION {
+            return Err(InvalidSnapshot(format!("unknown version {}", version)));
+        }
+        let mut network = vec![0; header[5] as usize];
+        reader.read(&mut network)?;
+        let network = String::from_utf8(network)?.parse()?;
+        let header_count = reader.read_u64()?;
+        let output_count = reader.read_u64()?;
+        let set_count = match version {
+            1 => 0,
+            _ => reader.read_u64()?,
+        };
+        if header_count == 0 {
+            return Err(InvalidSnapshot("no headers".to_owned()));
+        }
+        let mut headers = vec![];
+        for _ in 0..header_count {
+            headers.push(reader.read_item()?);
+        }
+        let mut outputs = vec![];
+        for _ in 0..output_count {
+            outputs.push(reader.read_item()?);
+        }
+        let mut authority_sets = vec![];
+        for _ in 0..set_count {
+            authority_sets.push(reader.read_item()?);
+        }
+        let snapshot = UtxoSnapshot {
+            network,
+            headers,
+            outputs,
+            authority_sets,
+        };
+        let mut hash = vec![0; 64];
+        reader.read(&mut hash)?;
+        let expected = reader.hasher.finalize_reset();
+        let mut checksum = [0; 32];
+        reader.reader.read_exact(&mut checksum).map_err(truncated)?;
+        if checksum[..] != expected[..] {
+            return Err(InvalidSnapshot("checksum mismatch".to_owned()));
+        }
+        if String::from_utf8(hash)? != snapshot.hash() {
+            return Err(InvalidSnapshot("snapshot hash mismatch".to_owned()));
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Return the hash of a UTXO set, which is the same on every node with the same set.
+///
+/// It is the hex SHA-256 of one `tx_id:idx_vout:value:pub_key_hash` line per
+/// output, sorted by `tx_id` and `idx_vout`, with the public key hash in hex.
+pub fn utxo_set_hash(outputs: &[(String, usize, TXOutput)]) -> String {
+    let mut sorted: Vec<&(String, usize, TXOutput)> = outputs.iter().collect();
+    sorted.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+    let mut hasher = Sha256::new();
+    for (tx_id, idx_vout, output) in sorted {
+        hasher.update(format!(
+            "{}:{}:{}:{}\n",
+            tx_id,
+            idx_vout,
+            output.value,
+            hex_encode(output.pub_key_hash())
+        ));
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Return the hash that a snapshot commits to, which is the same on every node with the same state.
+///
+/// Without authority sets it is the [`utxo_set_hash`]. Otherwise it is the hex
+/// SHA-256 of that hash followed by one `\nheight:authority,...` line per set.
+pub fn snapshot_hash(
+    outputs: &[(String, usize, TXOutput)],
+    authority_sets: &[(u64, Vec<String>)],
+) -> String {
+    let utxo_hash = utxo_set_hash(outputs);
+    if authority_sets.is_empty() {
+        return utxo_hash;
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(utxo_hash);
+    for (height, authorities) in authority_sets {
+        hasher.update(format!("\n{}:{}", height, authorities.join(",")));
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+struct HashingWriter<W: Write> {
+    writer: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.hasher.update(data);
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+
+    fn write_item(&mut self, item: &str) -> Result<()> {
+        self.write(&(item.len() as u32).to_be_bytes())?;
+        self.write(item.as_bytes())
+    }
+}
+
+struct HashingReader<R: Read> {
+    reader: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.reader.read_exact(buf).map_err(truncated)?;
+        self.hasher.update(&buf);
+        Ok(())
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        let mut buf = [0; 8];
+        self.read(&mut buf)?;
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    fn read_item<T: serde::de::DeserializeOwned>(&mut self) -> Result<T> {
+        let mut len = [0; 4];
+        self.read(&mut len)?;
+        let len = u32::from_be_bytes(len);
+        if len > MAX_ITEM_LEN {
+            return Err(InvalidSnapshot(format!("item of {} bytes", len)));
+        }
+        let mut item = vec![0; len as usize];
+        self.read(&mut item)?;
+        ron::from_str(&String::from_utf8(item)?)
+            .map_err(|e| InvalidSnapshot(format!("unreadable item: {}", e)))
+    }
+}
+
+fn truncated(e: std::io::Error) -> crate::Error {
+    match e.kind() {
+        ErrorKind::UnexpectedEof => InvalidSnapshot("truncated".to_owned()),
+        _ => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::MemoryEngine;
+    use crate::wallet::Wallets;
+    use crate::{Address, ChainParams};
+    use std::sync::Arc;
+
+    fn snapshot() -> UtxoSnapshot {
+        let params = ChainParams::regtest();
+        let address = Address::new(Network::Regtest, vec![0; 20]);
+        let wallets = Wallets::with_engine(Arc::new(MemoryEngine::new())).unwrap();
+        let genesis = params.genesis_block(&address, &wallets).unwrap();
+        let coinbase = &genesis.transactions[0];
+        UtxoSnapshot {
+            network: Network::Regtest,
+            headers: vec![genesis.header()],
+            outputs: vec![(coinbase.id.clone(), 0, coinbase.vout[0].clone())],
+            authority_sets: vec![(1, vec![address.to_string()])],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let snapshot = snapshot();
+        let mut data = vec![];
+        snapshot.write(&mut data).unwrap();
+        let read = UtxoSnapshot::read(&data[..]).unwrap();
+        assert_eq!(read, snapshot);
+        assert_eq!(read.height(), 0);
+        assert_eq!(read.hash(), snapshot.hash());
+    }
+
+    #[test]
+    fn test_utxo_set_hash() {
+        let address = Address::new(Network::Regtest, vec![1; 20]);
+        let a = ("a".to_owned(), 0, TXOutput::new(1, &address));
+        let b = ("b".to_owned(), 1, TXOutput::new(2, &address));
+        let ab = utxo_set_hash(&[a.clone(), b.clone()]);
+        assert_eq!(ab, utxo_set_hash(&[b, a.clone()]));
+        let only_a = [a];
+        assert_ne!(utxo_set_hash(&only_a), ab);
+        assert_eq!(
+            utxo_set_hash(&[]),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        // Authority sets change the hash, which is the UTXO set hash without them.
+        assert_eq!(snapshot_hash(&only_a, &[]), utxo_set_hash(&only_a));
+        let set = |from, authority: &str| (from, vec![authority.to_owned()]);
+        let one = snapshot_hash(&only_a, &[set(1, "x")]);
+        assert_ne!(one, utxo_set_hash(&only_a));
+        assert_ne!(one, snapshot_hash(&only_a, &[set(2, "x")]));
+        assert_ne!(one, snapshot_hash(&only_a, &[set(1, "y")]));
+    }
+
+    #[test]
+    fn test_corrupt_snapshot() {
+        let mut data = vec![];
+        snapshot().write(&mut data).unwrap();
+        let truncated = UtxoSnapshot::read(&data[..data.len() - 1]);
+        assert!(matches!(truncated, Err(InvalidSnapshot(e)) if e == "truncated"));
+
+        let mut flipped = data.clone();
+        let last = flipped.len() - 40;
+        flipped[last] ^= 1;
+        assert!(UtxoSnapshot::read(&flipped[..]).is_err());
+
+        assert!(UtxoSnapshot::read(&b"RCHN"[..]).is_err());
+    }
+}
@@ -0,0 +1,472 @@
+//! Stratum-style mining protocol for external miners.
+//!
+//! The server speaks newline-delimited JSON-RPC over TCP with the methods of
+//! Stratum. A miner calls `mining.subscribe`, then `mining.authorize` with the
+//! address that its blocks reward as the worker name, and the server sends it a
+//! job with `mining.notify`, and a new one whenever the tip changes:
+//!
+//! ```json
+//! {"id":null,"method":"mining.notify","params":[JOB_ID, PRE_HASH, DATA, TARGET, POW_HASH, CLEAN_JOBS]}
+//! ```
+//!
+//! `DATA` is the hex of what [`ProofOfWork::work_data`] returns for the block
+//! template. The miner appends a nonce in lowercase hex to it, hashes it with
+//! the `POW_HASH` function, and submits the nonce with
+//! `mining.submit [WORKER, JOB_ID, NONCE]` once the hash is below the hex
+//! `TARGET`. The node validates the block with [`ProofOfWork::validate`] and
+//! connects it. Errors are `[CODE, MESSAGE, null]` with the codes of Stratum.
+
+use crate::common::{append_str, hex_decode, hex_encode, hex_to_big_int};
+use crate::error::Error::StringError;
+use crate::events::ChainEvent;
+use crate::{Address, Block, Blockchain, ConsensusParams, PowHash, ProofOfWork, Result};
+use log::{info, warn};
+use num::BigInt;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long a connection waits for a request before it checks for a new tip.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The Stratum error code of other errors.
+const OTHER: i64 = 20;
+
+/// The Stratum error code of a job that is unknown or no longer on the tip.
+const STALE_JOB: i64 = 21;
+
+/// The Stratum error code of a hash above the target.
+const LOW_DIFFICULTY: i64 = 23;
+
+/// The Stratum error code of a worker that is not authorized.
+const UNAUTHORIZED: i64 = 24;
+
+/// The Stratum error code of a miner that is not subscribed.
+const NOT_SUBSCRIBED: i64 = 25;
+
+/// The error of a Stratum request, a code and a message.
+type RpcError = (i64, String);
+
+/// A Stratum server that hands out block templates and connects the blocks miners solve.
+pub struct StratumServer {
+    listener: TcpListener,
+    chain: Arc<Mutex<Blockchain>>,
+}
+
+impl StratumServer {
+    /// Bind a Stratum server of a proof-of-work chain to the address.
+    pub fn bind(chain: Blockchain, addr: &str) -> Result<Self> {
+        if chain.params().consensus != ConsensusParams::ProofOfWork {
+            return Err(StringError(format!(
+                "the chain runs on {}, which does not mine blocks",
+                chain.consensus().name()
+            )));
+        }
+        let listener = TcpListener::bind(addr)?;
+        Ok(StratumServer {
+            listener,
+            chain: Arc::new(Mutex::new(chain)),
+        })
+    }
+
+    /// Return the address the server is listening on.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept miners until the listener fails.
+    pub fn run(&self) {
+        info!(
+            "Stratum server listening on {:?}",
+            self.listener.local_addr()
+        );
+        for (id, stream) in self.listener.incoming().enumerate() {
+            match stream {
+                Ok(stream) => {
+                    let chain = self.chain.clone();
+                    let events = chain.lock().expect("lock chain").events().subscribe();
+                    thread::spawn(move || {
+                        let mut session = Session::new(format!("{:x}", id), chain, events);
+                        if let Err(e) = session.serve(stream) {
+                            info!("stratum connection closed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("failed to accept: {}", e),
+            }
+        }
+    }
+}
+
+/// The state of one miner connection.
+struct Session {
+    id: String,
+    chain: Arc<Mutex<Blockchain>>,
+    events: Receiver<ChainEvent>,
+    subscribed: bool,
+
+    /// The address that the blocks of the miner reward, set by `mining.authorize`.
+    address: Option<Address>,
+
+    /// The block templates of the jobs on the tip, by job id.
+    jobs: HashMap<String, Block>,
+    next_job: u64,
+}
+
+impl Session {
+    fn new(id: String, chain: Arc<Mutex<Blockchain>>, events: Receiver<ChainEvent>) -> Self {
+        Session {
+            id,
+            chain,
+            events,
+            subscribed: false,
+            address: None,
+            jobs: HashMap::new(),
+            next_job: 0,
+        }
+    }
+
+    /// Answer requests, and send a new job when the tip changes, until the miner disconnects.
+    fn serve(&mut self, mut stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = vec![];
+        loop {
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => return Ok(()),
+                // A timeout can split a line, so only handle whole ones.
+                Ok(_) if line.ends_with(b"\n") => {
+                    let request = std::mem::take(&mut line);
+                    self.handle(&mut stream, &request)?;
+                }
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e.into()),
+            }
+            let tip_changed = self.events.try_iter().any(|event| {
+                matches!(
+                    event,
+                    ChainEvent::BlockConnected { .. } | ChainEvent::BlockDisconnected { .. }
+                )
+            });
+            if tip_changed && self.subscribed && self.address.is_some() {
+                self.notify(&mut stream)?;
+            }
+        }
+    }
+
+    fn handle(&mut self, stream: &mut TcpStream, request: &[u8]) -> Result<()> {
+        let request: Value = match serde_json::from_slice(request) {
+            Ok(v) => v,
+            Err(e) => {
+                let error = (OTHER, format!("invalid request: {}", e));
+                return send(stream, &response(Value::Null, Err(error)));
+            }
+        };
+        let params = request["params"].as_array().cloned().unwrap_or_default();
+        let ready = self.subscribed && self.address.is_some();
+        let result = match request["method"].as_str() {
+            Some("mining.subscribe") => {
+                self.subscribed = true;
+                // No extranonce, the miner only iterates the nonce.
+                Ok(json!([[["mining.notify", self.id]], "", 0]))
+            }
+            Some("mining.authorize") => self.authorize(&params),
+            Some("mining.submit") => self.submit(&params),
+            _ => Err((OTHER, format!("unknown method {}", request["method"]))),
+        };
+        send(stream, &response(request["id"].clone(), result))?;
+        // The first job follows the response that makes the miner ready.
+        if !ready && self.subscribed && self.address.is_some() {
+            self.notify(stream)?;
+        }
+        Ok(())
+    }
+
+    fn authorize(&mut self, params: &[Value]) -> std::result::Result<Value, RpcError> {
+        let worker = params.first().and_then(Value::as_str).unwrap_or_default();
+        let network = self.chain.lock().expect("lock chain").network();
+        let address = Address::parse(worker, network).map_err(|e| (UNAUTHORIZED, e.to_string()))?;
+        self.address = Some(address);
+        Ok(json!(true))
+    }
+
+    fn submit(&mut self, params: &[Value]) -> std::result::Result<Value, RpcError> {
+        if !self.subscribed {
+            return Err((NOT_SUBSCRIBED, "not subscribed".to_owned()));
+        }
+        if self.address.is_none() {
+            return Err((UNAUTHORIZED, "not authorized".to_owned()));
+        }
+        let param = |i: usize| params.get(i).and_then(Value::as_str).unwrap_or_default();
+        let mut block = self
+            .jobs
+            .get(param(1))
+            .ok_or((STALE_JOB, format!("no job {}", param(1))))?
+            .clone();
+        block.nonce = u64::from_str_radix(param(2), 16)
+            .map_err(|_| (OTHER, format!("invalid nonce {}", param(2))))?;
+
+        let mut chain = self.chain.lock().expect("lock chain");
+        if block.pre_hash != chain.tip {
+            return Err((STALE_JOB, "the job is not on the tip".to_owned()));
+        }
+        let pow = ProofOfWork::new(block.header(), chain.params());
+        let internal = |e: crate::Error| (OTHER, e.to_string());
+        if !pow.validate().map_err(internal)? {
+            return Err((LOW_DIFFICULTY, "the hash is above the target".to_owned()));
+        }
+        block.hash = pow.hash().map_err(internal)?;
+        chain.connect_block(&block).map_err(internal)?;
+        // The miner learns the block is accepted, so it must survive a crash.
+        chain.flush().map_err(internal)?;
+        info!("Stratum miner {} found block {}", self.id, block.hash);
+        Ok(json!(true))
+    }
+
+    /// Send a job on the tip that rewards the address of the miner, and drop the older jobs.
+    fn notify(&mut self, stream: &mut TcpStream) -> Result<()> {
+        let address = self.address.as_ref().expect("an authorized miner");
+        let chain = self.chain.lock().expect("lock chain");
+        let block = chain.block_template(address)?;
+        let pow = ProofOfWork::new(block.header(), chain.params());
+        let job_id = format!("{:x}", self.next_job);
+        let params = json!([
+            job_id,
+            block.pre_hash,
+            hex_encode(&pow.work_data()?),
+            pow.target_hex(),
+            chain.params().pow_hash,
+            true,
+        ]);
+        drop(chain);
+        self.next_job += 1;
+        self.jobs.clear();
+        self.jobs.insert(job_id, block);
+        send(
+            stream,
+            &json!({ "id": null, "method": "mining.notify", "params": params }),
+        )
+    }
+}
+
+/// Return the JSON-RPC response to the request with the id.
+fn response(id: Value, result: std::result::Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "id": id, "result": result, "error": null }),
+        Err((code, message)) => json!({ "id": id, "result": null, "error": [code, message, null] }),
+    }
+}
+
+/// Send the message as one line of JSON.
+fn send(stream: &mut impl Write, message: &Value) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// A Stratum miner, the client side of [`StratumServer`].
+pub struct StratumMiner {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    worker: String,
+    next_id: u64,
+
+    /// The latest job that arrived while waiting for a response.
+    job: Option<Value>,
+}
+
+impl StratumMiner {
+    /// Connect to the server, subscribe, and authorize with the address to reward.
+    pub fn connect(addr: &str, address: &str) -> Result<Self> {
+        let writer = TcpStream::connect(addr)?;
+        let mut miner = StratumMiner {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+            worker: address.to_owned(),
+            next_id: 0,
+            job: None,
+        };
+        miner.call("mining.subscribe", json!([]))?;
+        miner.call("mining.authorize", json!([address, ""]))?;
+        Ok(miner)
+    }
+
+    /// Mine jobs until the server accepts `count` blocks, and return their hashes.
+    ///
+    /// A rejected block, for example one that another miner beat, is skipped.
+    pub fn mine(&mut self, count: usize) -> Result<Vec<String>> {
+        let mut hashes = vec![];
+        while hashes.len() < count {
+            let message = match self.job.take() {
+                Some(job) => job,
+                None => self.read()?,
+            };
+            if message["method"] != "mining.notify" {
+                continue;
+            }
+            let params = &message["params"];
+            let job_id = params[0].as_str().unwrap_or_default().to_owned();
+            let data = hex_decode(params[2].as_str().unwrap_or_default())?;
+            let target = hex_to_big_int(params[3].as_str().unwrap_or_default())?;
+            let pow_hash: PowHash = serde_json::from_value(params[4].clone())?;
+            let (nonce, hash) = solve(&data, &target, pow_hash)?;
+            let nonce = format!("{:x}", nonce);
+            match self.call("mining.submit", json!([self.worker, job_id, nonce])) {
+                Ok(_) => hashes.push(hash),
+                Err(e) => warn!("block {} rejected: {}", hash, e),
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// Send a request, and return its result, skipping the notifications before the response.
+    ///
+    /// A job that arrives meanwhile is kept for [`StratumMiner::mine`].
+    fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        send(
+            &mut self.writer,
+            &json!({ "id": id, "method": method, "params": params }),
+        )?;
+        loop {
+            let message = self.read()?;
+            if message["method"] == "mining.notify" {
+                self.job = Some(message);
+                continue;
+            }
+            if message["id"] != id {
+                continue;
+            }
+            return match message["error"].as_array() {
+                Some(error) => Err(StringError(format!(
+                    "{} failed: {}",
+                    method,
+                    error.get(1).and_then(Value::as_str).unwrap_or_default()
+                ))),
+                None => Ok(message["result"].clone()),
+            };
+        }
+    }
+
+    fn read(&mut self) -> Result<Value> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(StringError(
+                "the stratum server closed the connection".to_owned(),
+            ));
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+/// Return the first nonce whose hash of the work data meets the target, with the hash.
+pub fn solve(data: &[u8], target: &BigInt, pow_hash: PowHash) -> Result<(u64, String)> {
+    let mut data = data.to_vec();
+    let len = data.len();
+    for nonce in 0..u64::MAX {
+        data.truncate(len);
+        append_str(&mut data, &format!("{:x}", nonce));
+        let hash = pow_hash.hash(&data)?;
+        if hex_to_big_int(&hash)? < *target {
+            return Ok((nonce, hash));
+        }
+    }
+    Err(StringError("no nonce meets the target".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::MemoryEngine;
+    use crate::wallet::{Wallet, Wallets};
+    use crate::{ChainParams, Network};
+
+    #[test]
+    fn test_stratum() {
+        let mut params = ChainParams::regtest();
+        params.target_bits = 8;
+        params.genesis = None;
+        let address = Wallet::new().address_on(Network::Regtest);
+        let wallets = Wallets::with_engine(Arc::new(MemoryEngine::new())).unwrap();
+        let chain =
+            Blockchain::with_engine(Arc::new(MemoryEngine::new()), wallets, &address, params)
+                .unwrap();
+        let events = chain.events().subscribe();
+        let server = StratumServer::bind(chain, "127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        thread::spawn(move || server.run());
+
+        // The node connects the blocks that a miner solves.
+        let mut miner = StratumMiner::connect(&addr, &address).unwrap();
+        let mut other = StratumMiner::connect(&addr, &address).unwrap();
+        let hashes = miner.mine(2).unwrap();
+        assert_eq!(hashes.len(), 2);
+        for (height, hash) in (1..).zip(&hashes) {
+            let connected = events
+                .iter()
+                .find(|e| matches!(e, ChainEvent::BlockConnected { .. }))
+                .unwrap();
+            assert_eq!(
+                connected,
+                ChainEvent::BlockConnected {
+                    hash: hash.clone(),
+                    height,
+                }
+            );
+        }
+
+        // A nonce whose hash is above the target, and a stale job, are rejected.
+        let job = miner.job.take().unwrap_or_else(|| miner.read().unwrap());
+        let params = &job["params"];
+        let data = hex_decode(params[2].as_str().unwrap()).unwrap();
+        let target = hex_to_big_int(params[3].as_str().unwrap()).unwrap();
+        let nonce = (0u64..)
+            .find(|nonce| {
+                let mut data = data.clone();
+                append_str(&mut data, &format!("{:x}", nonce));
+                hex_to_big_int(&PowHash::Sha256.hash(&data).unwrap()).unwrap() >= target
+            })
+            .unwrap();
+        let submit = |job_id: &Value, nonce: u64| json!([address, job_id, format!("{:x}", nonce)]);
+        let e = miner
+            .call("mining.submit", submit(&params[0], nonce))
+            .unwrap_err();
+        assert!(e.to_string().ends_with("the hash is above the target"));
+        let e = miner
+            .call("mining.submit", submit(&json!("old"), 0))
+            .unwrap_err();
+        assert!(e.to_string().ends_with("no job old"));
+
+        // The other miner skips the jobs that went stale meanwhile.
+        assert_eq!(other.mine(1).unwrap().len(), 1);
+
+        // Proof-of-authority blocks are signed, not mined.
+        let wallets = Wallets::with_engine(Arc::new(MemoryEngine::new()))
+            .unwrap()
+            .on_network(Network::Regtest);
+        let authority = Wallet::new();
+        wallets.set(&authority).unwrap();
+        let authority = authority.address_on(Network::Regtest);
+        let mut params = ChainParams::regtest();
+        params.consensus = ConsensusParams::ProofOfAuthority {
+            authorities: vec![authority.clone()],
+        };
+        params.genesis = None;
+        let chain =
+            Blockchain::with_engine(Arc::new(MemoryEngine::new()), wallets, &authority, params)
+                .unwrap();
+        assert!(matches!(
+            StratumServer::bind(chain, "127.0.0.1:0"),
+            Err(StringError(e)) if e == "the chain runs on proof-of-authority, which does not mine blocks"
+        ));
+    }
+}
@@ -1,9 +1,11 @@
-use crate::common::{base58_decode, hash_str};
-use crate::error::Error;
-use crate::error::Error::{InvalidTransaction, NoEnoughBalance, StringError};
+use crate::common::hash_str;
+use crate::consensus::{is_valid_signature, sign_hash};
+use crate::error::Error::{
+    InvalidKey, InvalidTransaction, NoEnoughBalance, WalletNotFound, WatchOnly,
+};
 use crate::wallet::Wallet;
-use crate::Blockchain;
 use crate::Result;
+use crate::{Address, Blockchain, Network};
 use p256::ecdsa::signature::{Signer, Verifier};
 use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
 use p256::{PublicKey, SecretKey};
@@ -11,9 +13,6 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::str::{from_utf8, FromStr};
 
-/// The subsidy of mining a block.
-const SUBSIDY: i64 = 10;
-
 /// The output in a transaction.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TXOutput {
@@ -25,31 +24,22 @@ pub struct TXOutput {
 }
 
 impl TXOutput {
-    pub fn new(value: i64, address: &str) -> Result<Self> {
-        let pub_key_hash = Self::get_pub_key_hash(address)?;
-        let tx_out = TXOutput {
+    /// New an output locked with the address.
+    pub fn new(value: i64, address: &Address) -> Self {
+        TXOutput {
             value,
-            pub_key_hash,
-        };
-        Ok(tx_out)
+            pub_key_hash: address.pub_key_hash().to_vec(),
+        }
     }
 
-    fn get_pub_key_hash(address: &str) -> Result<Vec<u8>> {
-        let full_hash = base58_decode(address)?;
-        if full_hash.len() < 4 {
-            return Err(Error::StringError(
-                "lock with an invalid address.".to_owned(),
-            ));
-        }
-        // Take out the version and the checksum.
-        let pub_key_hash = full_hash[1..full_hash.len() - 4].to_vec();
-        Ok(pub_key_hash)
+    /// Lock the output with the address.
+    pub fn lock(&mut self, address: &Address) {
+        self.pub_key_hash = address.pub_key_hash().to_vec();
     }
 
-    pub fn lock(&mut self, address: &str) -> Result<()> {
-        let pub_key_hash = Self::get_pub_key_hash(address)?;
-        self.pub_key_hash = pub_key_hash;
-        Ok(())
+    /// Return the public key hash that locks the output.
+    pub fn pub_key_hash(&self) -> &[u8] {
+        &self.pub_key_hash
     }
 
     /// Check if provided public key hash was used to lock the output.
@@ -82,6 +72,11 @@ pub struct TXInput {
 }
 
 impl TXInput {
+    /// Return the signature of the input, coinbase inputs have none.
+    pub fn signature(&self) -> Option<&Signature> {
+        self.signature.as_ref()
+    }
+
     /// Check if someone with the public key hash can use the input.
     pub fn use_key(&self, pub_key_hash: &[u8]) -> bool {
         let locking_hash = Wallet::hash_pub_key(&self.public_key);
@@ -89,6 +84,79 @@ impl TXInput {
     }
 }
 
+/// A change to the authorities of a proof-of-authority chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AuthorityChange {
+    /// Add the authority with the address.
+    Add(String),
+
+    /// Remove the authority with the address.
+    Remove(String),
+}
+
+/// The change that a governance transaction makes, see [`crate::PoaConsensus`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Governance {
+    /// The change to the authorities.
+    pub change: AuthorityChange,
+
+    /// The height of the block that carries the transaction,
+    /// which keeps the ids of repeated changes unique.
+    pub height: u64,
+
+    /// The approvals of the authorities, a majority of those in effect at the height must approve.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub approvals: Vec<Approval>,
+}
+
+/// The signature of an authority that approves a governance change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Approval {
+    /// The public key of the authority.
+    pub public_key: String,
+
+    /// The signature of [`Governance::approval_hash`] in hex.
+    pub signature: String,
+}
+
+impl Governance {
+    /// New the change in the block at the height, with no approvals.
+    pub fn new(change: AuthorityChange, height: u64) -> Self {
+        Governance {
+            change,
+            height,
+            approvals: vec![],
+        }
+    }
+
+    /// Return the hash that authorities sign, which commits to the change and the height.
+    pub fn approval_hash(&self) -> Result<String> {
+        Ok(hash_str(ron::to_string(&(&self.change, self.height))?))
+    }
+
+    /// Return the approval of the change by the wallet.
+    pub fn approve(&self, wallet: &Wallet) -> Result<Approval> {
+        Ok(Approval {
+            public_key: wallet.public_key(),
+            signature: sign_hash(&self.approval_hash()?, wallet)?,
+        })
+    }
+
+    /// Return the address on the network of the authority that made the approval,
+    /// if its signature is valid.
+    pub fn approver(&self, approval: &Approval, network: Network) -> Result<Option<String>> {
+        if !is_valid_signature(
+            &approval.public_key,
+            &self.approval_hash()?,
+            &approval.signature,
+        ) {
+            return Ok(None);
+        }
+        let pub_key_hash = Wallet::hash_pub_key(approval.public_key.as_bytes());
+        Ok(Some(Address::new(network, pub_key_hash).to_string()))
+    }
+}
+
 /// Transaction.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Transaction {
@@ -100,11 +168,15 @@ pub struct Transaction {
 
     /// Outputs are where coins are actually stored.
     pub vout: Vec<TXOutput>,
+
+    /// The change of a governance transaction, which has no inputs or outputs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub governance: Option<Governance>,
 }
 
 impl Transaction {
-    /// New a coinbase transaction.
-    pub fn new_coinbase_tx(to: String, data: String) -> Self {
+    /// New a coinbase transaction that creates `subsidy` coins.
+    pub fn new_coinbase_tx(to: &Address, data: String, subsidy: i64) -> Result<Self> {
         let data = if data.is_empty() {
             format!("Reward to {}", to)
         } else {
@@ -119,32 +191,52 @@ impl Transaction {
             signature: None,
             public_key: data.into_bytes(),
         };
-        let tx_out = TXOutput::new(SUBSIDY, &to).unwrap();
-        println!("out: {:?}", tx_out);
+        let tx_out = TXOutput::new(subsidy, to);
 
         let mut tx = Transaction {
             id: String::new(),
             vin: vec![tx_in],
             vout: vec![tx_out],
+            governance: None,
         };
-        tx.set_id();
+        tx.set_id()?;
 
-        tx
+        Ok(tx)
+    }
+
+    /// New a governance transaction that changes the authorities.
+    ///
+    /// Only an authority can seal the block that carries it, and a majority
+    /// of the authorities must approve it, see [`Governance::approve`].
+    pub fn new_governance_tx(governance: Governance) -> Result<Self> {
+        let mut tx = Transaction {
+            id: String::new(),
+            vin: vec![],
+            vout: vec![],
+            governance: Some(governance),
+        };
+        tx.set_id()?;
+        Ok(tx)
     }
 
     /// New a normal transaction.
     pub fn new(from: &str, to: &str, amount: i64, blockchain: &Blockchain) -> Result<Self> {
+        let from_address = Address::parse(from, blockchain.network())?;
+        let to_address = Address::parse(to, blockchain.network())?;
+        let wallets = blockchain.wallets();
         let from_wallet = match blockchain.get_wallet(from)? {
             Some(v) => v,
-            None => return Err(StringError(format!("no such address: {}", from))),
+            None if wallets.is_watch_only(from)? => return Err(WatchOnly(from.to_owned())),
+            None => return Err(WalletNotFound(from.to_owned())),
         };
 
-        let _to_wallet = match blockchain.get_wallet(to)? {
-            Some(v) => v,
-            None => return Err(StringError(format!("no such address: {}", from))),
-        };
+        // Coins can be sent to a watch-only address, such as cold storage.
+        if blockchain.get_wallet(to)?.is_none() && !wallets.is_watch_only(to)? {
+            return Err(WalletNotFound(to.to_owned()));
+        }
         // Find all unspent outputs and ensure that they store enough value.
-        let (acc, outputs_idx) = blockchain.find_spendable_outputs(from, amount);
+        let (acc, outputs_idx) =
+            blockchain.find_spendable_outputs(from_address.pub_key_hash(), amount)?;
         if acc < amount {
             return Err(NoEnoughBalance);
         }
@@ -168,11 +260,11 @@ impl Transaction {
 
         // Build a list of outputs
         // Locked with the receiver address.This is the actual transferring of coins to other address.
-        let output = TXOutput::new(amount, to).unwrap();
+        let output = TXOutput::new(amount, &to_address);
         outputs.push(output);
         if acc > amount {
             // Locked with the sender address.This is a change.
-            let output = TXOutput::new(acc - amount, from).unwrap();
+            let output = TXOutput::new(acc - amount, &from_address);
             outputs.push(output);
         }
 
@@ -180,24 +272,36 @@ impl Transaction {
             id: String::new(),
             vin: inputs,
             vout: outputs,
+            governance: None,
         };
 
-        tx.set_id();
+        tx.set_id()?;
 
         // Here, we sign the transaction to guarantee
         // that one cannot spend coins belonging to someone else.
-        blockchain.sign_transaction(&mut tx, &from_wallet.private_key());
+        blockchain.sign_transaction(&mut tx, &from_wallet.private_key()?)?;
 
         Ok(tx)
     }
 
-    fn set_id(&mut self) {
-        self.id = self.hash();
+    /// Check that the id is the hash of the transaction.
+    pub(crate) fn has_valid_id(&self) -> Result<bool> {
+        let mut tx = self.clone();
+        tx.id = String::new();
+        for vin in &mut tx.vin {
+            vin.signature = None;
+        }
+        Ok(tx.hash()? == self.id)
+    }
+
+    fn set_id(&mut self) -> Result<()> {
+        self.id = self.hash()?;
+        Ok(())
     }
 
-    fn hash(&self) -> String {
-        let str = self.serialize().unwrap();
-        hash_str(str)
+    fn hash(&self) -> Result<String> {
+        let str = self.serialize()?;
+        Ok(hash_str(str))
     }
 
     /// Serialize a transaction to String.
@@ -218,33 +322,53 @@ impl Transaction {
     /// Public key hashes stored in new, locked outputs. This identifies "recipient" of a tx.
     ///
     /// We store the signature of the referenced output to the `signature` field in vin.
-    pub fn sign(&mut self, private_key: &str, prev_txs: HashMap<String, Transaction>) {
+    ///
+    /// `spent_outputs` maps `(tx_id, idx_vout)` of each input to the output it spends.
+    pub fn sign(
+        &mut self,
+        private_key: &str,
+        spent_outputs: &HashMap<(String, usize), TXOutput>,
+    ) -> Result<()> {
         // Nothing to do if a coinbase tx.
         if self.is_coinbase() {
-            return;
+            return Ok(());
         }
 
+        let secret_key = private_key
+            .parse::<SecretKey>()
+            .map_err(|e| InvalidKey(e.to_string()))?;
+        let signing_key: SigningKey = secret_key.into();
+
         // A trimmed clone will be signed, not a full transaction.
         let mut tx = self.trimmed_clone();
 
         for i in 0..tx.vin.len() {
-            let vin = tx.vin.get_mut(i).unwrap();
-            let prev_tx = match prev_txs.get(&vin.tx_id) {
-                Some(v) => v,
-                None => continue,
-            };
-            vin.signature = None;
+            tx.vin[i].signature = None;
             // All the inputs but the current one are empty.
-            vin.public_key = prev_tx.vout[vin.idx_vout].pub_key_hash.clone();
-            let hash = tx.hash();
+            tx.vin[i].public_key = Self::spent_output(&self.vin[i], spent_outputs)?
+                .pub_key_hash
+                .clone();
+            let hash = tx.hash()?;
             // Set empty for the next input.
             tx.vin[i].public_key = vec![];
 
-            let secret_key = private_key.parse::<SecretKey>().unwrap();
-            let signing_key: SigningKey = secret_key.into();
             let signature = signing_key.sign(hash.as_bytes());
             self.vin[i].signature = Some(signature);
         }
+        Ok(())
+    }
+
+    /// Return the output that the input spends.
+    fn spent_output<'a>(
+        input: &TXInput,
+        spent_outputs: &'a HashMap<(String, usize), TXOutput>,
+    ) -> Result<&'a TXOutput> {
+        spent_outputs
+            .get(&(input.tx_id.clone(), input.idx_vout))
+            .ok_or(InvalidTransaction(format!(
+                "no output {} in tx {}",
+                input.idx_vout, input.tx_id
+            )))
     }
 
     /// Verify the tx.
@@ -252,20 +376,18 @@ impl Transaction {
     /// We get the hash of previous transactions. The operation is just like `sign`.
     ///
     /// And compare it to the `signature` field in vin.
-    pub fn verify(&self, prev_txs: HashMap<String, Transaction>) -> Result<bool> {
+    pub fn verify(&self, spent_outputs: &HashMap<(String, usize), TXOutput>) -> Result<bool> {
         if self.is_coinbase() {
             return Ok(true);
         }
         let mut tx = self.trimmed_clone();
         for i in 0..self.vin.len() {
-            let prev_tx = match prev_txs.get(&self.vin[i].tx_id) {
-                Some(v) => v,
-                None => continue,
-            };
             tx.vin[i].signature = None;
-            tx.vin[i].public_key = prev_tx.vout[self.vin[i].idx_vout].pub_key_hash.clone();
+            tx.vin[i].public_key = Self::spent_output(&self.vin[i], spent_outputs)?
+                .pub_key_hash
+                .clone();
 
-            let hash = tx.hash();
+            let hash = tx.hash()?;
             tx.vin[i].public_key = vec![];
 
             let signature = match self.vin[i].signature.as_ref() {
@@ -279,7 +401,7 @@ impl Transaction {
             };
             let public_key = self.vin[i].public_key.clone();
             let str = from_utf8(&public_key)?;
-            let public_key = PublicKey::from_str(str).unwrap();
+            let public_key = PublicKey::from_str(str).map_err(|e| InvalidKey(e.to_string()))?;
             let verifying_key: VerifyingKey = public_key.into();
             if verifying_key.verify(hash.as_bytes(), signature).is_err() {
                 return Ok(false);
@@ -312,19 +434,7 @@ impl Transaction {
             id: String::new(),
             vin: inputs,
             vout: outputs,
+            governance: self.governance.clone(),
         }
     }
-
-    /// .
-    pub fn pub_key_hash_from_address(address: &str) -> Vec<u8> {
-        let full_hash = match base58_decode(address) {
-            Ok(v) => v,
-            Err(_) => return vec![],
-        };
-        if full_hash.len() < 4 {
-            return vec![];
-        }
-        // Take out the version and the checksum.
-        full_hash[1..full_hash.len() - 4].to_vec()
-    }
 }
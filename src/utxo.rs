@@ -0,0 +1,151 @@
+//! The set of unspent transaction outputs.
+
+use crate::common::hex_encode;
+use crate::engine::{Batch, Tree};
+use crate::transaction::{TXInput, TXOutput};
+use crate::wallet::Wallet;
+use crate::{Block, Result};
+use std::collections::HashMap;
+
+/// Index of the unspent outputs, kept up to date as blocks are connected.
+///
+/// Keys are `pub_key_hash/tx_id/idx_vout` so that a prefix scan returns the
+/// outputs of an address, and an input finds the output it spends by the
+/// hash of its public key.
+#[derive(Clone)]
+pub(crate) struct UtxoSet {
+    tree: Tree,
+}
+
+impl UtxoSet {
+    pub(crate) fn new(tree: Tree) -> Self {
+        UtxoSet { tree }
+    }
+
+    /// Spend the outputs that the block uses and add the ones it creates in the batch.
+    pub(crate) fn connect_block(&self, batch: &mut Batch, block: &Block) -> Result<()> {
+        for tx in &block.transactions {
+            if !tx.is_coinbase() {
+                for input in &tx.vin {
+                    let pub_key_hash = Wallet::hash_pub_key(&input.public_key);
+                    batch.delete(
+                        &self.tree,
+                        Self::key(&pub_key_hash, &input.tx_id, input.idx_vout),
+                    );
+                }
+            }
+            for (idx_vout, output) in tx.vout.iter().enumerate() {
+                self.add(batch, &tx.id, idx_vout, output)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add an unspent output in the batch.
+    pub(crate) fn add(
+        &self,
+        batch: &mut Batch,
+        tx_id: &str,
+        idx_vout: usize,
+        output: &TXOutput,
+    ) -> Result<()> {
+        batch.put(
+            &self.tree,
+            Self::key(output.pub_key_hash(), tx_id, idx_vout),
+            ron::to_string(output)?,
+        );
+        Ok(())
+    }
+
+    /// Undo [`UtxoSet::connect_block`].
+    ///
+    /// `spent_output` returns the output that an input spends.
+    pub(crate) fn disconnect_block(
+        &self,
+        batch: &mut Batch,
+        block: &Block,
+        spent_output: impl Fn(&TXInput) -> Result<TXOutput>,
+    ) -> Result<()> {
+        for tx in block.transactions.iter().rev() {
+            for (idx_vout, output) in tx.vout.iter().enumerate() {
+                batch.delete(
+                    &self.tree,
+                    Self::key(output.pub_key_hash(), &tx.id, idx_vout),
+                );
+            }
+            if !tx.is_coinbase() {
+                for input in &tx.vin {
+                    self.add(batch, &input.tx_id, input.idx_vout, &spent_output(input)?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the unspent outputs of the public key hash by transaction id.
+    pub(crate) fn find(
+        &self,
+        pub_key_hash: &[u8],
+    ) -> Result<HashMap<String, Vec<(usize, TXOutput)>>> {
+        let prefix = format!("{}/", hex_encode(pub_key_hash));
+        let mut utxo: HashMap<String, Vec<(usize, TXOutput)>> = HashMap::new();
+        for (k, v) in self.tree.scan_prefix(&prefix)? {
+            let (tx_id, idx_vout) = Self::outpoint(&k)?;
+            let output: TXOutput = ron::from_str(&v).map_err(|e| e.code)?;
+            utxo.entry(tx_id).or_default().push((idx_vout, output));
+        }
+        Ok(utxo)
+    }
+
+    /// Return the unspent output of a transaction locked with the public key hash.
+    pub(crate) fn get(
+        &self,
+        pub_key_hash: &[u8],
+        tx_id: &str,
+        idx_vout: usize,
+    ) -> Result<Option<TXOutput>> {
+        match self.tree.get(Self::key(pub_key_hash, tx_id, idx_vout))? {
+            Some(v) => Ok(Some(ron::from_str(&v).map_err(|e| e.code)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Return all unspent outputs as `(tx_id, idx_vout, output)`, in key order.
+    pub(crate) fn outputs(&self) -> Result<Vec<(String, usize, TXOutput)>> {
+        let mut outputs = vec![];
+        for (k, v) in self.tree.list()? {
+            let (tx_id, idx_vout) = Self::outpoint(&k)?;
+            let output: TXOutput = ron::from_str(&v).map_err(|e| e.code)?;
+            outputs.push((tx_id, idx_vout, output));
+        }
+        Ok(outputs)
+    }
+
+    /// Return the number of unspent outputs and the sum of their values.
+    pub(crate) fn stats(&self) -> Result<(u64, i64)> {
+        let mut count = 0;
+        let mut supply = 0;
+        for (_, v) in self.tree.scan_prefix("")? {
+            let output: TXOutput = ron::from_str(&v).map_err(|e| e.code)?;
+            count += 1;
+            supply += output.value;
+        }
+        Ok((count, supply))
+    }
+
+    fn key(pub_key_hash: &[u8], tx_id: &str, idx_vout: usize) -> String {
+        format!("{}/{}/{:010}", hex_encode(pub_key_hash), tx_id, idx_vout)
+    }
+
+    /// Return the transaction id and the output index of a key.
+    fn outpoint(key: &str) -> Result<(String, usize)> {
+        let invalid = || crate::error::Error::StringError(format!("invalid utxo key {}", key));
+        let mut parts = key.split('/').skip(1);
+        let tx_id = parts.next().ok_or_else(invalid)?;
+        let idx_vout = parts
+            .next()
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(invalid)?;
+        Ok((tx_id.to_owned(), idx_vout))
+    }
+}
@@ -0,0 +1,267 @@
+//! JSON views of the chain data.
+//!
+//! The stored types keep raw bytes around, these views render them
+//! as hex strings and addresses so the JSON stays stable and readable.
+
+use crate::common::hex_encode;
+use crate::transaction::{TXInput, TXOutput};
+use crate::wallet::Wallet;
+use crate::{Address, Block, Governance, HistoryEntry, Network, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// JSON view of a block.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlockView {
+    /// The hash of the block.
+    pub hash: String,
+
+    /// The hash of the previous block, empty for the genesis block.
+    pub pre_hash: String,
+
+    /// The height of the block, the genesis block is at height 0.
+    pub height: u64,
+
+    /// The hash of the transactions, which the block hash commits to.
+    pub tx_root: String,
+
+    /// The timestamp when the block was created.
+    pub timestamp: u64,
+
+    /// The nonce from Proof-of-Work mining.
+    pub nonce: u64,
+
+    /// The address of the validator that signed the block, `None` under proof of work.
+    pub validator: Option<String>,
+
+    /// The transactions in the block.
+    pub transactions: Vec<TransactionView>,
+}
+
+impl BlockView {
+    /// New a view of the block at the given height, with addresses on the network.
+    pub fn new(block: &Block, height: u64, network: Network) -> Self {
+        BlockView {
+            hash: block.hash.clone(),
+            pre_hash: block.pre_hash.clone(),
+            height,
+            tx_root: block.tx_root.clone(),
+            timestamp: block.timestamp,
+            nonce: block.nonce,
+            validator: (!block.validator.is_empty()).then(|| {
+                let pub_key_hash = Wallet::hash_pub_key(block.validator.as_bytes());
+                Address::new(network, pub_key_hash).to_string()
+            }),
+            transactions: block
+                .transactions
+                .iter()
+                .map(|tx| TransactionView::new(tx, network))
+                .collect(),
+        }
+    }
+}
+
+/// JSON view of a transaction.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TransactionView {
+    /// Transaction id.
+    pub id: String,
+
+    /// Whether it is a coinbase transaction.
+    pub coinbase: bool,
+
+    /// Inputs of the transaction.
+    pub vin: Vec<TXInputView>,
+
+    /// Outputs of the transaction.
+    pub vout: Vec<TXOutputView>,
+
+    /// The authority change of a governance transaction, absent for others.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub governance: Option<Governance>,
+}
+
+impl TransactionView {
+    /// New a view of the transaction, with addresses on the network.
+    pub fn new(tx: &Transaction, network: Network) -> Self {
+        TransactionView {
+            id: tx.id.clone(),
+            coinbase: tx.is_coinbase(),
+            vin: tx.vin.iter().map(Into::into).collect(),
+            vout: tx
+                .vout
+                .iter()
+                .map(|output| TXOutputView::new(output, network))
+                .collect(),
+            governance: tx.governance.clone(),
+        }
+    }
+}
+
+/// JSON view of a transaction input.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TXInputView {
+    /// The id of the transaction that the input references.
+    pub tx_id: String,
+
+    /// The index of the referenced output.
+    pub idx_vout: usize,
+
+    /// Hex encoded signature, `null` for coinbase inputs.
+    pub signature: Option<String>,
+
+    /// The public key of the spender, or the arbitrary data of a coinbase input.
+    pub public_key: String,
+}
+
+impl From<&TXInput> for TXInputView {
+    fn from(input: &TXInput) -> Self {
+        TXInputView {
+            tx_id: input.tx_id.clone(),
+            idx_vout: input.idx_vout,
+            signature: input.signature().map(|s| format!("{:x}", s)),
+            public_key: String::from_utf8_lossy(&input.public_key).into_owned(),
+        }
+    }
+}
+
+/// JSON view of a transaction output.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TXOutputView {
+    /// The number of coins.
+    pub value: i64,
+
+    /// The address that the output is locked with.
+    pub address: String,
+
+    /// Hex encoded public key hash that the output is locked with.
+    pub pub_key_hash: String,
+}
+
+impl TXOutputView {
+    /// New a view of the output, with the address on the network.
+    pub fn new(output: &TXOutput, network: Network) -> Self {
+        TXOutputView {
+            value: output.value,
+            address: Address::new(network, output.pub_key_hash().to_vec()).to_string(),
+            pub_key_hash: hex_encode(output.pub_key_hash()),
+        }
+    }
+}
+
+/// JSON view of an unspent transaction output.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UtxoView {
+    /// The id of the transaction that contains the output.
+    pub tx_id: String,
+
+    /// The index of the output in the transaction.
+    pub idx_vout: usize,
+
+    /// The output itself.
+    pub output: TXOutputView,
+}
+
+impl UtxoView {
+    /// New views of the unspent outputs by transaction id, in outpoint order.
+    pub fn list(utxo: HashMap<String, Vec<(usize, TXOutput)>>, network: Network) -> Vec<Self> {
+        let mut utxos = vec![];
+        for (tx_id, outputs) in utxo {
+            for (idx_vout, output) in outputs {
+                utxos.push(UtxoView {
+                    tx_id: tx_id.clone(),
+                    idx_vout,
+                    output: TXOutputView::new(&output, network),
+                });
+            }
+        }
+        utxos.sort_by(|a, b| (&a.tx_id, a.idx_vout).cmp(&(&b.tx_id, b.idx_vout)));
+        utxos
+    }
+}
+
+/// JSON view of the state of the chain.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChainInfoView {
+    /// The network that the chain runs on.
+    pub network: String,
+
+    /// The hash of the tip.
+    pub tip: String,
+
+    /// The height of the tip.
+    pub height: u64,
+
+    /// The sum of the work of all blocks, as a decimal string.
+    pub total_work: String,
+
+    /// The number of unspent outputs.
+    pub utxo_count: u64,
+
+    /// The coins held by the unspent outputs.
+    pub supply: i64,
+
+    /// The height of the last block whose body is pruned, `null` if none is.
+    pub pruned_height: Option<u64>,
+}
+
+/// JSON view of the UTXO set, like Bitcoin's `gettxoutsetinfo`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UtxoSetInfoView {
+    /// The height of the tip.
+    pub height: u64,
+
+    /// The hash of the tip.
+    pub tip: String,
+
+    /// The number of unspent outputs.
+    pub utxo_count: u64,
+
+    /// The coins held by the unspent outputs.
+    pub supply: i64,
+
+    /// The hash of the UTXO set, the same on every node with the same set.
+    pub hash: String,
+}
+
+/// JSON view of the balance of an address.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BalanceView {
+    /// The address.
+    pub address: String,
+
+    /// The sum of the unspent outputs of the address.
+    pub balance: i64,
+
+    /// Whether the address is watch-only.
+    pub watch_only: bool,
+}
+
+/// JSON view of the transaction history of an address.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryView {
+    /// The address.
+    pub address: String,
+
+    /// Whether the address is watch-only.
+    pub watch_only: bool,
+
+    /// The history entries, newest first.
+    pub history: Vec<HistoryEntry>,
+}
+
+/// JSON view of a wallet, it never contains the private key.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WalletView {
+    /// The address.
+    pub address: String,
+
+    /// The public key, `null` for a watch-only address known by its address only.
+    pub public_key: Option<String>,
+
+    /// Whether the address is watch-only.
+    pub watch_only: bool,
+
+    /// Whether the private key is encrypted.
+    pub encrypted: bool,
+}
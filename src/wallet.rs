@@ -1,24 +1,69 @@
 //! Wallet.
 
-use crate::common::{base58_encode, ripemd160_digest, sha256_digest};
-use crate::engine::SledEngine;
+use crate::address::{Address, ADDRESS_CHECKSUM_LEN};
+use crate::common::{
+    base58_decode, base58_encode, hex_decode, hex_encode, ripemd160_digest, sha256_digest,
+};
+use crate::config::wallets_path;
+use crate::crypto::{KeyDerivation, MasterKey, Sealed};
+use crate::engine::{Batch, SledEngine, StorageEngine, Tree};
+use crate::error::Error;
+use crate::error::Error::{InvalidKey, StringError, WalletLocked};
+use crate::hd::{ExtendedKey, HARDENED};
+use crate::migration::{self, rewrite_tree, Migration};
+use crate::params::Network;
 use crate::Result;
-use p256::pkcs8::EncodePrivateKey;
-use p256::SecretKey;
+use p256::pkcs8::{DecodePrivateKey, EncodePrivateKey};
+use p256::{PublicKey, SecretKey};
 use rand_core::OsRng;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::env::current_dir;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::{from_utf8, FromStr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Version for encoding a private key in the WIF-like format.
+const WIF_VERSION: u8 = 0x80;
+
+/// Formats to import and export private keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// PKCS#8 PEM.
+    Pem,
+    /// PKCS#8 DER.
+    Der,
+    /// Base58check of a version byte and the 32-byte key, like Bitcoin's WIF.
+    Wif,
+    /// Hex of the 32-byte key.
+    Hex,
+}
+
+impl FromStr for KeyFormat {
+    type Err = Error;
 
-/// Version for generate an address.
-const VERSION: u8 = 0x00;
-/// The len of checksum.
-const ADDRESS_CHECKSUM_LEN: usize = 4;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pem" => Ok(KeyFormat::Pem),
+            "der" => Ok(KeyFormat::Der),
+            "wif" => Ok(KeyFormat::Wif),
+            "hex" => Ok(KeyFormat::Hex),
+            _ => Err(InvalidKey(format!("unknown key format {}", s))),
+        }
+    }
+}
 
 /// Basic wallet.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Wallet {
     private_key: String,
     public_key: String,
+
+    /// The private key sealed with the master key of the wallets,
+    /// `private_key` is empty if it is set.
+    #[serde(default)]
+    encrypted_key: Option<Sealed>,
 }
 
 impl Wallet {
@@ -28,6 +73,7 @@ impl Wallet {
         Wallet {
             private_key,
             public_key,
+            encrypted_key: None,
         }
     }
 
@@ -36,6 +82,7 @@ impl Wallet {
         Wallet {
             private_key: String::from("empty"),
             public_key: String::from("empty"),
+            encrypted_key: None,
         }
     }
 
@@ -45,16 +92,119 @@ impl Wallet {
         let public_key = private_key.public_key();
         let private_key = private_key
             .to_pkcs8_pem(Default::default())
-            .unwrap()
+            .expect("a new key encodes to PEM")
             .to_string();
         let public_key = public_key.to_string();
 
         (private_key, public_key)
     }
 
+    /// New a wallet from an existing private key.
+    pub fn from_secret_key(secret_key: &SecretKey) -> Result<Self> {
+        let private_key = secret_key
+            .to_pkcs8_pem(Default::default())
+            .map_err(|e| StringError(e.to_string()))?
+            .to_string();
+        Ok(Wallet {
+            private_key,
+            public_key: secret_key.public_key().to_string(),
+            encrypted_key: None,
+        })
+    }
+
+    /// New a wallet from a private key in the format.
+    pub fn import_key(key: &[u8], format: KeyFormat) -> Result<Self> {
+        let invalid = |e: &dyn std::fmt::Display| InvalidKey(e.to_string());
+        let secret_key = match format {
+            KeyFormat::Pem => {
+                SecretKey::from_pkcs8_pem(from_utf8(key)?.trim()).map_err(|e| invalid(&e))?
+            }
+            KeyFormat::Der => SecretKey::from_pkcs8_der(key).map_err(|e| invalid(&e))?,
+            KeyFormat::Hex => SecretKey::from_slice(&hex_decode(from_utf8(key)?.trim())?)
+                .map_err(|e| invalid(&e))?,
+            KeyFormat::Wif => {
+                let payload = base58_decode(from_utf8(key)?.trim())?;
+                if payload.len() != 1 + 32 + ADDRESS_CHECKSUM_LEN {
+                    return Err(InvalidKey("invalid WIF length".to_owned()));
+                }
+                let (versioned, checksum) = payload.split_at(1 + 32);
+                if Self::checksum(versioned) != checksum {
+                    return Err(InvalidKey("invalid WIF checksum".to_owned()));
+                }
+                if versioned[0] != WIF_VERSION {
+                    return Err(InvalidKey(format!("invalid WIF version {}", versioned[0])));
+                }
+                SecretKey::from_slice(&versioned[1..]).map_err(|e| invalid(&e))?
+            }
+        };
+        Self::from_secret_key(&secret_key)
+    }
+
+    /// Export the private key in the format.
+    ///
+    /// Return `WalletLocked` if the private key is encrypted.
+    pub fn export_key(&self, format: KeyFormat) -> Result<Vec<u8>> {
+        let private_key = self.private_key()?;
+        let secret_key = private_key
+            .parse::<SecretKey>()
+            .map_err(|e| InvalidKey(e.to_string()))?;
+        let key = match format {
+            KeyFormat::Pem => private_key.into_bytes(),
+            KeyFormat::Der => secret_key
+                .to_pkcs8_der()
+                .map_err(|e| InvalidKey(e.to_string()))?
+                .as_bytes()
+                .to_vec(),
+            KeyFormat::Hex => hex_encode(&secret_key.to_bytes()).into_bytes(),
+            KeyFormat::Wif => {
+                let mut payload = vec![WIF_VERSION];
+                payload.extend(secret_key.to_bytes());
+                let checksum = Self::checksum(&payload);
+                payload.extend(checksum);
+                base58_encode(&payload).into_bytes()
+            }
+        };
+        Ok(key)
+    }
+
     /// Return private key.
-    pub fn private_key(&self) -> String {
-        self.private_key.clone()
+    ///
+    /// Return `WalletLocked` if the private key is encrypted.
+    pub fn private_key(&self) -> Result<String> {
+        if self.is_encrypted() {
+            return Err(WalletLocked);
+        }
+        Ok(self.private_key.clone())
+    }
+
+    /// Whether the private key is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted_key.is_some()
+    }
+
+    /// Seal the private key with the master key.
+    fn seal(&self, key: &MasterKey, address: &str) -> Result<Self> {
+        if self.is_encrypted() {
+            return Ok(self.clone());
+        }
+        Ok(Wallet {
+            private_key: String::new(),
+            public_key: self.public_key.clone(),
+            encrypted_key: Some(key.seal(self.private_key.as_bytes(), address.as_bytes())?),
+        })
+    }
+
+    /// Open the sealed private key with the master key.
+    fn open(&self, key: &MasterKey, address: &str) -> Result<Self> {
+        let Some(sealed) = &self.encrypted_key else {
+            return Ok(self.clone());
+        };
+        let private_key = key.open(sealed, address.as_bytes())?;
+        Ok(Wallet {
+            private_key: String::from_utf8(private_key)?,
+            public_key: self.public_key.clone(),
+            encrypted_key: None,
+        })
     }
 
     /// Return public key.
@@ -65,18 +215,17 @@ impl Wallet {
     /// Calculate an address that is a real Bitcoin address.
     /// We can even check its balance on https://blockchain.info/.
     pub fn address(&self) -> String {
-        let pub_key_hash = Self::hash_pub_key(self.public_key.as_bytes());
+        self.address_on(Network::Mainnet)
+    }
 
-        // Check sum.
-        let mut versioned_payload = vec![VERSION];
-        versioned_payload.extend(pub_key_hash.as_slice());
-        let checksum = Self::checksum(&versioned_payload);
+    /// Calculate the address on the network.
+    pub fn address_on(&self, network: Network) -> String {
+        Address::new(network, self.pub_key_hash()).to_string()
+    }
 
-        // `Version + pub_key_hash + checksum` and encode it with Base58
-        let mut full_payload = vec![VERSION];
-        full_payload.extend(pub_key_hash.as_slice());
-        full_payload.extend(checksum.as_slice());
-        base58_encode(&full_payload)
+    /// Encode a public key hash to a mainnet address.
+    pub fn address_from_pub_key_hash(pub_key_hash: &[u8]) -> String {
+        Address::new(Network::default(), pub_key_hash.to_vec()).to_string()
     }
 
     /// Get the public key hash.
@@ -92,7 +241,7 @@ impl Wallet {
     }
 
     /// Calculate the checksum by hashing the payload with `SHA256(SHA256(version + pub_key_hash))`.
-    fn checksum(payload: &[u8]) -> Vec<u8> {
+    pub(crate) fn checksum(payload: &[u8]) -> Vec<u8> {
         let first_sha = sha256_digest(payload);
         let second_sha = sha256_digest(first_sha.as_slice());
         second_sha[0..ADDRESS_CHECKSUM_LEN].to_vec()
@@ -111,6 +260,16 @@ impl Wallet {
     }
 }
 
+/// Never print the private key.
+impl fmt::Debug for Wallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Wallet")
+            .field("public_key", &self.public_key)
+            .field("encrypted", &self.is_encrypted())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Default for Wallet {
     fn default() -> Self {
         Self::new()
@@ -119,74 +278,453 @@ impl Default for Wallet {
 
 const WALLETS_TREE: &str = "wallets_tree";
 
+/// The isolated keyspace that stores the metadata of the wallets.
+const WALLETS_META_TREE: &str = "wallets_meta_tree";
+
+/// The tree of watch-only addresses.
+const WATCH_ONLY_TREE: &str = "watch_only_tree";
+
+/// The key that stores the key derivation parameters of encrypted wallets.
+const KEY_DERIVATION: &str = "key_derivation";
+
+/// The key that stores the seed of HD wallets.
+const HD_SEED: &str = "hd_seed";
+
+/// The key that stores the index of the next HD receive wallet.
+const HD_NEXT_RECEIVE: &str = "hd_next_receive";
+
+/// The key that stores the index of the next HD change wallet.
+const HD_NEXT_CHANGE: &str = "hd_next_change";
+
+/// The path of the HD account on the network, `m/44'/coin_type'/0'`.
+fn hd_account_path(network: Network) -> [u32; 3] {
+    [44 | HARDENED, network.hd_coin_type() | HARDENED, HARDENED]
+}
+
+/// The seed of HD wallets.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct HdSeed {
+    /// Hex encoded seed, empty if it is encrypted.
+    seed: String,
+    encrypted_seed: Option<Sealed>,
+}
+
+impl HdSeed {
+    fn seal(&self, key: &MasterKey) -> Result<Self> {
+        if self.encrypted_seed.is_some() {
+            return Ok(self.clone());
+        }
+        Ok(HdSeed {
+            seed: String::new(),
+            encrypted_seed: Some(key.seal(self.seed.as_bytes(), HD_SEED.as_bytes())?),
+        })
+    }
+
+    fn open(&self, key: &MasterKey) -> Result<Self> {
+        let Some(sealed) = &self.encrypted_seed else {
+            return Ok(self.clone());
+        };
+        Ok(HdSeed {
+            seed: String::from_utf8(key.open(sealed, HD_SEED.as_bytes())?)?,
+            encrypted_seed: None,
+        })
+    }
+}
+
+/// An address watched without its private key.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WatchOnly {
+    /// The public key, if it is known.
+    pub public_key: Option<String>,
+
+    /// The public key hash that locks the outputs of the address.
+    pub pub_key_hash: Vec<u8>,
+}
+
+impl WatchOnly {
+    /// Watch the address of a public key.
+    pub fn from_public_key(public_key: &str) -> Result<Self> {
+        PublicKey::from_str(public_key).map_err(|e| InvalidKey(e.to_string()))?;
+        Ok(WatchOnly {
+            public_key: Some(public_key.to_owned()),
+            pub_key_hash: Wallet::hash_pub_key(public_key.as_bytes()),
+        })
+    }
+
+    /// Watch an address, only its public key hash is known.
+    pub fn from_address(address: &Address) -> Self {
+        WatchOnly {
+            public_key: None,
+            pub_key_hash: address.pub_key_hash().to_vec(),
+        }
+    }
+
+    /// Return the mainnet address.
+    pub fn address(&self) -> String {
+        self.address_on(Network::Mainnet)
+    }
+
+    /// Return the address on the network.
+    pub fn address_on(&self, network: Network) -> String {
+        Address::new(network, self.pub_key_hash.clone()).to_string()
+    }
+}
+
 /// Wallets.
+///
+/// Once encrypted with a passphrase, private keys are stored sealed
+/// and can only be read while the wallets are unlocked.
+///
+/// Wallets are keyed by their addresses on the network, mainnet by default.
 pub struct Wallets {
-    wallets: SledEngine,
+    store: Arc<dyn StorageEngine>,
+    wallets: Tree,
+    meta: Tree,
+    watch_only: Tree,
+    network: Network,
+
+    /// The master key and when it expires.
+    unlocked: Mutex<Option<(MasterKey, Instant)>>,
 }
 
 impl Wallets {
-    /// New wallets in current directory.
+    /// New wallets in the wallet database of current directory.
     pub fn new() -> Result<Self> {
-        let wallets = SledEngine::new(WALLETS_TREE)?;
-        Ok(Wallets { wallets })
+        let db = sled::open(wallets_path(current_dir()?))?;
+        Self::with_db(&db)
     }
 
     /// New wallets with sled db.
-    pub fn with_db(db: &sled::Db) -> Self {
-        let wallets = SledEngine::with_db(WALLETS_TREE, db).unwrap();
-        Wallets { wallets }
+    pub fn with_db(db: &sled::Db) -> Result<Self> {
+        Self::with_engine(Arc::new(SledEngine::with_db(db.clone())))
+    }
+
+    /// New wallets in a storage engine, upgrading the database to the schema of this binary.
+    pub fn with_engine(store: Arc<dyn StorageEngine>) -> Result<Self> {
+        let wallets = store.open_tree(WALLETS_TREE)?;
+        let meta = store.open_tree(WALLETS_META_TREE)?;
+        let watch_only = store.open_tree(WATCH_ONLY_TREE)?;
+        migration::migrate(&*store, &meta, "wallet", &Self::migrations(&wallets))?;
+        Ok(Wallets {
+            store,
+            wallets,
+            meta,
+            watch_only,
+            network: Network::Mainnet,
+            unlocked: Mutex::new(None),
+        })
+    }
+
+    /// The migrations of the wallet database, in version order.
+    fn migrations(wallets: &Tree) -> Vec<Migration<'_>> {
+        vec![Migration::new(
+            1,
+            "rewrite the wallets in the current layout",
+            move || {
+                // Wallets written before encryption have no `encrypted_key`.
+                rewrite_tree(wallets, |_, v| {
+                    Ok(Some(Wallet::deserialize(v)?.serialize()?))
+                })
+            },
+        )]
+    }
+
+    /// Return the schema version of the wallet database.
+    pub fn schema_version(&self) -> Result<u64> {
+        migration::version(&self.meta)
+    }
+
+    /// Key the wallets by their addresses on the network.
+    pub fn on_network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
     }
 
-    /// New wallets with path.
-    pub fn with_path(path: impl Into<PathBuf>) -> Self {
-        let wallets = SledEngine::with_path(path, WALLETS_TREE).unwrap();
-        Wallets { wallets }
+    /// Return the network of the addresses.
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// New wallets with the path of the database.
+    pub fn with_path(path: impl Into<PathBuf>) -> Result<Self> {
+        let db = sled::open(path.into())?;
+        Self::with_db(&db)
+    }
+
+    /// New wallets in the wallet database of a network directory, see [`crate::config`].
+    pub fn with_datadir(network_dir: impl AsRef<Path>) -> Result<Self> {
+        Self::with_path(wallets_path(network_dir))
     }
 
     /// Set wallet.
     pub fn set(&self, wallet: &Wallet) -> Result<()> {
-        let address = wallet.address();
-        let serialized = wallet.serialize()?;
-        self.wallets.set(address, serialized)?;
-        Ok(())
+        self.set_with_address(&wallet.address_on(self.network), wallet)
     }
 
-    /// Set wallet with an address.
+    /// Set wallet with an address, which must be the address of the wallet.
+    ///
+    /// The private key is sealed if the wallets are encrypted,
+    /// so they must be unlocked.
     pub fn set_with_address(&self, address: &str, wallet: &Wallet) -> Result<()> {
-        let serialized = wallet.serialize()?;
-        self.wallets.set(address, serialized)?;
+        if wallet.address_on(self.network) != address {
+            return Err(InvalidKey(format!(
+                "the key belongs to {}, not {}",
+                wallet.address_on(self.network),
+                address
+            )));
+        }
+        let wallet = match self.key_derivation()? {
+            Some(_) => wallet.seal(&self.master_key()?, address)?,
+            None => wallet.clone(),
+        };
+        self.wallets.set(address, wallet.serialize()?)?;
         Ok(())
     }
 
     /// Get wallet.
+    ///
+    /// The private key is opened if the wallets are unlocked.
     pub fn get(&self, address: &str) -> Result<Option<Wallet>> {
         let wallet = match self.wallets.get(address)? {
             Some(v) => Wallet::deserialize(&v)?,
             None => return Ok(None),
         };
-        Ok(Some(wallet))
+        Ok(Some(self.open(address, wallet)?))
     }
 
     /// List wallets.
-    pub fn list(&self) -> Vec<(String, Wallet)> {
+    pub fn list(&self) -> Result<Vec<(String, Wallet)>> {
         let mut v = vec![];
-        let vec = self.wallets.list();
-        for (key, val) in vec {
-            let wallet = Wallet::deserialize(&val).unwrap();
+        for (key, val) in self.wallets.list()? {
+            let wallet = Wallet::deserialize(&val)?;
+            let wallet = self.open(&key, wallet)?;
             v.push((key, wallet));
         }
-        v
+        Ok(v)
+    }
+
+    /// Watch an address without its private key.
+    pub fn watch(&self, watch_only: &WatchOnly) -> Result<()> {
+        self.watch_only.set(
+            watch_only.address_on(self.network),
+            ron::to_string(watch_only)?,
+        )?;
+        Ok(())
+    }
+
+    /// Stop watching an address.
+    pub fn unwatch(&self, address: &str) -> Result<()> {
+        self.watch_only.remove(address)?;
+        Ok(())
+    }
+
+    /// Whether the address is watch-only.
+    pub fn is_watch_only(&self, address: &str) -> Result<bool> {
+        Ok(self.watch_only.get(address)?.is_some())
+    }
+
+    /// List watch-only addresses.
+    pub fn list_watch_only(&self) -> Result<Vec<(String, WatchOnly)>> {
+        let mut v = vec![];
+        for (address, val) in self.watch_only.list()? {
+            let watch_only: WatchOnly = ron::from_str(&val).map_err(|e| e.code)?;
+            v.push((address, watch_only));
+        }
+        Ok(v)
+    }
+
+    /// Whether the wallets are encrypted with a passphrase.
+    pub fn is_encrypted(&self) -> Result<bool> {
+        Ok(self.key_derivation()?.is_some())
+    }
+
+    /// Whether the wallets are encrypted and not unlocked.
+    pub fn is_locked(&self) -> Result<bool> {
+        Ok(self.is_encrypted()? && self.master_key().is_err())
+    }
+
+    /// Encrypt all private keys with the passphrase.
+    ///
+    /// The wallets stay locked afterwards.
+    pub fn encrypt(&self, passphrase: &str) -> Result<()> {
+        if self.is_encrypted()? {
+            return Err(StringError("wallets are already encrypted".to_owned()));
+        }
+        let (derivation, key) = KeyDerivation::new(passphrase)?;
+        self.reseal(&derivation, None, &key)
+    }
+
+    /// Unlock the wallets with the passphrase, they are locked again after the timeout.
+    pub fn unlock(&self, passphrase: &str, timeout: Duration) -> Result<()> {
+        let derivation = self
+            .key_derivation()?
+            .ok_or(StringError("wallets are not encrypted".to_owned()))?;
+        let key = derivation.derive(passphrase)?;
+        *self.unlocked.lock().expect("lock master key") = Some((key, Instant::now() + timeout));
+        Ok(())
+    }
+
+    /// Lock the wallets, forgetting the master key.
+    pub fn lock(&self) {
+        *self.unlocked.lock().expect("lock master key") = None;
+    }
+
+    /// Whether the wallets have a seed to derive HD wallets from.
+    pub fn has_seed(&self) -> Result<bool> {
+        Ok(self.hd_seed()?.is_some())
+    }
+
+    /// Set the seed that HD wallets are derived from.
+    ///
+    /// The seed is sealed if the wallets are encrypted, so they must be unlocked.
+    /// An existing seed is never replaced.
+    pub fn set_seed(&self, seed: &[u8]) -> Result<()> {
+        if self.has_seed()? {
+            return Err(StringError("wallets already have a seed".to_owned()));
+        }
+        let mut seed = HdSeed {
+            seed: hex_encode(seed),
+            encrypted_seed: None,
+        };
+        if self.is_encrypted()? {
+            seed = seed.seal(&self.master_key()?)?;
+        }
+        self.meta.set(HD_SEED, ron::to_string(&seed)?)?;
+        Ok(())
+    }
+
+    /// Derive the next receive or change wallet from the seed and store it.
+    ///
+    /// Wallets are derived along `m/44'/coin_type'/0'/change/index`,
+    /// with the coin type of the network, see [`crate::ChainParams::hd_coin_type`].
+    pub fn derive_next(&self, change: bool) -> Result<Wallet> {
+        let mut seed = self
+            .hd_seed()?
+            .ok_or(StringError("wallets have no seed".to_owned()))?;
+        if seed.encrypted_seed.is_some() {
+            seed = seed.open(&self.master_key()?)?;
+        }
+        let (counter, chain) = if change {
+            (HD_NEXT_CHANGE, 1)
+        } else {
+            (HD_NEXT_RECEIVE, 0)
+        };
+        let index: u32 = match self.meta.get(counter)? {
+            Some(v) => v
+                .parse()
+                .map_err(|_| StringError(format!("invalid index {}", v)))?,
+            None => 0,
+        };
+        let mut path = hd_account_path(self.network).to_vec();
+        path.extend([chain, index]);
+        let key = ExtendedKey::master(&hex_decode(&seed.seed)?)?.derive_path(&path)?;
+        let wallet = Wallet::from_secret_key(key.secret_key())?;
+        self.set(&wallet)?;
+        self.meta.set(counter, (index + 1).to_string())?;
+        Ok(wallet)
+    }
+
+    /// Re-encrypt all private keys with a new passphrase.
+    pub fn change_passphrase(&self, old: &str, new: &str) -> Result<()> {
+        let derivation = self
+            .key_derivation()?
+            .ok_or(StringError("wallets are not encrypted".to_owned()))?;
+        let old_key = derivation.derive(old)?;
+        let (derivation, new_key) = KeyDerivation::new(new)?;
+        self.lock();
+        self.reseal(&derivation, Some(&old_key), &new_key)
+    }
+
+    /// Open every private key and the seed with the old master key if given,
+    /// seal them with the new one, and store them with the key derivation in one transaction.
+    fn reseal(
+        &self,
+        derivation: &KeyDerivation,
+        old: Option<&MasterKey>,
+        new: &MasterKey,
+    ) -> Result<()> {
+        let mut sealed = vec![];
+        for (address, val) in self.wallets.list()? {
+            let mut wallet = Wallet::deserialize(&val)?;
+            if let Some(old) = old {
+                wallet = wallet.open(old, &address)?;
+            }
+            sealed.push((address.clone(), wallet.seal(new, &address)?.serialize()?));
+        }
+        let seed = match self.hd_seed()? {
+            Some(mut seed) => {
+                if let Some(old) = old {
+                    seed = seed.open(old)?;
+                }
+                Some(ron::to_string(&seed.seal(new)?)?)
+            }
+            None => None,
+        };
+        let derivation = ron::to_string(derivation)?;
+        let mut batch = Batch::new();
+        for (address, wallet) in sealed {
+            batch.put(&self.wallets, address, wallet);
+        }
+        if let Some(seed) = seed {
+            batch.put(&self.meta, HD_SEED, seed);
+        }
+        batch.put(&self.meta, KEY_DERIVATION, derivation);
+        self.store.apply_batch(&batch)
+    }
+
+    fn hd_seed(&self) -> Result<Option<HdSeed>> {
+        match self.meta.get(HD_SEED)? {
+            Some(v) => Ok(Some(ron::from_str(&v).map_err(|e| e.code)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn key_derivation(&self) -> Result<Option<KeyDerivation>> {
+        match self.meta.get(KEY_DERIVATION)? {
+            Some(v) => Ok(Some(ron::from_str(&v).map_err(|e| e.code)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Return the master key if the wallets are unlocked and it has not expired.
+    fn master_key(&self) -> Result<MasterKey> {
+        let mut unlocked = self.unlocked.lock().expect("lock master key");
+        match unlocked.as_ref() {
+            Some((key, expires)) if Instant::now() < *expires => Ok(key.clone()),
+            _ => {
+                *unlocked = None;
+                Err(WalletLocked)
+            }
+        }
+    }
+
+    fn open(&self, address: &str, wallet: Wallet) -> Result<Wallet> {
+        if !wallet.is_encrypted() {
+            return Ok(wallet);
+        }
+        match self.master_key() {
+            Ok(key) => wallet.open(&key, address),
+            Err(_) => Ok(wallet),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::wallet::Wallet;
+    use crate::address::Address;
+    use crate::engine::{MemoryEngine, StorageEngine, SCHEMA_VERSION};
+    use crate::error::Error::{UnsupportedSchema, WalletLocked};
+    use crate::hd::{generate_mnemonic, mnemonic_to_seed};
+    use crate::params::Network;
+    use crate::wallet::{KeyFormat, Wallet, Wallets, WatchOnly, WALLETS_TREE};
     use p256::ecdsa::signature::{Signer, Verifier};
     use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
     use p256::pkcs8::EncodePrivateKey;
     use p256::SecretKey;
     use rand_core::OsRng;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::TempDir;
 
     #[test]
     fn test_wallet() {
@@ -197,6 +735,196 @@ mod tests {
         println!("address: {}", address);
     }
 
+    #[test]
+    fn test_schema_version() {
+        let store = MemoryEngine::new();
+        let wallets = Wallets::with_engine(Arc::new(store.clone())).unwrap();
+        assert_eq!(wallets.schema_version().unwrap(), 1);
+        wallets.meta.set(SCHEMA_VERSION, "99").unwrap();
+        drop(wallets);
+
+        // A database written before versioning, with a wallet from before encryption.
+        let old = MemoryEngine::new();
+        let wallet = Wallet::new();
+        let record = format!(
+            "(private_key:{:?},public_key:{:?})",
+            wallet.private_key, wallet.public_key
+        );
+        old.open_tree(WALLETS_TREE)
+            .unwrap()
+            .set(wallet.address(), &record)
+            .unwrap();
+        let migrated = Wallets::with_engine(Arc::new(old)).unwrap();
+        assert_eq!(migrated.schema_version().unwrap(), 1);
+        assert_eq!(
+            migrated.wallets.get(wallet.address()).unwrap(),
+            Some(wallet.serialize().unwrap())
+        );
+        assert_eq!(
+            migrated
+                .get(&wallet.address())
+                .unwrap()
+                .unwrap()
+                .private_key()
+                .unwrap(),
+            wallet.private_key
+        );
+
+        assert!(matches!(
+            Wallets::with_engine(Arc::new(store)),
+            Err(UnsupportedSchema(name, 99, 1)) if name == "wallet"
+        ));
+    }
+
+    #[test]
+    fn test_encrypted_wallets() {
+        let temp_dir = TempDir::new().unwrap();
+        let wallets = Wallets::with_path(temp_dir.path()).unwrap();
+        let wallet = Wallet::new();
+        let address = wallet.address();
+        wallets.set(&wallet).unwrap();
+
+        wallets.encrypt("passphrase").unwrap();
+        assert!(wallets.is_locked().unwrap());
+        let locked = wallets.get(&address).unwrap().unwrap();
+        assert!(matches!(locked.private_key(), Err(WalletLocked)));
+        assert!(matches!(wallets.set(&Wallet::new()), Err(WalletLocked)));
+
+        assert!(wallets.unlock("wrong", Duration::from_secs(60)).is_err());
+        wallets
+            .unlock("passphrase", Duration::from_secs(60))
+            .unwrap();
+        let unlocked = wallets.get(&address).unwrap().unwrap();
+        assert_eq!(
+            unlocked.private_key().unwrap(),
+            wallet.private_key().unwrap()
+        );
+
+        wallets.unlock("passphrase", Duration::ZERO).unwrap();
+        assert!(wallets.is_locked().unwrap());
+
+        wallets.change_passphrase("passphrase", "new").unwrap();
+        assert!(wallets
+            .unlock("passphrase", Duration::from_secs(60))
+            .is_err());
+        wallets.unlock("new", Duration::from_secs(60)).unwrap();
+        let unlocked = wallets.get(&address).unwrap().unwrap();
+        assert_eq!(
+            unlocked.private_key().unwrap(),
+            wallet.private_key().unwrap()
+        );
+
+        wallets.lock();
+        assert!(wallets.is_locked().unwrap());
+    }
+
+    #[test]
+    fn test_hd_wallets() {
+        let temp_dir = TempDir::new().unwrap();
+        let wallets = Wallets::with_path(temp_dir.path()).unwrap();
+        let seed = mnemonic_to_seed(&generate_mnemonic().unwrap(), "").unwrap();
+        wallets.set_seed(&seed).unwrap();
+        assert!(wallets.set_seed(&seed).is_err());
+
+        let first = wallets.derive_next(false).unwrap();
+        let second = wallets.derive_next(false).unwrap();
+        let change = wallets.derive_next(true).unwrap();
+        assert_ne!(first.address(), second.address());
+        assert_ne!(first.address(), change.address());
+        assert!(wallets.get(&second.address()).unwrap().is_some());
+
+        wallets.encrypt("passphrase").unwrap();
+        assert!(matches!(wallets.derive_next(false), Err(WalletLocked)));
+
+        // The same seed derives the same wallets.
+        let restored_dir = TempDir::new().unwrap();
+        let restored = Wallets::with_path(restored_dir.path()).unwrap();
+        restored.set_seed(&seed).unwrap();
+        assert_eq!(
+            restored.derive_next(false).unwrap().address(),
+            first.address()
+        );
+
+        wallets
+            .unlock("passphrase", Duration::from_secs(60))
+            .unwrap();
+        let third = wallets.derive_next(false).unwrap();
+        assert_eq!(
+            restored.derive_next(false).unwrap().address(),
+            second.address()
+        );
+        assert_eq!(
+            restored.derive_next(false).unwrap().address(),
+            third.address()
+        );
+
+        // Mainnet and the test networks derive different keys from the same seed.
+        let testnet_dir = TempDir::new().unwrap();
+        let testnet = Wallets::with_path(testnet_dir.path())
+            .unwrap()
+            .on_network(Network::Testnet);
+        testnet.set_seed(&seed).unwrap();
+        assert_ne!(
+            testnet.derive_next(false).unwrap().pub_key_hash(),
+            first.pub_key_hash()
+        );
+    }
+
+    #[test]
+    fn test_import_and_export_key() {
+        let wallet = Wallet::new();
+        for format in [
+            KeyFormat::Pem,
+            KeyFormat::Der,
+            KeyFormat::Wif,
+            KeyFormat::Hex,
+        ] {
+            let key = wallet.export_key(format).unwrap();
+            let imported = Wallet::import_key(&key, format).unwrap();
+            assert_eq!(imported.address(), wallet.address());
+        }
+
+        let mut wif = wallet.export_key(KeyFormat::Wif).unwrap();
+        wif[10] = if wif[10] == b'2' { b'3' } else { b'2' };
+        assert!(Wallet::import_key(&wif, KeyFormat::Wif).is_err());
+        assert!(Wallet::import_key(b"00", KeyFormat::Hex).is_err());
+        assert!(!format!("{:?}", wallet).contains("PRIVATE KEY"));
+
+        let temp_dir = TempDir::new().unwrap();
+        let wallets = Wallets::with_path(temp_dir.path()).unwrap();
+        assert!(wallets
+            .set_with_address(&Wallet::new().address(), &wallet)
+            .is_err());
+        wallets
+            .set_with_address(&wallet.address(), &wallet)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_watch_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let wallets = Wallets::with_path(temp_dir.path()).unwrap();
+        let wallet = Wallet::new();
+        let address = wallet.address();
+
+        let by_key = WatchOnly::from_public_key(&wallet.public_key()).unwrap();
+        let by_address =
+            WatchOnly::from_address(&Address::parse(&address, Network::Mainnet).unwrap());
+        assert_eq!(by_key.address(), address);
+        assert_eq!(by_key.pub_key_hash, by_address.pub_key_hash);
+        assert!(WatchOnly::from_public_key("not a key").is_err());
+
+        wallets.watch(&by_address).unwrap();
+        assert!(wallets.is_watch_only(&address).unwrap());
+        assert!(wallets.get(&address).unwrap().is_none());
+        assert_eq!(
+            wallets.list_watch_only().unwrap(),
+            vec![(address.clone(), by_address)]
+        );
+        wallets.unwatch(&address).unwrap();
+        assert!(!wallets.is_watch_only(&address).unwrap());
+    }
+
     #[test]
     fn test_key() {
         let secret_key = SecretKey::random(&mut OsRng);
@@ -1,7 +1,12 @@
+// The original tests pass their arguments as `&[..]`.
+#![allow(clippy::needless_borrows_for_generic_args)]
+
 use assert_cmd::cargo::CommandCargoExt;
 use assert_cmd::prelude::*;
+use predicates::boolean::PredicateBooleanExt;
 use predicates::str::contains;
 use rchain::wallet::{Wallet, Wallets};
+use rchain::{Address, Network};
 use std::process::Command;
 use tempfile::TempDir;
 
@@ -15,7 +20,29 @@ fn cli_ls_chain() {
         .args(&["ls"])
         .current_dir(&temp_dir)
         .assert()
+        .code(1)
+        .stderr(contains("error: no chain found").and(contains("panicked").not()));
+    // Listing creates neither a chain nor a wallet.
+    assert!(!temp_dir.path().join("chain").exists());
+    assert!(Wallets::with_datadir(temp_dir.path())
+        .unwrap()
+        .list()
+        .unwrap()
+        .is_empty());
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["create-blockchain", INIT_ADDRESS])
+        .current_dir(&temp_dir)
+        .assert()
         .success();
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["ls"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("pow: true"));
 }
 
 #[test]
@@ -66,14 +93,30 @@ fn cli_balance() {
         .args(&["balance", &address])
         .current_dir(&temp_dir)
         .assert()
-        .stdout(contains("10"));
+        .failure()
+        .stderr(contains("error: no chain found"));
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["create-blockchain", &address])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["balance", &address])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("balance: 10"));
 
     Command::cargo_bin("rchain")
         .unwrap()
         .args(&["balance", "who"])
         .current_dir(&temp_dir)
         .assert()
-        .stdout(contains("0"));
+        .failure()
+        .stderr(contains("error: invalid address who"));
 }
 
 #[test]
@@ -84,7 +127,7 @@ fn cli_send() {
     let address_1;
     let address_2;
     {
-        let wallets = Wallets::with_path(temp_dir.path());
+        let wallets = Wallets::with_datadir(temp_dir.path()).unwrap();
 
         let init_wallet = Wallet::new();
         init_address = init_wallet.address();
@@ -147,7 +190,7 @@ fn cli_send_no_enough() {
     let init_address;
     let address_1;
     {
-        let wallets = Wallets::with_path(temp_dir.path());
+        let wallets = Wallets::with_datadir(temp_dir.path()).unwrap();
 
         let init_wallet = Wallet::new();
         init_address = init_wallet.address();
@@ -170,5 +213,1144 @@ fn cli_send_no_enough() {
         .args(&["send", &init_address, &address_1, "15"])
         .current_dir(&temp_dir)
         .assert()
-        .stderr(contains("NoEnoughBalance"));
+        .stderr(contains("error: no enough balance"));
+}
+
+#[test]
+fn cli_history() {
+    let temp_dir = TempDir::new().unwrap();
+    let init_address;
+    let address_1;
+    {
+        let wallets = Wallets::with_datadir(temp_dir.path()).unwrap();
+
+        let init_wallet = Wallet::new();
+        init_address = init_wallet.address();
+        wallets.set(&init_wallet).unwrap();
+
+        let wallet1 = Wallet::new();
+        address_1 = wallet1.address();
+        wallets.set(&wallet1).unwrap();
+    }
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["create-blockchain", &init_address])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["send", &init_address, &address_1, "3"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["history", &init_address])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("height: 2").and(contains("Sent: 10")))
+        .stdout(contains("Received: 7"))
+        .stdout(contains("height: 1"));
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["history", &init_address, "--limit", "1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("height: 1").not());
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["history", &address_1])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("Received: 3"));
+}
+
+#[test]
+fn cli_encrypted_wallet() {
+    let temp_dir = TempDir::new().unwrap();
+    let init_address;
+    let address_1;
+    {
+        let wallets = Wallets::with_datadir(temp_dir.path()).unwrap();
+
+        let init_wallet = Wallet::new();
+        init_address = init_wallet.address();
+        wallets.set(&init_wallet).unwrap();
+
+        let wallet1 = Wallet::new();
+        address_1 = wallet1.address();
+        wallets.set(&wallet1).unwrap();
+    }
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["create-blockchain", &init_address])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["encrypt-wallet"])
+        .env("RCHAIN_PASSPHRASE", "passphrase")
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["send", &init_address, &address_1, "5"])
+        .env("RCHAIN_PASSPHRASE", "wrong")
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("error: invalid passphrase"));
+
+    // The Stratum server unlocks the wallets at startup only with --unlock-timeout.
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args([
+            "stratum-server",
+            "--bind",
+            "127.0.0.1:0",
+            "--unlock-timeout",
+            "600",
+        ])
+        .env("RCHAIN_PASSPHRASE", "wrong")
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("error: invalid passphrase"));
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["change-passphrase"])
+        .env("RCHAIN_PASSPHRASE", "passphrase")
+        .env("RCHAIN_NEW_PASSPHRASE", "new")
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["send", &init_address, &address_1, "5"])
+        .env("RCHAIN_PASSPHRASE", "new")
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["balance", &address_1])
+        .current_dir(&temp_dir)
+        .assert()
+        .stdout(contains("5"));
+}
+
+#[test]
+fn cli_mnemonic_wallet() {
+    let temp_dir = TempDir::new().unwrap();
+    let output = Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["create-wallet", "--mnemonic"])
+        .current_dir(&temp_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let field = |name: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .unwrap()
+            .to_owned()
+    };
+    let mnemonic = field("mnemonic: ");
+    let address = field("address: ");
+
+    let next = Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["create-wallet"])
+        .current_dir(&temp_dir)
+        .output()
+        .unwrap();
+    let next = String::from_utf8(next.stdout).unwrap();
+    assert!(!next.contains(&address));
+
+    let restored_dir = TempDir::new().unwrap();
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["restore-wallet", &mnemonic, "--count", "2"])
+        .current_dir(&restored_dir)
+        .assert()
+        .success()
+        .stdout(contains(address.as_str()))
+        .stdout(contains(next.trim()));
+
+    let invalid_dir = TempDir::new().unwrap();
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["restore-wallet", "not a mnemonic"])
+        .current_dir(&invalid_dir)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn cli_import_export_key() {
+    let temp_dir = TempDir::new().unwrap();
+    let wallet = Wallet::new();
+    let address = wallet.address();
+    Wallets::with_datadir(temp_dir.path())
+        .unwrap()
+        .set(&wallet)
+        .unwrap();
+
+    let output = Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["export-key", &address, "--format", "wif"])
+        .current_dir(&temp_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let wif = String::from_utf8(output.stdout).unwrap().trim().to_owned();
+
+    let der_file = temp_dir.path().join("key.der");
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["export-key", &address, "--format", "der", "--file"])
+        .arg(&der_file)
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["wallets"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains(address.as_str()))
+        .stdout(contains("PRIVATE KEY").not());
+
+    let import_dir = TempDir::new().unwrap();
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["import-key", &wif])
+        .current_dir(&import_dir)
+        .assert()
+        .success()
+        .stdout(contains(address.as_str()));
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["import-key", "--format", "der", "--file"])
+        .arg(&der_file)
+        .args(["--address", &address])
+        .current_dir(&import_dir)
+        .assert()
+        .success();
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["import-key", &wif, "--address", &Wallet::new().address()])
+        .current_dir(&import_dir)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn cli_watch_only() {
+    let temp_dir = TempDir::new().unwrap();
+    let init_address;
+    let cold_address;
+    {
+        let wallets = Wallets::with_datadir(temp_dir.path()).unwrap();
+        let init_wallet = Wallet::new();
+        init_address = init_wallet.address();
+        wallets.set(&init_wallet).unwrap();
+        cold_address = Wallet::new().address();
+    }
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["watch", &cold_address])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["create-blockchain", &init_address])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["send", &init_address, &cold_address, "3"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["balance"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains(format!("address: {}, balance: 7", init_address)))
+        .stdout(contains(format!(
+            "address: {} (watch-only), balance: 3",
+            cold_address
+        )));
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["history"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains(format!("address: {} (watch-only)", cold_address)))
+        .stdout(contains("Received: 3"));
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["send", &cold_address, &init_address, "1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("is watch-only"));
+}
+
+#[test]
+fn cli_regtest_generate() {
+    let temp_dir = TempDir::new().unwrap();
+    let address = Address::new(Network::Regtest, Wallet::new().pub_key_hash()).to_string();
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["generate", "2", &address, "--network", "regtest"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+    assert!(temp_dir.path().join("regtest").exists());
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["balance", &address, "--network", "regtest"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("balance: 20"));
+
+    // Mainnet neither accepts regtest addresses nor generates blocks on demand.
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["balance", &address])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure();
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["generate", "1", INIT_ADDRESS])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn cli_fixed_genesis() {
+    let dir = TempDir::new().unwrap();
+    let rchain = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest", "--output", "json"])
+            .current_dir(&dir);
+        cmd
+    };
+
+    // Every node of a network starts from the same genesis block.
+    rchain(&["create-blockchain"]).assert().success();
+    let genesis = rchain(&["get-block", "0"]).output().unwrap();
+    let genesis: serde_json::Value = serde_json::from_slice(&genesis.stdout).unwrap();
+    assert_eq!(
+        genesis["hash"],
+        rchain::ChainParams::regtest().genesis.unwrap().hash
+    );
+
+    // A chain with a hash function of its own mints its own genesis block.
+    let custom = TempDir::new().unwrap();
+    std::fs::write(custom.path().join("rchain.toml"), "pow_hash = \"blake3\"\n").unwrap();
+    let mut cmd = rchain(&["create-blockchain"]);
+    cmd.current_dir(&custom)
+        .assert()
+        .code(1)
+        .stderr(contains("an address is required"));
+}
+
+#[test]
+fn cli_datadir_and_config() {
+    let work_dir = TempDir::new().unwrap();
+    let datadir = TempDir::new().unwrap();
+    let address = Address::new(Network::Regtest, Wallet::new().pub_key_hash()).to_string();
+    std::fs::write(
+        datadir.path().join("rchain.toml"),
+        format!("network = \"regtest\"\nmining_address = \"{}\"\n", address),
+    )
+    .unwrap();
+
+    // The network and the mining address come from the config.
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["generate", "1", "--datadir"])
+        .arg(datadir.path())
+        .current_dir(&work_dir)
+        .assert()
+        .success();
+    assert!(datadir.path().join("regtest").join("chain").exists());
+    assert!(datadir.path().join("regtest").join("wallets").exists());
+    assert!(!work_dir.path().join("regtest").exists());
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["balance", &address])
+        .env("RCHAIN_DATADIR", datadir.path())
+        .current_dir(&work_dir)
+        .assert()
+        .success()
+        .stdout(contains("balance: 10"));
+
+    // The environment overrides the config, and the flag overrides the environment.
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["balance", &address])
+        .env("RCHAIN_DATADIR", datadir.path())
+        .env("RCHAIN_NETWORK", "mainnet")
+        .current_dir(&work_dir)
+        .assert()
+        .failure();
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["balance", &address, "--network", "regtest"])
+        .env("RCHAIN_DATADIR", datadir.path())
+        .env("RCHAIN_NETWORK", "mainnet")
+        .current_dir(&work_dir)
+        .assert()
+        .success();
+}
+
+#[test]
+fn cli_json_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let output = Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["create-wallet", "--output", "json"])
+        .current_dir(&temp_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let wallet: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let address = wallet["address"].as_str().unwrap().to_owned();
+    assert!(wallet["mnemonic"].is_null());
+
+    let output = Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["create-blockchain", &address, "--output", "json"])
+        .current_dir(&temp_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let blocks: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    // The fixed genesis block rewards nobody, so the first block rewards the address.
+    assert_eq!(blocks.as_array().unwrap().len(), 2);
+    assert_eq!(blocks[0]["height"], 1);
+    assert_eq!(blocks[0]["transactions"][0]["coinbase"], true);
+    assert_eq!(blocks[1]["height"], 0);
+
+    let output = Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["balance", "--output", "json"])
+        .current_dir(&temp_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let balances: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(balances[0]["address"], address.as_str());
+    assert_eq!(balances[0]["balance"], 10);
+    assert_eq!(balances[0]["watch_only"], false);
+
+    let output = Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["wallets", "--output", "json"])
+        .current_dir(&temp_dir)
+        .output()
+        .unwrap();
+    let wallets: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(wallets[0]["address"], address.as_str());
+    assert_eq!(wallets[0]["encrypted"], false);
+    assert!(wallets[0].get("private_key").is_none());
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["wallets", "--output", "yaml"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn cli_inspect_chain() {
+    let temp_dir = TempDir::new().unwrap();
+    let address = Address::new(Network::Regtest, Wallet::new().pub_key_hash()).to_string();
+    let json = |args: &[&str]| -> serde_json::Value {
+        let output = Command::cargo_bin("rchain")
+            .unwrap()
+            .args(args)
+            .args(["--network", "regtest", "--output", "json"])
+            .current_dir(&temp_dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    let hashes = json(&["generate", "2", &address]);
+    let info = json(&["chain-info"]);
+    assert_eq!(info["tip"], hashes["hashes"][1]);
+    assert_eq!(info["height"], 2);
+    assert_eq!(info["total_work"], "3");
+    assert_eq!(info["utxo_count"], 3);
+    assert_eq!(info["supply"], 30);
+
+    let block = json(&["get-block", "1"]);
+    assert_eq!(block["hash"], hashes["hashes"][0]);
+    let hash = block["hash"].as_str().unwrap();
+    assert_eq!(json(&["get-block", hash])["height"], 1);
+
+    let tx_id = block["transactions"][0]["id"].as_str().unwrap();
+    let tx = json(&["get-tx", tx_id]);
+    assert_eq!(tx["block_hash"], hash);
+    assert_eq!(tx["transaction"]["vout"][0]["address"], address.as_str());
+
+    let utxos = json(&["get-utxos", &address]);
+    // The fixed genesis block rewards nobody.
+    assert_eq!(utxos["balance"], 20);
+    assert_eq!(utxos["utxos"].as_array().unwrap().len(), 2);
+
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["chain-info", "--network", "regtest"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("height: 2").and(contains("supply: 30")));
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["get-tx", tx_id, "--network", "regtest"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(
+            contains(format!(
+                "tx: {}\nblock: {}\nheight: 1\ncoinbase: ",
+                tx_id, hash
+            ))
+            .and(contains(format!("output: 10 to {}", address))),
+        );
+    Command::cargo_bin("rchain")
+        .unwrap()
+        .args(["get-block", "9", "--network", "regtest"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn cli_export_and_import_chain() {
+    let (source, target) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let archive = source.path().join("chain.rchn");
+    let address = Address::new(Network::Regtest, Wallet::new().pub_key_hash()).to_string();
+    let rchain = |dir: &TempDir, args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest"])
+            .current_dir(dir);
+        cmd
+    };
+
+    rchain(&source, &["generate", "3", &address])
+        .assert()
+        .success();
+    rchain(&source, &["export-chain", archive.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(contains("exported 4 blocks"));
+    rchain(&target, &["import-chain", archive.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(contains("imported 4 blocks"));
+    let info = |dir| {
+        rchain(dir, &["chain-info", "--output", "json"])
+            .output()
+            .unwrap()
+            .stdout
+    };
+    assert_eq!(info(&source), info(&target));
+
+    // A corrupt archive is rejected before any block is connected.
+    let mut data = std::fs::read(&archive).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    let corrupt = target.path().join("corrupt.rchn");
+    std::fs::write(&corrupt, data).unwrap();
+    let empty = TempDir::new().unwrap();
+    rchain(&empty, &["import-chain", corrupt.to_str().unwrap()])
+        .assert()
+        .code(1)
+        .stderr(contains("error: invalid archive: checksum mismatch"));
+    assert!(!empty.path().join("regtest").join("chain").exists());
+}
+
+#[test]
+fn cli_prune() {
+    let temp_dir = TempDir::new().unwrap();
+    let address = Address::new(Network::Regtest, Wallet::new().pub_key_hash()).to_string();
+    let rchain = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest"])
+            .current_dir(&temp_dir);
+        cmd
+    };
+
+    rchain(&["generate", "3", &address, "--prune", "2"])
+        .assert()
+        .success();
+    let output = rchain(&["chain-info", "--output", "json"])
+        .output()
+        .unwrap();
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(info["pruned_height"], 1);
+    assert_eq!(info["utxo_count"], 4);
+
+    rchain(&["get-block", "1"])
+        .assert()
+        .code(1)
+        .stderr(contains("is pruned, only its header is kept"));
+    rchain(&["get-block", "2"]).assert().success();
+    let output = rchain(&["ls", "--output", "json"]).output().unwrap();
+    let blocks: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(blocks.as_array().unwrap().len(), 2);
+    rchain(&["export-chain", "chain.rchn"])
+        .assert()
+        .code(1)
+        .stderr(contains("is pruned"));
+}
+
+#[test]
+fn cli_utxo_snapshot() {
+    let (source, target) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let snapshot = source.path().join("utxos.rutx");
+    let archive = source.path().join("chain.rchn");
+    let address = Address::new(Network::Regtest, Wallet::new().pub_key_hash()).to_string();
+    let rchain = |dir: &TempDir, args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest", "--output", "json"])
+            .current_dir(dir);
+        cmd
+    };
+    let json = |cmd: &mut Command| -> serde_json::Value {
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    rchain(&source, &["generate", "3", &address])
+        .assert()
+        .success();
+    let dumped = json(&mut rchain(
+        &source,
+        &["dump-utxos", snapshot.to_str().unwrap(), "--height", "2"],
+    ));
+    assert_eq!(dumped["height"], 2);
+    assert_eq!(dumped["utxo_count"], 3);
+
+    rchain(&target, &["load-utxos", snapshot.to_str().unwrap()])
+        .assert()
+        .code(2)
+        .stderr(contains("--hash <HASH>"));
+    rchain(
+        &target,
+        &["load-utxos", snapshot.to_str().unwrap(), "--hash", "00"],
+    )
+    .assert()
+    .code(1)
+    .stderr(contains("invalid snapshot: the UTXO set hash is"));
+    let hash = dumped["hash"].as_str().unwrap();
+    let loaded = json(&mut rchain(
+        &target,
+        &["load-utxos", snapshot.to_str().unwrap(), "--hash", hash],
+    ));
+    assert_eq!(loaded["tip"], dumped["tip"]);
+    assert_eq!(json(&mut rchain(&target, &["utxo-set-info"]))["hash"], hash);
+
+    // The rest of the chain is validated on top of the snapshot.
+    rchain(&source, &["export-chain", archive.to_str().unwrap()])
+        .assert()
+        .success();
+    rchain(&target, &["import-chain", archive.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(
+        json(&mut rchain(&source, &["utxo-set-info"])),
+        json(&mut rchain(&target, &["utxo-set-info"]))
+    );
+}
+
+#[test]
+fn cli_migrate() {
+    let (source, target) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let address = Address::new(Network::Regtest, Wallet::new().pub_key_hash()).to_string();
+    let rchain = |dir: &TempDir, args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest", "--output", "json"])
+            .current_dir(dir);
+        cmd
+    };
+    let json = |cmd: &mut Command| -> serde_json::Value {
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    rchain(&target, &["migrate"])
+        .assert()
+        .code(1)
+        .stderr(contains("no chain found"));
+    rchain(&source, &["generate", "2", &address])
+        .assert()
+        .success();
+    let migrated = json(&mut rchain(&source, &["migrate"]));
+    assert_eq!(migrated["chain_version"], 2);
+    assert_eq!(migrated["wallets_version"], 1);
+
+    let copy_to = target.path().to_str().unwrap();
+    let copied = json(&mut rchain(&source, &["migrate", "--copy-to", copy_to]));
+    assert_eq!(copied["chain_version"], 2);
+    assert_eq!(
+        json(&mut rchain(&source, &["utxo-set-info"])),
+        json(&mut rchain(&target, &["utxo-set-info"]))
+    );
+    rchain(&source, &["migrate", "--copy-to", copy_to])
+        .assert()
+        .code(1)
+        .stderr(contains("already has a chain"));
+}
+
+#[test]
+fn cli_proof_of_stake() {
+    let dir = TempDir::new().unwrap();
+    let rchain = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest", "--output", "json"])
+            .current_dir(&dir);
+        cmd
+    };
+    let json = |cmd: &mut Command| -> serde_json::Value {
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    let created = json(&mut rchain(&["create-wallet"]));
+    let address = created["address"].as_str().unwrap().to_owned();
+    std::fs::write(
+        dir.path().join("rchain.toml"),
+        format!(
+            "[consensus]\nengine = \"proof-of-stake\"\nvalidators = [{{ address = \"{}\", stake = 5 }}]\n",
+            address
+        ),
+    )
+    .unwrap();
+
+    // The validator signs the blocks without mining them.
+    rchain(&["generate", "2", &address]).assert().success();
+    let block = json(&mut rchain(&["get-block", "2"]));
+    assert_eq!(block["validator"], address.as_str());
+    assert_eq!(block["nonce"], 0);
+    assert_eq!(json(&mut rchain(&["chain-info"]))["total_work"], "15");
+
+    std::fs::remove_file(dir.path().join("rchain.toml")).unwrap();
+    rchain(&["chain-info"]).assert().code(1).stderr(contains(
+        "the chain runs on proof-of-stake, not proof-of-work",
+    ));
+}
+
+#[test]
+fn cli_proof_of_authority() {
+    let dir = TempDir::new().unwrap();
+    let rchain = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest", "--output", "json"])
+            .current_dir(&dir);
+        cmd
+    };
+    let json = |cmd: &mut Command| -> serde_json::Value {
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    let a = json(&mut rchain(&["create-wallet"]))["address"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    let b = json(&mut rchain(&["create-wallet"]))["address"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    std::fs::write(
+        dir.path().join("rchain.toml"),
+        format!(
+            "[consensus]\nengine = \"proof-of-authority\"\nauthorities = [\"{}\"]\n",
+            a
+        ),
+    )
+    .unwrap();
+
+    // The authority signs the blocks in turn, then b joins from the block after its addition.
+    rchain(&["generate", "1", &a]).assert().success();
+    let added = json(&mut rchain(&["add-authority", &b]));
+    assert_eq!(added["authorities"], serde_json::json!([a, b]));
+    let tx = json(&mut rchain(&["get-tx", added["tx_id"].as_str().unwrap()]));
+    assert_eq!(tx["transaction"]["governance"]["change"]["Add"], b.as_str());
+    rchain(&["generate", "2", &a]).assert().success();
+    assert_eq!(
+        json(&mut rchain(&["get-block", "3"]))["validator"],
+        b.as_str()
+    );
+    assert_eq!(
+        json(&mut rchain(&["get-block", "4"]))["validator"],
+        a.as_str()
+    );
+    assert_eq!(json(&mut rchain(&["chain-info"]))["total_work"], "5");
+
+    let removed = json(&mut rchain(&["remove-authority", &b]));
+    assert_eq!(removed["authorities"], serde_json::json!([a]));
+    rchain(&["remove-authority", &a])
+        .assert()
+        .code(1)
+        .stderr(contains("removes the last authority"));
+}
+
+#[test]
+fn cli_governance_approvals() {
+    // Two nodes, each with the key of one authority.
+    let (node_a, node_b) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let rchain = |dir: &TempDir, args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest", "--output", "json"])
+            .current_dir(dir);
+        cmd
+    };
+    let json = |cmd: &mut Command| -> serde_json::Value {
+        let output = cmd.output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+    let address = |dir: &TempDir| {
+        json(&mut rchain(dir, &["create-wallet"]))["address"]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    };
+    let (a, b) = (address(&node_a), address(&node_b));
+    for dir in [&node_a, &node_b] {
+        std::fs::write(
+            dir.path().join("rchain.toml"),
+            format!(
+                "[consensus]\nengine = \"proof-of-authority\"\nauthorities = [\"{}\"]\n",
+                a
+            ),
+        )
+        .unwrap();
+    }
+    let sync = |from: &TempDir, to: &TempDir| {
+        let archive = from.path().join("archive");
+        let archive = archive.to_str().unwrap();
+        rchain(from, &["export-chain", archive]).assert().success();
+        rchain(to, &["import-chain", archive]).assert().success();
+    };
+
+    // a alone is the majority of the authorities that add b, then b seals its turn.
+    rchain(&node_a, &["generate", "1", &a]).assert().success();
+    rchain(&node_a, &["add-authority", &b]).assert().success();
+    sync(&node_a, &node_b);
+    rchain(&node_b, &["generate", "1", &b]).assert().success();
+    sync(&node_b, &node_a);
+
+    // a seals the block at height 4, but removing b needs b's approval too.
+    rchain(&node_a, &["remove-authority", &b])
+        .assert()
+        .code(1)
+        .stderr(contains(
+            "is approved by 1 of 2 authorities, it needs a majority",
+        ));
+    rchain(&node_a, &["approve-authority", "remove", &b])
+        .assert()
+        .success();
+    let approval = node_b.path().join("approval.json");
+    let approved = json(&mut rchain(&node_b, &["approve-authority", "remove", &b]));
+    assert_eq!(approved["height"], 4);
+    std::fs::write(&approval, approved.to_string()).unwrap();
+    let removed = json(&mut rchain(
+        &node_a,
+        &[
+            "remove-authority",
+            &b,
+            "--approvals",
+            approval.to_str().unwrap(),
+        ],
+    ));
+    assert_eq!(removed["authorities"], serde_json::json!([a]));
+    let tx = json(&mut rchain(
+        &node_a,
+        &["get-tx", removed["tx_id"].as_str().unwrap()],
+    ));
+    assert_eq!(
+        tx["transaction"]["governance"]["approvals"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn cli_pow_hash() {
+    let dir = TempDir::new().unwrap();
+    let rchain = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest", "--output", "json"])
+            .current_dir(&dir);
+        cmd
+    };
+    let json = |cmd: &mut Command| -> serde_json::Value {
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    let rates = json(&mut rchain(&["hash-rate", "--seconds", "0"]));
+    let hashes: Vec<_> = rates
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["hash"].as_str().unwrap())
+        .collect();
+    assert_eq!(hashes, ["sha256", "double-sha256", "blake3", "argon2"]);
+    assert!(rates[0]["hashes_per_second"].as_f64().unwrap() > 0.0);
+
+    let address = json(&mut rchain(&["create-wallet"]))["address"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    std::fs::write(dir.path().join("rchain.toml"), "pow_hash = \"blake3\"\n").unwrap();
+    rchain(&["generate", "2", &address]).assert().success();
+    assert_eq!(json(&mut rchain(&["chain-info"]))["height"], 2);
+
+    std::fs::write(dir.path().join("rchain.toml"), "pow_hash = \"argon2\"\n").unwrap();
+    rchain(&["chain-info"]).assert().code(1).stderr(contains(
+        "the chain runs on proof-of-work-blake3, not proof-of-work-argon2",
+    ));
+}
+
+#[test]
+fn cli_stratum() {
+    let dir = TempDir::new().unwrap();
+    let rchain = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest", "--output", "json"])
+            .current_dir(&dir);
+        cmd
+    };
+    let address = Address::new(Network::Regtest, Wallet::new().pub_key_hash()).to_string();
+    rchain(&["generate", "1", &address]).assert().success();
+
+    let listeners = [(); 2].map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap());
+    let [addr, events_addr] = listeners.map(|l| l.local_addr().unwrap().to_string());
+    let mut server = rchain(&["stratum-server", "--bind", &addr, "--events", &events_addr])
+        .spawn()
+        .unwrap();
+    while std::net::TcpStream::connect(&addr).is_err() {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    let events = std::net::TcpStream::connect(&events_addr).unwrap();
+
+    // An external miner process mines blocks that the server connects.
+    let output = rchain(&[
+        "stratum-miner",
+        &addr,
+        "--address",
+        &address,
+        "--blocks",
+        "2",
+    ])
+    .output()
+    .unwrap();
+    assert!(output.status.success());
+    let mined: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(mined["hashes"].as_array().unwrap().len(), 2);
+
+    // The server streams the blocks it connects.
+    let mut lines = std::io::BufRead::lines(std::io::BufReader::new(events));
+    let event: serde_json::Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+    assert_eq!(event["type"], "block_connected");
+    assert_eq!(event["hash"], mined["hashes"][0]);
+    assert_eq!(event["height"], 2);
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    let info = rchain(&["chain-info"]).output().unwrap();
+    let info: serde_json::Value = serde_json::from_slice(&info.stdout).unwrap();
+    assert_eq!(info["height"], 3);
+    assert_eq!(info["tip"], mined["hashes"][1]);
+}
+
+#[test]
+fn cli_events_server() {
+    let dir = TempDir::new().unwrap();
+    let rchain = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest", "--output", "json"])
+            .current_dir(&dir);
+        cmd
+    };
+    let output = rchain(&["create-wallet"]).output().unwrap();
+    let wallet: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let address = wallet["address"].as_str().unwrap().to_owned();
+    std::fs::write(
+        dir.path().join("rchain.toml"),
+        format!(
+            "[consensus]\nengine = \"proof-of-authority\"\nauthorities = [\"{}\"]\n",
+            address
+        ),
+    )
+    .unwrap();
+    rchain(&["generate", "1", &address]).assert().success();
+
+    // The server of a proof-of-authority chain streams the blocks it seals.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let mut server = rchain(&[
+        "events-server",
+        "--bind",
+        &addr,
+        "--seal",
+        &address,
+        "--interval",
+        "1",
+    ])
+    .spawn()
+    .unwrap();
+    let events = loop {
+        match std::net::TcpStream::connect(&addr) {
+            Ok(events) => break events,
+            Err(_) => std::thread::sleep(std::time::Duration::from_millis(10)),
+        }
+    };
+    let mut lines = std::io::BufRead::lines(std::io::BufReader::new(events));
+    let events: Vec<serde_json::Value> = (0..4)
+        .map(|_| serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap())
+        .collect();
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    // Each block is followed by the confirmation of its coinbase.
+    let blocks: Vec<_> = events
+        .iter()
+        .filter(|e| e["type"] == "block_connected")
+        .collect();
+    assert_eq!(blocks.len(), 2);
+    let height = blocks[0]["height"].as_u64().unwrap();
+    assert!(height >= 2);
+    assert_eq!(blocks[1]["height"], height + 1);
+    assert!(events
+        .iter()
+        .all(|e| e["type"] == "block_connected" || e["type"] == "tx_confirmed"));
+
+    // The server flushes a block before it seals the next one.
+    let info = rchain(&["chain-info"]).output().unwrap();
+    let info: serde_json::Value = serde_json::from_slice(&info.stdout).unwrap();
+    assert!(info["height"].as_u64().unwrap() >= height);
+}
+
+#[test]
+fn cli_checkpoints() {
+    let dir = TempDir::new().unwrap();
+    let rchain = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rchain").unwrap();
+        cmd.args(args)
+            .args(["--network", "regtest", "--output", "json"])
+            .current_dir(&dir);
+        cmd
+    };
+    let json = |cmd: &mut Command| -> serde_json::Value {
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    let address = json(&mut rchain(&["create-wallet"]))["address"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    rchain(&["generate", "3", &address]).assert().success();
+    let hash = |height: &str| json(&mut rchain(&["get-block", height]))["hash"].clone();
+    let (hash1, hash3) = (hash("1"), hash("3"));
+    rchain(&["export-chain", "chain.rchn"]).assert().success();
+
+    let config = dir.path().join("rchain.toml");
+    std::fs::write(&config, "checkpoints = [{ height = 1, hash = \"00\" }]\n").unwrap();
+    rchain(&["chain-info"])
+        .assert()
+        .code(1)
+        .stderr(contains("conflicts with checkpoint 00"));
+
+    rchain(&["import-chain", "chain.rchn"])
+        .args(["--datadir", "conflict", "--config", "rchain.toml"])
+        .assert()
+        .code(1)
+        .stderr(contains("conflicts with a checkpoint"));
+
+    // A new chain imports the archive up to the assume-valid block.
+    std::fs::write(
+        &config,
+        format!(
+            "checkpoints = [{{ height = 1, hash = {} }}]\nassume_valid = {}\n",
+            hash1, hash3
+        ),
+    )
+    .unwrap();
+    let imported = json(rchain(&["import-chain", "chain.rchn"]).args([
+        "--datadir",
+        "copy",
+        "--config",
+        "rchain.toml",
+    ]));
+    assert_eq!(imported["imported"], 4);
+    assert_eq!(imported["tip"], hash3);
 }